@@ -0,0 +1,110 @@
+//! Latency of listing a page of records as the dataset grows, e.g.:
+//!
+//! ```bash
+//! cargo bench --bench listing
+//! cargo bench --bench listing -- 10000 100000  # only these sizes
+//! ```
+//!
+//! For every size, this times the first page, a page deep into the listing
+//! (reached with a cursor), the first page of the records that match a `where`
+//! predicate (which are all counted), and, for comparison, what every listing
+//! used to do: copy every record and sort them all.
+
+use std::time::{Duration, Instant};
+
+use homework::api::cursor::Cursor;
+use homework::api::handlers::resultset;
+use homework::api::models::ListOptions;
+use homework::api::storage::{MemoryStorage, Record, Storage};
+use homework::filtering::Predicate;
+use homework::person::Person;
+use homework::sorting::{FieldsOrd, SortDirection, SortSpec};
+
+
+const SIZES: [usize; 4] = [1_000, 10_000, 100_000, 1_000_000];
+const PER_PAGE: usize = 50;
+const RUNS: usize = 25;
+
+
+/// Deterministic, fairly unique people
+fn people(n: usize) -> impl Iterator<Item = Person> {
+    const SYLLABLES: [&str; 16] = ["an", "ber", "cor", "dal", "en", "fin", "gar", "hol",
+                                   "is", "jan", "kel", "lor", "mar", "nes", "or", "per"];
+    const COLORS: [&str; 6] = ["red", "green", "blue", "yellow", "purple", ""];
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut next = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed
+    };
+
+    (0..n).map(move |_| {
+        let name = |r: u64| (0..3).map(|i| SYLLABLES[(r >> (i * 4)) as usize % 16]).collect::<String>();
+        let (last, first, color, day) = (next(), next(), next(), next());
+
+        Person::new(
+            &name(last),
+            &name(first),
+            "",
+            COLORS[color as usize % COLORS.len()],
+            &format!("{}/{}/{}", day % 12 + 1, day % 28 + 1, 1940 + day % 70),
+        )
+    })
+}
+
+
+/// Median of `RUNS` timings of `f`
+fn time<T>(mut f: impl FnMut() -> T) -> Duration {
+    let mut timings: Vec<Duration> = (0..RUNS).map(|_| {
+        let start = Instant::now();
+        std::hint::black_box(f());
+        start.elapsed()
+    }).collect();
+
+    timings.sort();
+    timings[RUNS / 2]
+}
+
+
+fn main() {
+    let sizes = std::env::args().skip(1)
+        .filter(|arg| !arg.starts_with('-'))
+        .map(|arg| arg.parse::<usize>())
+        .collect::<Result<Vec<usize>, _>>();
+    let sizes = match sizes {
+        Ok(sizes) if !sizes.is_empty() => sizes,
+        _ => SIZES.to_vec(),
+    };
+    let fields = vec![("last_name", SortDirection::Asc), ("first_name", SortDirection::Desc)];
+    let sort = SortSpec::parse::<Person>(&fields, false).unwrap();
+    let predicates: Vec<Predicate> = vec!["favorite_color=red".parse().unwrap()];
+
+    println!("{:>10} {:>14} {:>14} {:>14} {:>16}", "records", "first page", "middle page", "filtered page", "copy and sort");
+
+    for &size in sizes.iter() {
+        let mut storage = MemoryStorage::default();
+
+        for person in people(size) {
+            storage.insert(person).unwrap();
+        }
+
+        let opts = |cursor: Option<String>| ListOptions { per_page: Some(PER_PAGE), cursor, ..Default::default() };
+
+        // A cursor to the middle of the listing, as if the client had paged through half of it
+        let (id, person) = storage.scan(&sort, None).nth(size / 2).unwrap();
+        let middle = Cursor::after(&Record { id, person: person.clone() }, &sort, size / 2 + 1).encode();
+
+        let first_page = time(|| resultset(&storage, &[], &opts(None), &sort).unwrap());
+        let middle_page = time(|| resultset(&storage, &[], &opts(Some(middle.clone())), &sort).unwrap());
+        let filtered_page = time(|| resultset(&storage, &predicates, &opts(None), &sort).unwrap());
+        let copy_and_sort = time(|| {
+            let mut records = storage.records();
+            records.sort_by(|a, b| a.person.cmp_order_by_fields(&b.person, &fields));
+            records.truncate(PER_PAGE);
+            records
+        });
+
+        println!("{:>10} {:>14?} {:>14?} {:>14?} {:>16?}", size, first_page, middle_page, filtered_page, copy_and_sort);
+    }
+}
@@ -0,0 +1,307 @@
+//! Derives for the record types built on `homework`, e.g., `Person`:
+//!
+//! - `#[derive(StructFields)]` implements `serialization::StructFieldDeserialize`,
+//!   listing the fields by the names they're deserialized with.
+//! - `#[derive(FieldsOrd)]` implements `sorting::FieldsOrd`, comparing each field
+//!   by its `sorting::SortValue`.
+//! - `#[derive(FieldsFilter)]` implements `filtering::FieldsFilter`, giving the
+//!   value of each field by its `filtering::FilterValue`.
+//!
+//! They honour serde's `rename` (or `rename(deserialize = "...")`) and
+//! `rename_all`, and leave out the fields serde skips or flattens. A field marked
+//! `#[fields(extra)]` (which needs a `get(&str) -> Option<&str>`, like
+//! `person::Extra`) is compared and filtered on any other field name, as a
+//! string, while the record's `resource::Resource::keeps_extra` is true, so a
+//! record with one has to implement `Resource`. Otherwise, or without one, any
+//! other field is a `sorting::UnknownField`, and has no value.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Ident, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Error, Fields, Lit, Meta, NestedMeta, Result};
+
+
+/// A field of a record, and the name it's sorted and deserialized by
+struct Field {
+    ident: Ident,
+    name: String,
+}
+
+
+/// The fields of a record, and the `#[fields(extra)]` one, if any
+struct Record {
+    fields: Vec<Field>,
+    extra: Option<Ident>,
+}
+
+
+/// The items of every `#[<attr>(...)]` attribute of `attrs`
+fn metas(attrs: &[Attribute], attr: &str) -> Result<Vec<Meta>> {
+    let mut metas = vec![];
+
+    for attribute in attrs.iter().filter(|attribute| attribute.path.is_ident(attr)) {
+        match attribute.parse_meta()? {
+            Meta::List(list) => for nested in list.nested {
+                match nested {
+                    NestedMeta::Meta(meta) => metas.push(meta),
+                    NestedMeta::Lit(lit) => return Err(Error::new_spanned(lit, format!("unexpected literal in `#[{}]`", attr))),
+                }
+            },
+            meta => return Err(Error::new_spanned(meta, format!("expected `#[{}(...)]`", attr))),
+        }
+    }
+
+    Ok(metas)
+}
+
+
+fn string(lit: &Lit) -> Result<String> {
+    match lit {
+        Lit::Str(lit) => Ok(lit.value()),
+        lit => Err(Error::new_spanned(lit, "expected a string")),
+    }
+}
+
+
+/// The value of `key = "..."`, or of `key(deserialize = "...")`, in `meta`
+fn deserialize_value(meta: &Meta, key: &str) -> Result<Option<String>> {
+    match meta {
+        Meta::NameValue(name_value) if name_value.path.is_ident(key) => string(&name_value.lit).map(Some),
+        Meta::List(list) if list.path.is_ident(key) => {
+            for nested in list.nested.iter() {
+                if let NestedMeta::Meta(Meta::NameValue(name_value)) = nested {
+                    if name_value.path.is_ident("deserialize") {
+                        return string(&name_value.lit).map(Some)
+                    }
+                }
+            }
+
+            Ok(None)
+        },
+        _ => Ok(None),
+    }
+}
+
+
+/// Renames the field `name` by serde's `rename_all` `rule`, e.g., `camelCase`
+fn rename_all(name: &str, rule: &str) -> Option<String> {
+    let pascal = || name.split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            chars.next().map(|first| first.to_uppercase().chain(chars).collect::<String>()).unwrap_or_default()
+        })
+        .collect::<String>();
+
+    Some(match rule {
+        "lowercase" | "snake_case" => name.to_string(),
+        "UPPERCASE" | "SCREAMING_SNAKE_CASE" => name.to_ascii_uppercase(),
+        "kebab-case" => name.replace('_', "-"),
+        "SCREAMING-KEBAB-CASE" => name.to_ascii_uppercase().replace('_', "-"),
+        "PascalCase" => pascal(),
+        "camelCase" => {
+            let pascal = pascal();
+            let mut chars = pascal.chars();
+            chars.next().map(|first| first.to_lowercase().chain(chars).collect()).unwrap_or_default()
+        },
+        _ => return None,
+    })
+}
+
+
+impl Record {
+
+    fn parse(input: &DeriveInput) -> Result<Self> {
+        let fields = match &input.data {
+            Data::Struct(data) => match &data.fields {
+                Fields::Named(fields) => &fields.named,
+                _ => return Err(Error::new_spanned(&input.ident, "only structs with named fields can be derived")),
+            },
+            _ => return Err(Error::new_spanned(&input.ident, "only structs with named fields can be derived")),
+        };
+
+        let mut rule = None;
+
+        for meta in metas(&input.attrs, "serde")? {
+            if let Some(value) = deserialize_value(&meta, "rename_all")? {
+                rule = Some((value, meta));
+            }
+        }
+
+        let mut record = Record { fields: vec![], extra: None };
+
+        for field in fields.iter() {
+            let ident = field.ident.clone().expect("named fields have an ident");
+            let mut name = ident.to_string().trim_start_matches("r#").to_string();
+            let mut skip = false;
+
+            if let Some((rule, meta)) = &rule {
+                name = rename_all(&name, rule)
+                    .ok_or_else(|| Error::new_spanned(meta, format!("unknown `rename_all` rule \"{}\"", rule)))?;
+            }
+
+            for meta in metas(&field.attrs, "serde")? {
+                match deserialize_value(&meta, "rename")? {
+                    Some(rename) => name = rename,
+                    None => skip |= ["skip", "skip_deserializing", "flatten"].iter().any(|path| meta.path().is_ident(path)),
+                }
+            }
+
+            for meta in metas(&field.attrs, "fields")? {
+                match &meta {
+                    Meta::Path(path) if path.is_ident("extra") && record.extra.is_none() => {
+                        record.extra = Some(ident.clone());
+                        skip = true;
+                    },
+                    Meta::Path(path) if path.is_ident("extra") => return Err(Error::new_spanned(meta, "only one field can be `#[fields(extra)]`")),
+                    Meta::Path(path) if path.is_ident("skip") => skip = true,
+                    meta => return Err(Error::new_spanned(meta, "expected `#[fields(extra)]` or `#[fields(skip)]`")),
+                }
+            }
+
+            if !skip {
+                record.fields.push(Field { ident, name });
+            }
+        }
+
+        Ok(record)
+    }
+
+    fn names(&self) -> Vec<&str> {
+        self.fields.iter().map(|field| field.name.as_str()).collect()
+    }
+}
+
+
+fn struct_fields(input: &DeriveInput) -> Result<TokenStream2> {
+    let record = Record::parse(input)?;
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let names = record.names();
+
+    Ok(quote! {
+        impl #impl_generics ::homework::serialization::StructFieldDeserialize for #ident #ty_generics #where_clause {
+            fn struct_fields() -> &'static [&'static str] {
+                &[#(#names),*]
+            }
+        }
+    })
+}
+
+
+fn fields_ord(input: &DeriveInput) -> Result<TokenStream2> {
+    let record = Record::parse(input)?;
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let names = record.names();
+    let idents: Vec<&Ident> = record.fields.iter().map(|field| &field.ident).collect();
+
+    let unknown = quote! { return Err(::homework::sorting::UnknownField::new(field, &[#(#names),*])) };
+    let (other_cmp, other_is_null) = match &record.extra {
+        Some(extra) => (
+            quote! {
+                if !<Self as ::homework::resource::Resource>::keeps_extra() {
+                    #unknown
+                }
+
+                ::homework::sorting::SortValue::cmp_value(
+                    self.#extra.get(field).unwrap_or_default(),
+                    b.#extra.get(field).unwrap_or_default(),
+                    collation,
+                )
+            },
+            quote! { <Self as ::homework::resource::Resource>::keeps_extra() && self.#extra.get(field).map_or(true, str::is_empty) },
+        ),
+        None => (unknown, quote! { false }),
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::homework::sorting::FieldsOrd for #ident #ty_generics #where_clause {
+
+            fn try_cmp_field(
+                &self,
+                b: &Self,
+                field: &str,
+                direction: &::homework::sorting::SortDirection,
+                collation: ::homework::sorting::Collation,
+            ) -> ::std::result::Result<::std::cmp::Ordering, ::homework::sorting::UnknownField>
+            {
+                let ord = match field {
+                    #(#names => ::homework::sorting::SortValue::cmp_value(&self.#idents, &b.#idents, collation),)*
+                    _ => { #other_cmp },
+                };
+
+                Ok(match direction {
+                    ::homework::sorting::SortDirection::Desc => ord.reverse(),
+                    ::homework::sorting::SortDirection::Asc => ord,
+                })
+            }
+
+            fn is_null(&self, field: &str) -> bool {
+                match field {
+                    #(#names => ::homework::sorting::SortValue::is_null(&self.#idents),)*
+                    _ => #other_is_null,
+                }
+            }
+        }
+    })
+}
+
+
+fn fields_filter(input: &DeriveInput) -> Result<TokenStream2> {
+    let record = Record::parse(input)?;
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let names = record.names();
+    let idents: Vec<&Ident> = record.fields.iter().map(|field| &field.ident).collect();
+
+    let other = match &record.extra {
+        Some(extra) => quote! {
+            _ if <Self as ::homework::resource::Resource>::keeps_extra() => Some(::homework::filtering::FieldValue::Str(
+                self.#extra.get(field).unwrap_or_default()
+            )),
+        },
+        None => quote! {},
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::homework::filtering::FieldsFilter for #ident #ty_generics #where_clause {
+            fn field_value(&self, field: &str) -> ::std::option::Option<::homework::filtering::FieldValue<'_>> {
+                match field {
+                    #(#names => Some(::homework::filtering::FilterValue::filter_value(&self.#idents)),)*
+                    #other
+                    _ => None,
+                }
+            }
+        }
+    })
+}
+
+
+/// Implements `serialization::StructFieldDeserialize`, see the crate's docs
+#[proc_macro_derive(StructFields, attributes(serde, fields))]
+pub fn derive_struct_fields(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    struct_fields(&input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+
+/// Implements `sorting::FieldsOrd`, see the crate's docs
+#[proc_macro_derive(FieldsOrd, attributes(serde, fields))]
+pub fn derive_fields_ord(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    fields_ord(&input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+
+/// Implements `filtering::FieldsFilter`, see the crate's docs
+#[proc_macro_derive(FieldsFilter, attributes(serde, fields))]
+pub fn derive_fields_filter(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    fields_filter(&input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+
+mod tests;
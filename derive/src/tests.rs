@@ -0,0 +1,165 @@
+#![allow(dead_code)]
+
+use super::*;
+
+
+fn input(item: &str) -> DeriveInput {
+    syn::parse_str(item).expect("a valid item")
+}
+
+
+fn names(item: &str) -> Vec<String> {
+    Record::parse(&input(item)).unwrap().fields.into_iter()
+        .map(|field| field.name)
+        .collect()
+}
+
+
+fn error(item: &str) -> String {
+    match Record::parse(&input(item)) {
+        Ok(_) => panic!("expected an error for {}", item),
+        Err(e) => e.to_string(),
+    }
+}
+
+
+#[cfg(test)]
+mod names {
+    use super::*;
+
+    #[test]
+    fn rename() {
+        assert_eq!(names(r#"struct Address {
+            #[serde(rename = "no")]
+            number: u32,
+            #[serde(rename(serialize = "line", deserialize = "street"))]
+            street_name: String,
+            r#type: String,
+        }"#), ["no", "street", "type"]);
+    }
+
+    #[test]
+    fn rename_all_then_rename() {
+        assert_eq!(names(r#"#[serde(rename_all = "camelCase")]
+        struct Address {
+            street_name: String,
+            #[serde(rename = "no")]
+            house_number: u32,
+        }"#), ["streetName", "no"]);
+    }
+
+    #[test]
+    fn rename_all_rules() {
+        let renamed: Vec<Option<String>> = ["lowercase", "UPPERCASE", "PascalCase", "camelCase", "kebab-case", "SCREAMING-KEBAB-CASE", "Title"]
+            .iter()
+            .map(|rule| rename_all("street_name", rule))
+            .collect();
+
+        assert_eq!(renamed, [
+            Some("street_name".into()),
+            Some("STREET_NAME".into()),
+            Some("StreetName".into()),
+            Some("streetName".into()),
+            Some("street-name".into()),
+            Some("STREET-NAME".into()),
+            None,
+        ]);
+    }
+
+    #[test]
+    fn skipped_and_flattened_fields() {
+        let item = r#"struct Person {
+            last_name: String,
+            #[serde(skip)]
+            geocoded: bool,
+            #[serde(skip_deserializing)]
+            seen: bool,
+            #[fields(skip)]
+            notes: String,
+            #[serde(flatten)]
+            #[fields(extra)]
+            extra: Extra,
+        }"#;
+        let record = Record::parse(&input(item)).unwrap();
+
+        assert_eq!(names(item), ["last_name"]);
+        assert_eq!(record.extra.map(|ident| ident.to_string()).as_deref(), Some("extra"));
+    }
+}
+
+
+#[cfg(test)]
+mod errors {
+    use super::*;
+
+    #[test]
+    fn only_structs_with_named_fields() {
+        assert_eq!(error("enum Color { Red, Green }"), "only structs with named fields can be derived");
+        assert_eq!(error("struct Point(u32, u32);"), "only structs with named fields can be derived");
+    }
+
+    #[test]
+    fn unknown_rename_all_rule() {
+        assert_eq!(error(r#"#[serde(rename_all = "Title Case")] struct Address { street_name: String }"#),
+            "unknown `rename_all` rule \"Title Case\"");
+    }
+
+    #[test]
+    fn fields_attribute() {
+        assert_eq!(error("struct Person { #[fields(extra)] a: Extra, #[fields(extra)] b: Extra }"),
+            "only one field can be `#[fields(extra)]`");
+        assert_eq!(error("struct Person { #[fields(hidden)] a: String }"),
+            "expected `#[fields(extra)]` or `#[fields(skip)]`");
+        assert_eq!(error("struct Person { #[fields = \"extra\"] a: String }"),
+            "expected `#[fields(...)]`");
+        assert_eq!(error("struct Person { #[fields(\"extra\")] a: String }"),
+            "unexpected literal in `#[fields]`");
+    }
+
+    #[test]
+    fn rename_to_a_string() {
+        assert_eq!(error("struct Address { #[serde(rename = 1)] number: u32 }"), "expected a string");
+    }
+
+    #[test]
+    fn become_compile_errors() {
+        let input = input("enum Color { Red, Green }");
+
+        for derive in [struct_fields, fields_ord, fields_filter].iter() {
+            let tokens = derive(&input).unwrap_or_else(Error::into_compile_error).to_string();
+
+            assert!(tokens.starts_with("compile_error !"), "{}", tokens);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod expansion {
+    use super::*;
+
+    #[test]
+    fn struct_fields_lists_the_names() {
+        let input = input(r#"#[serde(rename_all = "camelCase")] struct Address { street_name: String, #[serde(skip)] geocoded: bool }"#);
+
+        assert_eq!(struct_fields(&input).unwrap().to_string(), quote! {
+            impl ::homework::serialization::StructFieldDeserialize for Address {
+                fn struct_fields() -> &'static [&'static str] {
+                    &["streetName"]
+                }
+            }
+        }.to_string());
+    }
+
+    #[test]
+    fn extra_columns_follow_the_resource() {
+        let with = input("struct Person { last_name: String, #[fields(extra)] extra: Extra }");
+        let without = input("struct Address { street_name: String }");
+        let keeps_extra = quote! { <Self as ::homework::resource::Resource>::keeps_extra() }.to_string();
+
+        for derive in [fields_ord, fields_filter].iter() {
+            assert!(derive(&with).unwrap().to_string().contains(&keeps_extra));
+            assert!(!derive(&without).unwrap().to_string().contains(&keeps_extra));
+        }
+    }
+}
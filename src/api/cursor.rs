@@ -0,0 +1,122 @@
+use std::cmp::Ordering;
+use serde::{Serialize, Deserialize};
+use serde_json::{Map, Value};
+
+use crate::api::storage::{Record, RecordId};
+use crate::person::Person;
+use crate::resource::Resource;
+use crate::serialization::date_format::{self, DateFormats};
+use crate::sorting::{SortDirection, SortSpec};
+
+
+/// What's encoded in a cursor token
+#[derive(Serialize, Deserialize)]
+struct Token {
+    sort: Vec<(String, SortDirection)>,
+    key: Map<String, Value>,
+    id: RecordId,
+    offset: usize,
+}
+
+
+/// Where a page of a listing ended: the sort key of its last record, i.e., the
+/// values of the fields `FieldsOrd` compares on (every field when there's no
+/// sort), and the record's id, which breaks ties.
+///
+/// Records are listed in the order of `(sort key, id)`, so a cursor marks the
+/// same position in the listing however many records are inserted before it.
+/// It also remembers how many records were listed up to it, to number pages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor<T = Person> {
+    sort: SortSpec,
+    /// What records are listed by: `sort`, or every field when it's empty
+    order: SortSpec,
+    person: T,
+    id: RecordId,
+    offset: usize,
+}
+
+
+/// The sort fields of `sort` as they're encoded in a token
+fn token_sort(sort: &SortSpec) -> Vec<(String, SortDirection)> {
+    sort.fields().into_iter().map(|(field, direction)| (field.to_string(), direction)).collect()
+}
+
+
+impl<T: Resource> Cursor<T> {
+
+    fn new(sort: &SortSpec, person: T, id: RecordId, offset: usize) -> Self {
+        let order = match sort.is_empty() {
+            true => SortSpec::all::<T>(),
+            false => sort.clone(),
+        };
+
+        Self { sort: sort.clone(), order, person, id, offset }
+    }
+
+    /// The cursor just after `record`, the `offset`th record in a listing sorted by `sort`
+    pub fn after(record: &Record<T>, sort: &SortSpec, offset: usize) -> Self {
+        Self::new(sort, record.person.clone(), record.id, offset)
+    }
+
+    /// The sort key of the record the cursor is after. Fields that aren't part of it are empty.
+    pub fn person(&self) -> &T {
+        &self.person
+    }
+
+    /// How many records were listed up to the cursor when it was made
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// The opaque token of the cursor: URL-safe base64 of JSON, with dates in
+    /// the default `DateFormats`.
+    pub fn encode(&self) -> String {
+        let person = match date_format::with_formats(&DateFormats::default(), || serde_json::to_value(&self.person)) {
+            Ok(Value::Object(person)) => person,
+            _ => Map::new(),
+        };
+        // The fields whose values make up the sort key
+        let key = person.into_iter()
+            .filter(|(field, _)| self.order.keys().iter().any(|key| key.field == *field))
+            .collect();
+
+        let token = Token { sort: token_sort(&self.sort), key, id: self.id, offset: self.offset };
+        let json = serde_json::to_vec(&token).unwrap_or_default();
+
+        base64::encode_config(json, base64::URL_SAFE_NO_PAD)
+    }
+
+    /// Reads a token made by `encode`. A token is only valid for the listing
+    /// it came from, i.e., sorted by the same `sort`.
+    pub fn decode(token: &str, sort: &SortSpec) -> Result<Self, String> {
+        let json = base64::decode_config(token, base64::URL_SAFE_NO_PAD)
+            .map_err(|e| format!("Malformed cursor: {}", e))?;
+        let token: Token = serde_json::from_slice(&json)
+            .map_err(|e| format!("Malformed cursor: {}", e))?;
+
+        if token.sort != token_sort(sort) {
+            return Err("The cursor belongs to a listing with a different sort".into())
+        }
+
+        // Fields that aren't part of the key are left empty: they're never compared.
+        let mut person = match serde_json::to_value(T::default()) {
+            Ok(Value::Object(person)) => person,
+            _ => Map::new(),
+        };
+        person.extend(token.key);
+
+        let person = date_format::with_formats(&DateFormats::default(), || {
+            serde_json::from_value(Value::Object(person))
+        }).map_err(|e| format!("Malformed cursor: {}", e))?;
+
+        Ok(Self::new(sort, person, token.id, token.offset))
+    }
+
+    /// Compares the record stored under `id` to the record the cursor is after, in listing order
+    pub fn cmp_record(&self, id: RecordId, person: &T) -> Ordering {
+        // Without a sort, records are listed by every field in turn (but not by their extra columns).
+        self.order.cmp(person, &self.person)
+            .then(id.cmp(&self.id))
+    }
+}
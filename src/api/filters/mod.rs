@@ -1,16 +1,26 @@
-use std::convert::Infallible;
+use std::{convert::Infallible, sync::Arc};
 
 use warp::{Buf, Filter, Rejection};
 
 use crate::api::models::{self, Db};
 use crate::api::handlers;
+use crate::api::storage::RecordId;
 use crate::person::Person;
-use crate::serialization::StructFieldDeserialize;
+use crate::resource::Resource;
+use crate::serialization::date_format::{self, DateFormat, DateFormats};
+use crate::sorting::{Modifiers, SortDirection, SortSpec, SortSpecError, did_you_mean};
+use crate::filtering::{self, Predicate};
+use crate::validation::Rules;
+use crate::io::Format;
+use crate::stats::GroupBy;
 
 
 /// I.e., 2 MiB
 const MAX_BYTES: u64 = (1 << 20) * 2;
 
+/// Maximum size of a `POST /records/batch` body, i.e., 64 MiB
+const MAX_BATCH_BYTES: u64 = (1 << 20) * 64;
+
 
 #[derive(Debug)]
 pub struct InvalidFilterField {
@@ -19,37 +29,208 @@ pub struct InvalidFilterField {
 impl warp::reject::Reject for InvalidFilterField {}
 
 
+#[derive(Debug)]
+pub struct InvalidSortDirection {
+    pub reason: String
+}
+impl warp::reject::Reject for InvalidSortDirection {}
+
+
+#[derive(Debug)]
+pub struct InvalidSortModifier {
+    pub reason: String
+}
+impl warp::reject::Reject for InvalidSortModifier {}
+
+
+#[derive(Debug)]
+pub struct InvalidPredicate {
+    pub reason: String
+}
+impl warp::reject::Reject for InvalidPredicate {}
+
+
+#[derive(Debug)]
+pub struct InvalidGroupBy {
+    pub reason: String
+}
+impl warp::reject::Reject for InvalidGroupBy {}
+
+
 #[derive(Debug)]
 pub struct InvalidCSV;
 impl warp::reject::Reject for InvalidCSV {}
 
 
+#[derive(Debug)]
+pub struct InvalidJSON {
+    pub reason: String
+}
+impl warp::reject::Reject for InvalidJSON {}
+
+
+#[derive(Debug)]
+pub struct InvalidDateFormat {
+    pub reason: String
+}
+impl warp::reject::Reject for InvalidDateFormat {}
+
+
 /// A filter that provides access to the "database"
-fn with_db(db: Db) -> impl Filter<Extract = (Db,), Error = Infallible> + Clone {
+fn with_db<T: Resource>(db: Db<T>) -> impl Filter<Extract = (Db<T>,), Error = Infallible> + Clone {
     warp::any().map(move || db.clone())
 }
 
-/// A filter that provides a Person deserialized from JSON
+
+/// A filter that provides the rules records are validated with before they're stored
+fn with_rules(rules: Arc<Rules>) -> impl Filter<Extract = (Arc<Rules>,), Error = Infallible> + Clone {
+    warp::any().map(move || rules.clone())
+}
+
+/// Returns the `date-format` parameter of a media type, if any,
+/// e.g., `application/json; date-format=%Y-%m-%d` or `text/csv; date-format=iso`.
+fn date_format_param(media_type: &str) -> Option<&str>
+{
+    media_type.split(';')
+        .skip(1)
+        .filter_map(|param| {
+            let mut parts = param.splitn(2, '=');
+            match (parts.next().map(str::trim), parts.next()) {
+                (Some(key), Some(value)) if key.eq_ignore_ascii_case("date-format") =>
+                    Some(value.trim().trim_matches('"')),
+                _ => None
+            }
+        })
+        .next()
+}
+
+
+/// The server's `DateFormats`, overridden by the `date-format` parameters of a
+/// request's headers: `Accept` replaces the output format, and `Content-Type`
+/// adds an input format that's tried before the others.
+fn request_date_formats(accept: Option<&str>, content_type: Option<&str>)
+    -> Result<DateFormats, Rejection>
+{
+    let parse = |format: &str| format.parse::<DateFormat>()
+        .map_err(|reason| warp::reject::custom(InvalidDateFormat { reason }));
+
+    let mut formats = date_format::current();
+
+    if let Some(format) = accept.and_then(date_format_param) {
+        formats.output = parse(format)?;
+    }
+
+    if let Some(format) = content_type.and_then(date_format_param) {
+        formats.input.insert(0, parse(format)?);
+    }
+
+    Ok(formats)
+}
+
+
+/// Filter that provides the `DateFormats` used to read and write the dates of a
+/// request (see `request_date_formats`).
+///
+/// E.g., `Accept: application/json; date-format=iso`
+pub fn date_formats() -> impl Filter<Extract = (DateFormats,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("accept")
+        .and(warp::header::optional::<String>("content-type"))
+        .and_then(|accept: Option<String>, content_type: Option<String>| async move {
+            request_date_formats(accept.as_deref(), content_type.as_deref())
+        })
+}
+
+
+/// The response format asked for by an `Accept` header: the first of its media
+/// types that we can respond with (q-values aren't taken into account).
+/// JSON if there's no header, or for a wildcard.
+fn accepted_format(accept: Option<&str>) -> Result<Format, Rejection>
+{
+    let accept = match accept {
+        Some(accept) if !accept.trim().is_empty() => accept,
+        _ => return Ok(Format::Json),
+    };
+
+    accept.split(',')
+        .map(|media_type| media_type.split(';').next().unwrap_or_default().trim())
+        .find_map(|media_type| match media_type {
+            "*/*" | "application/*" => Some(Format::Json),
+            "text/*" => Some(Format::Csv),
+            x => Format::from_media_type(x),
+        })
+        .ok_or_else(|| warp::reject::custom(handlers::NotAcceptable { accept: accept.to_string() }))
+}
+
+
+/// Filter that provides the response format asked for by the `Accept` header
+/// (see `accepted_format`).
+pub fn accept_format() -> impl Filter<Extract = (Format,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("accept")
+        .and_then(|accept: Option<String>| async move { accepted_format(accept.as_deref()) })
+}
+
+
+/// Filter that matches the media type of the `Content-Type` header, ignoring
+/// any parameters (e.g., `date-format`).
+fn content_type(media_type: &'static str) -> impl Filter<Extract = (), Error = Rejection> + Copy {
+    warp::header::<String>("content-type")
+        .and_then(move |value: String| async move {
+            match value.split(';').next().map(str::trim) {
+                Some(essence) if essence.eq_ignore_ascii_case(media_type) => Ok(()),
+                _ => Err(warp::reject::reject()),
+            }
+        })
+        .untuple_one()
+}
+
+
+/// A filter that provides a Person (or any other `Resource`) deserialized from
+/// JSON, with its dates read using the request's `date_formats`.
 /// Body must be under `MAX_BYTES` length.
-fn json_body() -> impl Filter<Extract = (Person,), Error = warp::Rejection> + Clone {
+fn json_body<T: Resource>() -> impl Filter<Extract = (T,), Error = warp::Rejection> + Clone {
+    use warp::hyper::body::Bytes;
+
     warp::body::content_length_limit(MAX_BYTES)
-        .and(warp::body::json())
+        .and(warp::body::bytes())
+        .and(date_formats())
+        .and_then(|buf: Bytes, formats: DateFormats| async move {
+            let mut person = date_format::with_formats(&formats, || serde_json::from_slice::<T>(&buf))
+                .map_err(|e| warp::reject::custom(InvalidJSON { reason: e.to_string() }))?;
+
+            // The id of a record that's sent back isn't one of its columns.
+            if let Some(extra) = person.extra_mut() {
+                extra.remove("id");
+            }
+
+            Ok::<T, Rejection>(person)
+        })
 }
 
 
-/// Filter that rejects an invalid field name.
-async fn filter_field(field: String) -> Result<String, warp::Rejection>
+/// A filter that provides an arbitrary JSON value, used as the body of a `PATCH`.
+/// Body must be under `MAX_BYTES` length.
+fn json_patch_body() -> impl Filter<Extract = (serde_json::Value,), Error = warp::Rejection> + Clone {
+    use warp::hyper::body::Bytes;
+
+    warp::body::content_length_limit(MAX_BYTES)
+        .and(warp::body::bytes())
+        .and_then(|buf: Bytes| async move {
+            serde_json::from_slice::<serde_json::Value>(&buf)
+                .map_err(|e| warp::reject::custom(InvalidJSON { reason: e.to_string() }))
+        })
+}
+
+
+/// Rejects a field name (or an alias of one, see `Resource::field_alias`) that
+/// isn't one of `T::struct_fields()`, unless extra columns are kept (see
+/// `person::extra`), which can have any name.
+fn valid_field<T: Resource>(field: &str) -> Result<String, warp::Rejection>
 {
-    let field = match field.as_str() {
-        "name" => "last_name",
-        "color" => "favorite_color",
-        "birthdate" => "dob",
-        x => x
-    }.to_string();
+    let field = T::field_alias(field).to_string();
 
-    let person_fields = Person::struct_fields();
+    let person_fields = T::struct_fields();
 
-    if !person_fields.contains(&field.as_str()) {
+    if !person_fields.contains(&field.as_str()) && !T::keeps_extra() {
         Err(warp::reject::custom(InvalidFilterField {
             available: person_fields
         }))
@@ -60,22 +241,161 @@ async fn filter_field(field: String) -> Result<String, warp::Rejection>
 }
 
 
-/// Filter that provides a Person deserialized from CSV.
+/// Filter that rejects an invalid field name.
+async fn filter_field<T: Resource>(field: String) -> Result<String, warp::Rejection>
+{
+    valid_field::<T>(&field)
+}
+
+
+/// Parses every `where` parameter in a raw query-string into a `Predicate`,
+/// rejecting any predicate on a field that isn't one of `T::struct_fields()`.
+fn parse_predicates<T: Resource>(query: &str) -> Result<Vec<Predicate>, warp::Rejection>
+{
+    let invalid = |reason: String| warp::reject::custom(InvalidPredicate { reason });
+
+    let pairs = serde_urlencoded::from_str::<Vec<(String, String)>>(query)
+        .map_err(|e| invalid(e.to_string()))?;
+
+    let predicates = pairs.into_iter()
+        .filter(|(key, _)| key == "where")
+        .map(|(_, value)| {
+            let mut predicate = value.parse::<Predicate>().map_err(|e| invalid(e.to_string()))?;
+            predicate.field = T::field_alias(&predicate.field).to_string();
+            Ok(predicate)
+        })
+        .collect::<Result<Vec<Predicate>, warp::Rejection>>()?;
+
+    match filtering::unknown_field(&predicates, T::struct_fields()) {
+        Some(_) if !T::keeps_extra() => Err(warp::reject::custom(InvalidFilterField {
+            available: T::struct_fields()
+        })),
+        _ => Ok(predicates)
+    }
+}
+
+
+/// Filter that provides the predicates given as (repeatable) `where` query parameters,
+/// e.g., `?where=favorite_color=red&where=dob>=1980-01-01`.
+/// Provides an empty list when there is no query-string.
+fn where_predicates<T: Resource>() -> impl Filter<Extract = (Vec<Predicate>,), Error = Rejection> + Clone {
+    warp::query::raw()
+        .or(warp::any().map(String::new))
+        .unify()
+        .and_then(|query: String| async move { parse_predicates::<T>(&query) })
+}
+
+
+/// Filter that provides the path and query-string of the request, to link to other pages.
+fn page_url() -> impl Filter<Extract = (handlers::PageUrl,), Error = Infallible> + Clone {
+    warp::path::full()
+        .and(warp::query::raw().or(warp::any().map(String::new)).unify())
+        .map(|path: warp::path::FullPath, query: String| handlers::PageUrl {
+            path: path.as_str().to_string(),
+            query,
+        })
+}
+
+
+/// Parses the `sort` query parameter, a comma-separated list of `field[:modifier...][:direction]`,
+/// e.g., `favorite_color:asc,last_name:nocase:desc,dob:nulls=last`, into a `SortSpec` of `T`'s fields
+/// (or aliases). Fields without a direction fall back to `direction`.
+pub fn sort_fields<T: Resource>(sort: &str, direction: SortDirection) -> Result<SortSpec, warp::Rejection>
+{
+    let keys = sort.split(',')
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(|key| sort_key::<T>(key, direction))
+        .collect::<Result<Vec<(String, SortDirection)>, warp::Rejection>>()?;
+    let keys: Vec<(&str, SortDirection)> = keys.iter().map(|(key, direction)| (key.as_str(), *direction)).collect();
+
+    SortSpec::parse::<T>(&keys, T::keeps_extra()).map_err(|e| match e {
+        SortSpecError::UnknownField(e) => warp::reject::custom(InvalidFilterField { available: e.available }),
+        SortSpecError::InvalidModifier { .. } => warp::reject::custom(InvalidSortModifier { reason: e.to_string() }),
+    })
+}
+
+
+/// Splits a single key of the `sort` query parameter into the (unaliased) field with its
+/// modifiers, as expected by `SortSpec::parse`, and its direction, which may be given
+/// among its modifiers. Anything that is neither is taken for a mistyped direction when
+/// it's close to one, or when it's last and doesn't look like a modifier.
+fn sort_key<T: Resource>(key: &str, mut direction: SortDirection) -> Result<(String, SortDirection), warp::Rejection>
+{
+    let mut parts = key.split(':').map(str::trim);
+    let mut field = T::field_alias(parts.next().unwrap_or_default()).to_string();
+    let mut parts = parts.peekable();
+
+    while let Some(part) = parts.next() {
+        if Modifiers::default().set(part).is_ok() {
+            field = format!("{}:{}", field, part);
+            continue;
+        }
+
+        match part.parse::<SortDirection>() {
+            Ok(parsed) => direction = parsed,
+            Err(reason) if did_you_mean(part, ["asc", "desc"]).is_some() => {
+                return Err(warp::reject::custom(InvalidSortDirection { reason }))
+            },
+            Err(reason) if parts.peek().is_none() && !part.contains('=')
+                && did_you_mean(part, ["binary", "nocase", "noaccent", "natural"]).is_none() => {
+                return Err(warp::reject::custom(InvalidSortDirection { reason }))
+            },
+            // Left for `SortSpec::parse` to reject as an invalid modifier
+            Err(_) => field = format!("{}:{}", field, part),
+        }
+    }
+
+    Ok((field, direction))
+}
+
+
+/// Parses the `group_by` query parameter, a comma-separated list of `field[:bucket]`,
+/// e.g., `favorite_color,dob:age`, into `GroupBy`s of a `T`.
+pub fn group_by_fields<T: Resource>(group_by: &str) -> Result<Vec<GroupBy>, warp::Rejection>
+{
+    let group_by = group_by.split(',')
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(|key| {
+            let mut parts = key.splitn(2, ':');
+            let field = valid_field::<T>(parts.next().unwrap_or_default())?;
+
+            let key = match parts.next() {
+                Some(bucket) => format!("{}:{}", field, bucket),
+                None => field,
+            };
+
+            GroupBy::parse::<T>(&key).map_err(|reason| warp::reject::custom(InvalidGroupBy { reason }))
+        })
+        .collect::<Result<Vec<GroupBy>, warp::Rejection>>()?;
+
+    match group_by.is_empty() {
+        true => Err(warp::reject::custom(InvalidGroupBy { reason: "Expected at least one field".into() })),
+        false => Ok(group_by),
+    }
+}
+
+
+/// Filter that provides a Person (or any other `Resource`) deserialized from
+/// CSV, with its dates read using the request's `date_formats`.
 /// N.B. that the body should not be urlencoded.
 /// Body must be under `MAX_BYTES` size.
-pub fn csv_body() -> impl Filter<Extract = (Person,), Error = Rejection> + Copy {
+pub fn csv_body<T: Resource>() -> impl Filter<Extract = (T,), Error = Rejection> + Clone {
     use warp::hyper::body::Bytes;
     
     warp::body::content_length_limit(MAX_BYTES)
         .and(warp::body::bytes())
-        .and_then(|buf: Bytes| async {
+        .and(date_formats())
+        .and_then(|buf: Bytes, formats: DateFormats| async move {
 
             let rejection = warp::reject::custom(InvalidCSV);
 
-            let results = crate::io::parse_csv_people_from_reader(
-                buf.reader(), ',', false);
+            let result = date_format::with_formats(&formats, || {
+                crate::io::parse_csv_people_from_reader::<_, T>(buf.reader(), ',', false).next()
+            });
 
-            let result = match results.into_iter().next() {
+            let result = match result {
                 Some(result) => result,
                 None => return Err(rejection)
             };
@@ -88,13 +408,13 @@ pub fn csv_body() -> impl Filter<Extract = (Person,), Error = Rejection> + Copy
 }
 
 
-/// "Entry point" filter that combines all of the `records_` filters.
-pub fn records(db: Db)
+/// "Entry point" filter that serves the people in `db` under `/records` (see
+/// `people`), and turns every rejection into a response.
+/// Records written to `db` must satisfy `rules`.
+pub fn records(db: Db, rules: Rules)
     -> impl Filter<Extract = impl warp::Reply, Error = Rejection> + Clone
 {
-    records_list(db.clone())
-        .or(records_sorted_by_column(db.clone()))
-        .or(create_record(db))
+    people(db, Arc::new(rules))
         .or(warp::path::end().and_then(|| async {
             Err::<warp::reply::Response, Rejection>(warp::reject())
         }))
@@ -102,17 +422,68 @@ pub fn records(db: Db)
 }
 
 
+/// Filter that serves the people in `db` under `/records`, like any other
+/// `resource`. Rejections aren't recovered, so that other
+/// resources can be mounted next to it, e.g.:
+///
+/// ```ignore
+/// filters::people(people, rules.clone())
+///     .or(filters::resource("accounts", accounts, rules))
+///     .recover(handlers::handle_rejection)
+/// ```
+pub fn people(db: Db, rules: Arc<Rules>)
+    -> impl Filter<Extract = impl warp::Reply, Error = Rejection> + Clone
+{
+    resource("records", db, rules)
+}
+
+
+/// Filter that serves the records of any `Resource` in `db` under `/<name>`,
+/// the same way people are served under `/records`, e.g., `/accounts/id/3`
+/// or `/accounts?sort=number`. Records written to `db` must satisfy `rules`.
+pub fn resource<T: Resource>(name: &'static str, db: Db<T>, rules: Arc<Rules>)
+    -> impl Filter<Extract = impl warp::Reply, Error = Rejection> + Clone
+{
+    warp::path(name).and(routes(db, rules))
+}
+
+
+/// Combines all of the `records_` filters, relative to the path of the resource.
+fn routes<T: Resource>(db: Db<T>, rules: Arc<Rules>)
+    -> impl Filter<Extract = (impl warp::Reply,), Error = Rejection> + Clone
+{
+    records_stats(db.clone())
+        .or(records_list(db.clone()))
+        .or(record_by_id(db.clone()))
+        .or(records_export(db.clone()))
+        .or(records_sorted_by_column(db.clone()))
+        .or(create_record(db.clone(), rules.clone()))
+        .or(create_records_batch(db.clone(), rules.clone()))
+        .or(update_record(db.clone(), rules.clone()))
+        .or(patch_record(db.clone(), rules))
+        .or(delete_record(db))
+}
+
+
 /// Filter that responds with a list of records.
 /// A query-string may be provided with the values encoded in `models::ListOptions`
-/// which provides simple pagination.
-pub fn records_list(db: Db)
+/// which provides simple pagination, and sorting by any sequence of fields.
+/// Records can be selected with any number of `where` parameters (see `where_predicates`).
+/// The page is JSON, CSV or NDJSON, depending on the `Accept` header (see `accept_format`).
+///
+/// E.g., /records?sort=favorite_color:asc,last_name:desc&where=last_name^=Bre
+pub fn records_list<T: Resource>(db: Db<T>)
     -> impl Filter<Extract = impl warp::Reply, Error = Rejection> + Clone
 {
-    warp::path!("records")
+    warp::path::end()
         .and(warp::get())
+        .and(accept_format())
         .and(warp::query::<models::ListOptions>())
-        .and(with_db(db.clone()))
-        .and_then(handlers::list_records)
+        .and(page_url())
+        .and(where_predicates::<T>())
+        .and(date_formats())
+        .and(with_db(db))
+        .and_then(handlers::list_records::<T>)
 }
 
 
@@ -122,56 +493,197 @@ pub fn records_list(db: Db)
 /// E.g., /records/last_name
 ///
 /// reponds with a list of records ordered by `Persion.last_name` ascending.
-pub fn records_sorted_by_column(db: Db)
+pub fn records_sorted_by_column<T: Resource>(db: Db<T>)
     -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
 {
-    warp::path("records")
-        .and(warp::get())
+    warp::get()
         .and(warp::path::param())
-        .and_then(filter_field)
+        .and(warp::path::end())
+        .and_then(filter_field::<T>)
+        .and(accept_format())
         .and(warp::query::<models::ListOptions>())
+        .and(page_url())
+        .and(where_predicates::<T>())
+        .and(date_formats())
+        .and(with_db(db))
+        .and_then(handlers::list_records_sorted_by_field::<T>)
+}
+
+
+/// Filter that streams every record, sorted and selected like `records_list`
+/// but without pagination, as CSV, a JSON array or NDJSON, depending on the
+/// `Accept` header. CSV takes `separator` and `header` query params.
+///
+/// E.g., /records/export?sort=last_name:desc&header=true
+pub fn records_export<T: Resource>(db: Db<T>)
+    -> impl Filter<Extract = impl warp::Reply, Error = Rejection> + Clone
+{
+    warp::path!("export")
+        .and(warp::get())
+        .and(accept_format())
+        .and(warp::query::<models::ExportOptions>())
+        .and(where_predicates::<T>())
+        .and(date_formats())
+        .and(with_db(db))
+        .and_then(handlers::export_records::<T>)
+}
+
+
+/// Filter that responds with how many of the records selected like `records_list`
+/// there are in each group, the most common first, as JSON, CSV or NDJSON,
+/// depending on the `Accept` header. The groups are given by the `group_by`
+/// query param (see `group_by_fields`). CSV takes `separator` and `header` params.
+///
+/// E.g., /records/stats?group_by=favorite_color,dob:age&where=email~=@example.com$
+pub fn records_stats<T: Resource>(db: Db<T>)
+    -> impl Filter<Extract = impl warp::Reply, Error = Rejection> + Clone
+{
+    warp::path!("stats")
+        .and(warp::get())
+        .and(accept_format())
+        .and(warp::query::<models::StatsOptions>())
+        .and(where_predicates::<T>())
+        .and(with_db(db))
+        .and_then(handlers::record_stats::<T>)
+}
+
+
+/// Filter that responds with the single record stored under the id in the path.
+///
+/// E.g., /records/id/3
+pub fn record_by_id<T: Resource>(db: Db<T>)
+    -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
+{
+    warp::path!("id" / RecordId)
+        .and(warp::get())
+        .and(date_formats())
         .and(with_db(db))
-        .and_then(handlers::list_records_sorted_by_field)
+        .and_then(handlers::get_record::<T>)
 }
 
 
 /// Filter that provides a mechanism for `POST`ing a record to the database.
 /// There are two methods: CSV and JSON. This filter proxies to those.
-pub fn create_record(db: Db)
+pub fn create_record<T: Resource>(db: Db<T>, rules: Arc<Rules>)
     -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
 {
-    create_record_from_csv(db.clone())
-        .or(create_record_from_json(db))
+    create_record_from_csv(db.clone(), rules.clone())
+        .or(create_record_from_json(db, rules))
 }
 
 
 /// Filter that provides a POST endpoint for a body containing a single CSV row
 /// representing a record.
 /// Content-Type must be set to exactly `text/csv`. UTF-8 is assumed.
-pub fn create_record_from_csv(db: Db)
+pub fn create_record_from_csv<T: Resource>(db: Db<T>, rules: Arc<Rules>)
     -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
 {
-    warp::path("records")
+    warp::path::end()
         .and(warp::post())
-        .and(warp::header::exact_ignore_case("content-type", "text/csv"))
-        .and(csv_body())
+        .and(content_type("text/csv"))
+        .and(csv_body::<T>())
+        .and(date_formats())
+        .and(with_rules(rules))
         .and(with_db(db))
-        .and_then(handlers::create_record)
+        .and_then(handlers::create_record::<T>)
 }
 
 
 /// Filter that provides a POST endpoint for a body containing a single JSON object
 /// representing a record.
 /// Content-Type must be set to exactly `application/json`.
-pub fn create_record_from_json(db: Db)
+pub fn create_record_from_json<T: Resource>(db: Db<T>, rules: Arc<Rules>)
+    -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
+{
+    warp::path::end()
+        .and(warp::post())
+        .and(content_type("application/json"))
+        .and(json_body::<T>())
+        .and(date_formats())
+        .and(with_rules(rules))
+        .and(with_db(db))
+        .and_then(handlers::create_record::<T>)
+}
+
+
+/// Filter that provides the format of a batch body from its `Content-Type`:
+/// `text/csv`, `application/json` (an array) or `application/x-ndjson`.
+fn batch_format() -> impl Filter<Extract = (Format,), Error = Rejection> + Clone {
+    content_type("text/csv").map(|| Format::Csv)
+        .or(content_type("application/json").map(|| Format::Json))
+        .unify()
+        .or(content_type("application/x-ndjson").map(|| Format::Ndjson))
+        .unify()
+}
+
+
+/// Filter that provides a POST endpoint for a body containing any number of
+/// records: CSV rows, a JSON array or NDJSON. The query-string may set
+/// `header` and `separator` for CSV, and `mode` (`best-effort` or `all-or-nothing`).
+/// Body must be under `MAX_BATCH_BYTES` size.
+///
+/// E.g., /records/batch?header=true&separator=%7C&mode=all-or-nothing
+pub fn create_records_batch<T: Resource>(db: Db<T>, rules: Arc<Rules>)
     -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
 {
-    warp::path("records")
+    warp::path!("batch")
         .and(warp::post())
-        .and(warp::header::exact_ignore_case("content-type", "application/json"))
-        .and(json_body())
+        .and(batch_format())
+        .and(warp::query::<models::BatchOptions>())
+        .and(warp::body::content_length_limit(MAX_BATCH_BYTES))
+        .and(warp::body::bytes())
+        .and(date_formats())
+        .and(with_rules(rules))
+        .and(with_db(db))
+        .and_then(handlers::create_records_batch::<T>)
+}
+
+
+/// Filter that provides a `PUT` endpoint that replaces the record stored under
+/// the id in the path. As with `create_record`, the body may be a single CSV row
+/// (`text/csv`) or a JSON object (`application/json`).
+pub fn update_record<T: Resource>(db: Db<T>, rules: Arc<Rules>)
+    -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
+{
+    let csv = content_type("text/csv")
+        .and(csv_body::<T>());
+    let json = content_type("application/json")
+        .and(json_body::<T>());
+
+    warp::path!("id" / RecordId)
+        .and(warp::put())
+        .and(csv.or(json).unify())
+        .and(date_formats())
+        .and(with_rules(rules))
+        .and(with_db(db))
+        .and_then(handlers::update_record::<T>)
+}
+
+
+/// Filter that provides a `PATCH` endpoint that merges the keys of a JSON object
+/// into the record stored under the id in the path.
+pub fn patch_record<T: Resource>(db: Db<T>, rules: Arc<Rules>)
+    -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
+{
+    warp::path!("id" / RecordId)
+        .and(warp::patch())
+        .and(content_type("application/json"))
+        .and(json_patch_body())
+        .and(date_formats())
+        .and(with_rules(rules))
+        .and(with_db(db))
+        .and_then(handlers::patch_record::<T>)
+}
+
+
+/// Filter that provides a `DELETE` endpoint for the record stored under the id in the path.
+pub fn delete_record<T: Resource>(db: Db<T>)
+    -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
+{
+    warp::path!("id" / RecordId)
+        .and(warp::delete())
         .and(with_db(db))
-        .and_then(handlers::create_record)
+        .and_then(handlers::delete_record::<T>)
 }
 
 
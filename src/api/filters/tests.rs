@@ -50,15 +50,16 @@ fn assert_eq_person(from_rs: &Person, from_db: &Person) {
 
 #[cfg(test)]
 mod get {
-    use std::sync::Arc;
-    use tokio::sync::Mutex;
-
+    use crate::validation::Rules;
     use crate::api::handlers::ResultSet;
+    use crate::api::storage::{MemoryStorage, Record};
+    use crate::assert_field_order;
+    use crate::sorting::{FieldsOrd, SortDirection};
 
     use super::*;
     
-    fn init_db() -> Arc<Mutex<Vec<Person>>> {
-        Arc::new(Mutex::new(vec![
+    fn init_db() -> Db {
+        models::new_db(MemoryStorage::from(vec![
             Person::new(LAST_NAME, FIRST_NAME, EMAIL, FAVORITE_COLOR, DOB),
             Person::new("Brennan", "Chester", "", "green", ""),
             Person::new("Fuller", "Rachel", "", "green", "8/10/1970"),
@@ -69,7 +70,7 @@ mod get {
     #[tokio::test]
     async fn get_records() {
         let db = init_db();
-        let filter = records(db.clone());
+        let filter = records(db.clone(), Rules::default());
         let response = warp::test::request()
             .path("/records")
             .reply(&filter)
@@ -78,18 +79,19 @@ mod get {
         assert_eq!(response.status(), 200);
 
         let resultset = serde_json::from_slice::<ResultSet>(response.body());
-        let mut db_people = db.lock().await;
+        let mut db_people = db.read().await.records();
 
-        db_people.sort();
+        db_people.sort_by(|a, b| a.person.cmp(&b.person));
 
         match resultset {
             Ok(resultset) => {
                 let people = resultset.results;
                 assert_eq!(resultset.count, db_people.len());
                 assert_eq!(people.len(), resultset.length);
-                for (idx, person) in db_people.iter().enumerate() {
-                    let (from_db, from_rs) = (person, &people[idx]);
-                    assert_eq_person(from_rs, from_db);
+                for (idx, record) in db_people.iter().enumerate() {
+                    let (from_db, from_rs) = (record, &people[idx]);
+                    assert_eq!(from_db.id, from_rs.id);
+                    assert_eq_person(&from_rs.person, &from_db.person);
                 }
             },
             Err(e) => assert!(false, "{:?}", e)
@@ -100,7 +102,7 @@ mod get {
     #[tokio::test]
     async fn get_records_sorted_by_bad_column() {
         let db = init_db();
-        let filter = records(db.clone());
+        let filter = records(db.clone(), Rules::default());
         let response = warp::test::request()
             .path("/records/foo")
             .reply(&filter)
@@ -121,9 +123,180 @@ mod get {
     }
 
 
+    async fn get_sorted_records(query: &str) -> Vec<Person> {
+        let response = warp::test::request()
+            .path(format!("/records?{}", query).as_str())
+            .reply(&records(init_db(), Rules::default()))
+            .await;
+
+        assert_eq!(response.status(), 200);
+
+        serde_json::from_slice::<ResultSet>(response.body()).unwrap()
+            .results
+            .into_iter()
+            .map(|record| record.person)
+            .collect()
+    }
+
+
+    #[tokio::test]
+    async fn get_records_sorted_by_many_fields() {
+        let people = get_sorted_records("sort=favorite_color:desc,first_name").await;
+
+        let mut expected = init_db().read().await.records().into_iter()
+            .map(|record| record.person)
+            .collect::<Vec<Person>>();
+
+        expected.sort_by(|a, b| a.cmp_order_by_fields(b, &vec![
+            ("favorite_color", SortDirection::Desc),
+            ("first_name", SortDirection::Asc),
+        ]));
+
+        assert_eq!(people, expected);
+        assert_field_order!(people, first_name, ["Tom", "Chester", "Rachel", "June"]);
+    }
+
+
+    #[tokio::test]
+    async fn get_records_sort_uses_aliases_and_default_direction() {
+        let people = get_sorted_records("sort=name,birthdate&direction=desc").await;
+
+        assert_field_order!(people, first_name, ["Rachel", "Tom", "Chester", "June"]);
+    }
+
+
+    #[tokio::test]
+    async fn get_records_sorted_with_collation() {
+        let db = models::new_db(MemoryStorage::from(vec![
+            Person::new("Zimmer", "Anna", "", "", ""),
+            Person::new("de la Cruz", "Maria", "", "", ""),
+            Person::new("Élodie", "Jean", "", "", ""),
+            Person::new("Elias", "Paul", "", "", ""),
+        ]));
+        let filter = records(db, Rules::default());
+        let mut pages = vec![];
+
+        for query in ["sort=last_name:nocase:desc", "sort=last_name:noaccent&per-page=2", "sort=last_name:desc:noaccent"].iter() {
+            let response = warp::test::request()
+                .path(format!("/records?{}", query).as_str())
+                .reply(&filter)
+                .await;
+
+            assert_eq!(response.status(), 200);
+            pages.push(serde_json::from_slice::<ResultSet>(response.body()).unwrap().results
+                .into_iter()
+                .map(|record| record.person)
+                .collect::<Vec<Person>>());
+        }
+
+        let (nocase, noaccent, noaccent_desc) = (&pages[0], &pages[1], &pages[2]);
+
+        assert_field_order!(nocase, last_name, ["Élodie", "Zimmer", "Elias", "de la Cruz"]);
+        assert_field_order!(noaccent, last_name, ["de la Cruz", "Elias"]);
+        assert_field_order!(noaccent_desc, last_name, ["Zimmer", "Élodie", "Elias", "de la Cruz"]);
+    }
+
+
+    #[tokio::test]
+    async fn get_records_sorted_with_nulls_last() {
+        let people = get_sorted_records("sort=dob:desc:nulls=last").await;
+        assert_field_order!(people, first_name, ["Tom", "Rachel", "Chester", "June"]);
+
+        let people = get_sorted_records("sort=dob:nulls=last,first_name").await;
+        assert_field_order!(people, first_name, ["Rachel", "Tom", "Chester", "June"]);
+    }
+
+
+    #[tokio::test]
+    async fn get_records_sorted_by_bad_field() {
+        let response = warp::test::request()
+            .path("/records?sort=last_name,favourite_colour:asc")
+            .reply(&records(init_db(), Rules::default()))
+            .await;
+
+        assert_eq!(response.status(), 404);
+    }
+
+
+    #[tokio::test]
+    async fn get_records_sorted_by_bad_direction() {
+        let response = warp::test::request()
+            .path("/records?sort=last_name:up")
+            .reply(&records(init_db(), Rules::default()))
+            .await;
+
+        assert_eq!(response.status(), 400);
+
+        let api_error = serde_json::from_slice::<handlers::APIError>(response.body()).unwrap();
+        assert!(api_error.reason.eq("Invalid sort direction"), "Bad `reason`: {}", api_error.reason);
+    }
+
+
+    #[tokio::test]
+    async fn get_records_sorted_by_mistyped_direction() {
+        let response = warp::test::request()
+            .path("/records?sort=last_name:desk:nocase")
+            .reply(&records(init_db(), Rules::default()))
+            .await;
+
+        assert_eq!(response.status(), 400);
+
+        let api_error = serde_json::from_slice::<handlers::APIError>(response.body()).unwrap();
+        assert_eq!(api_error.reason, "Invalid sort direction");
+        assert!(api_error.context.contains("Did you mean \"desc\"?"), "Bad `context`: {}", api_error.context);
+    }
+
+
+    #[tokio::test]
+    async fn get_records_sorted_by_bad_modifier() {
+        for query in ["sort=last_name:nocsae", "sort=dob:nulls=lst:desc", "sort=last_name:latin1:asc"].iter() {
+            let response = warp::test::request()
+                .path(format!("/records?{}", query).as_str())
+                .reply(&records(init_db(), Rules::default()))
+                .await;
+
+            assert_eq!(response.status(), 400, "{}", query);
+
+            let api_error = serde_json::from_slice::<handlers::APIError>(response.body()).unwrap();
+            assert_eq!(api_error.reason, "Invalid sort modifier", "{}", query);
+        }
+    }
+
+
+    #[tokio::test]
+    async fn get_records_where() {
+        let people = get_sorted_records("where=favorite_color%3Dgreen&where=dob%3E%3D1970-01-01&sort=first_name").await;
+
+        assert_eq!(people.len(), 1);
+        assert_field_order!(people, first_name, ["Rachel"]);
+    }
+
+
+    #[tokio::test]
+    async fn get_records_where_bad_field() {
+        let response = warp::test::request()
+            .path("/records/name?where=colour%3Dgreen")
+            .reply(&records(init_db(), Rules::default()))
+            .await;
+
+        assert_eq!(response.status(), 404);
+    }
+
+
+    #[tokio::test]
+    async fn get_records_where_bad_predicate() {
+        let response = warp::test::request()
+            .path("/records?where=favorite_color")
+            .reply(&records(init_db(), Rules::default()))
+            .await;
+
+        assert_eq!(response.status(), 400);
+    }
+
+
     async fn get_records_sorted_by_column(column: &'static str, first_idx: usize, last_idx: usize) {
         let db = init_db();
-        let filter = records(db.clone());
+        let filter = records(db.clone(), Rules::default());
         let response = warp::test::request()
             .path(format!("/records/{}", column).as_str())
             .reply(&filter)
@@ -132,15 +305,15 @@ mod get {
         assert_eq!(response.status(), 200);
 
         let results = serde_json::from_slice::<Vec<Person>>(response.body());
-        let db_people = db.lock().await;
+        let db_people = db.read().await.records();
 
         match results {
             Ok(people) => {
                 let first = people.first().unwrap();
                 let last = people.last().unwrap();
 
-                assert_eq_person(first, &db_people[first_idx]);
-                assert_eq_person(last, &db_people[last_idx]);
+                assert_eq_person(first, &db_people[first_idx].person);
+                assert_eq_person(last, &db_people[last_idx].person);
             },
             Err(e) => assert!(false, "{:?}", e)
         }
@@ -172,6 +345,151 @@ mod get {
         let _ = get_records_sorted_by_column("dob", 2, 3);
     }
 
+    #[tokio::test]
+    async fn get_records_as_csv() {
+        let response = warp::test::request()
+            .path("/records?sort=first_name&per-page=2&page=2&header=true&separator=%7C&where=last_name%3DBrennan")
+            .header("accept", "text/csv")
+            .reply(&records(init_db(), Rules::default()))
+            .await;
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["content-type"], "text/csv");
+        assert_eq!(response.headers()["x-total-count"], "3");
+        assert_eq!(response.headers()["link"], [
+            "</records?sort=first_name&per-page=2&header=true&separator=%7C&where=last_name%3DBrennan&page=1>; rel=\"first\"",
+            "</records?sort=first_name&per-page=2&header=true&separator=%7C&where=last_name%3DBrennan&page=1>; rel=\"prev\"",
+            "</records?sort=first_name&per-page=2&header=true&separator=%7C&where=last_name%3DBrennan&page=2>; rel=\"last\"",
+        ].join(", ").as_str());
+        assert_eq!(response.body(), "last_name|first_name|email|favorite_color|dob\r\nBrennan|Tom|tjb1982@gmail.com|red|8/19/1982\r\n");
+    }
+
+    #[tokio::test]
+    async fn get_records_as_ndjson() {
+        let response = warp::test::request()
+            .path("/records/first_name?per-page=3")
+            .header("accept", "application/x-ndjson")
+            .reply(&records(init_db(), Rules::default()))
+            .await;
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["x-total-count"], "4");
+        assert!(response.headers()["link"].to_str().unwrap().contains("page=2>; rel=\"next\""));
+
+        let body = String::from_utf8(response.body().to_vec()).unwrap();
+        let people: Vec<Record> = body.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+
+        assert_eq!(people.len(), 3);
+        assert_eq!(people[0].person.first_name, "Chester");
+    }
+
+    async fn get_page(db: &Db, query: &str) -> ResultSet {
+        let response = warp::test::request()
+            .path(&format!("/records?{}", query))
+            .reply(&records(db.clone(), Rules::default()))
+            .await;
+
+        assert_eq!(response.status(), 200);
+        serde_json::from_slice::<ResultSet>(response.body()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn get_records_by_cursor() {
+        let db = init_db();
+        let first = get_page(&db, "sort=last_name,first_name&per-page=2").await;
+        let names = |set: &ResultSet| set.results.iter()
+            .map(|record| record.person.first_name.clone())
+            .collect::<Vec<String>>();
+
+        assert_eq!(names(&first), vec!["Chester", "June"]);
+
+        // Inserted before the cursor: offset paging would repeat "June" on page 2.
+        db.write().await.insert(Person::new("Brennan", "Alice", "", "", "")).unwrap();
+
+        let cursor = first.next_cursor.unwrap();
+        let second = get_page(&db, &format!("sort=last_name,first_name&per-page=2&cursor={}", cursor)).await;
+
+        assert_eq!(names(&second), vec!["Tom", "Rachel"]);
+        assert_eq!(second.count, 5);
+        assert_eq!(second.next_cursor, None);
+    }
+
+    #[tokio::test]
+    async fn get_records_by_cursor_without_sort() {
+        let db = init_db();
+        let mut seen = vec![];
+        let mut query = String::from("per-page=1");
+
+        loop {
+            let set = get_page(&db, &query).await;
+            seen.extend(set.results.into_iter().map(|record| record.person));
+
+            match set.next_cursor {
+                Some(cursor) => query = format!("per-page=1&cursor={}", cursor),
+                None => break,
+            }
+        }
+
+        let mut expected: Vec<Person> = db.read().await.records().into_iter().map(|record| record.person).collect();
+        expected.sort();
+
+        assert_eq!(seen, expected);
+    }
+
+    #[tokio::test]
+    async fn get_records_by_bad_cursor() {
+        let db = init_db();
+        let cursor = get_page(&db, "sort=first_name&per-page=1").await.next_cursor.unwrap();
+
+        for query in [format!("sort=last_name&cursor={}", cursor), "cursor=nope".to_string()].iter() {
+            let response = warp::test::request()
+                .path(&format!("/records?{}", query))
+                .reply(&records(db.clone(), Rules::default()))
+                .await;
+
+            assert_eq!(response.status(), 400);
+
+            let api_error = serde_json::from_slice::<handlers::APIError>(response.body()).unwrap();
+            assert!(api_error.reason.eq("Invalid cursor"), "Bad `reason`: {}", api_error.reason);
+        }
+    }
+
+    #[tokio::test]
+    async fn get_records_per_page_clamped() {
+        let db = init_db();
+        let none = get_page(&db, "per-page=0").await;
+        let all = get_page(&db, &format!("per-page={}", usize::MAX)).await;
+        let past_the_end = get_page(&db, &format!("per-page={}&page={}", usize::MAX, usize::MAX)).await;
+
+        assert_eq!((none.length, none.next_cursor.is_some()), (1, true));
+        assert_eq!((all.length, all.next_cursor.is_some()), (4, false));
+        assert_eq!((past_the_end.length, past_the_end.count), (0, 4));
+    }
+
+    #[tokio::test]
+    async fn get_records_page_zero() {
+        let response = warp::test::request()
+            .path("/records?page=0")
+            .reply(&records(init_db(), Rules::default()))
+            .await;
+
+        assert_eq!(response.status(), 400);
+
+        let api_error = serde_json::from_slice::<handlers::APIError>(response.body()).unwrap();
+        assert!(api_error.reason.eq("Invalid page"), "Bad `reason`: {}", api_error.reason);
+    }
+
+    #[tokio::test]
+    async fn get_records_not_acceptable() {
+        let response = warp::test::request()
+            .path("/records")
+            .header("accept", "image/png")
+            .reply(&records(init_db(), Rules::default()))
+            .await;
+
+        assert_eq!(response.status(), 406);
+    }
+
 }
 
 #[cfg(test)]
@@ -197,7 +515,7 @@ mod post {
                 "dob": "{}"
             }}"###, LAST_NAME, FIRST_NAME, EMAIL, FAVORITE_COLOR, DOB));
 
-        let person = request.filter(&json_body()).await.unwrap();
+        let person = request.filter(&json_body::<Person>()).await.unwrap();
 
         assert_person_struct_fields(&person)
     }
@@ -210,7 +528,7 @@ mod post {
                 "last_name": "foo"
             }"###);
 
-        match request.filter(&json_body()).await {
+        match request.filter(&json_body::<Person>()).await {
             Ok(x) => assert!(false, "{:?} should not exist.", x),
             Err(e) => assert_missing_field(e)
         }
@@ -229,7 +547,7 @@ mod post {
                 "foo": "bar"
             }}"###, LAST_NAME, FIRST_NAME, EMAIL, FAVORITE_COLOR, DOB));
 
-        match request.filter(&json_body()).await {
+        match request.filter(&json_body::<Person>()).await {
             Ok(p) => assert_person_struct_fields(&p),
             Err(e) => assert!(false, "{:?}", e)
         }
@@ -242,7 +560,7 @@ mod post {
             format!(r###"{}, {}, {}, {}, {}
                 "###, LAST_NAME, FIRST_NAME, EMAIL, FAVORITE_COLOR, DOB));
 
-        match request.filter(&csv_body()).await {
+        match request.filter(&csv_body::<Person>()).await {
             Ok(p) => assert_person_struct_fields(&p),
             Err(e) => assert!(false, "{:?}", e)
         }
@@ -256,7 +574,7 @@ mod post {
                 "
         );
 
-        match request.filter(&csv_body()).await {
+        match request.filter(&csv_body::<Person>()).await {
             Ok(p) => assert!(false, "Should not exist: {:?}", p),
             Err(e) => assert_missing_field(e)
         }
@@ -270,9 +588,804 @@ mod post {
                 LAST_NAME, FIRST_NAME, EMAIL, FAVORITE_COLOR, DOB)
         );
 
-        match request.filter(&csv_body()).await {
+        match request.filter(&csv_body::<Person>()).await {
             Ok(p) => assert_person_struct_fields(&p),
             Err(e) => assert!(false, "{:?}", e)
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod by_id {
+    use crate::validation::Rules;
+    use crate::api::storage::{MemoryStorage, Record};
+
+    use super::*;
+
+    fn init_db() -> Db {
+        models::new_db(MemoryStorage::from(vec![
+            Person::new(LAST_NAME, FIRST_NAME, EMAIL, FAVORITE_COLOR, DOB),
+            Person::new("Fuller", "Rachel", "", "green", "8/10/1970"),
+        ]))
+    }
+
+    fn assert_record_not_found(response: &warp::http::Response<warp::hyper::body::Bytes>) {
+        assert_eq!(response.status(), 404);
+
+        let api_error = serde_json::from_slice::<handlers::APIError>(response.body()).unwrap();
+        assert!(api_error.reason.eq("Record not found"), "Bad `reason`: {}", api_error.reason);
+    }
+
+
+    #[tokio::test]
+    async fn post_returns_id() {
+        let db = init_db();
+        let response = warp::test::request()
+            .method("POST")
+            .path("/records")
+            .header("content-type", "text/csv")
+            .body("Brennan, June, , , ")
+            .reply(&records(db.clone(), Rules::default()))
+            .await;
+
+        assert_eq!(response.status(), 201);
+
+        let record = serde_json::from_slice::<Record>(response.body()).unwrap();
+
+        assert_eq!(record.id, 2);
+        assert_eq!(db.read().await.get(2), Some(record));
+    }
+
+
+    #[tokio::test]
+    async fn get_by_id() {
+        let db = init_db();
+        let response = warp::test::request()
+            .path("/records/id/1")
+            .reply(&records(db.clone(), Rules::default()))
+            .await;
+
+        assert_eq!(response.status(), 200);
+
+        let record = serde_json::from_slice::<Record>(response.body()).unwrap();
+
+        assert_eq!(record.id, 1);
+        assert!(record.person.first_name.eq("Rachel"));
+    }
+
+
+    #[tokio::test]
+    async fn get_missing_id() {
+        let response = warp::test::request()
+            .path("/records/id/42")
+            .reply(&records(init_db(), Rules::default()))
+            .await;
+
+        assert_record_not_found(&response);
+    }
+
+
+    #[tokio::test]
+    async fn put_replaces_record() {
+        let db = init_db();
+        let response = warp::test::request()
+            .method("PUT")
+            .path("/records/id/1")
+            .header("content-type", "text/csv")
+            .body("Fuller, Rae, rae@example.com, blue, 8/10/1970")
+            .reply(&records(db.clone(), Rules::default()))
+            .await;
+
+        assert_eq!(response.status(), 200);
+
+        let person = db.read().await.get(1).unwrap().person;
+
+        assert_eq_person(&person, &Person::new("Fuller", "Rae", "rae@example.com", "blue", "8/10/1970"));
+    }
+
+
+    #[tokio::test]
+    async fn patch_merges_fields() {
+        let db = init_db();
+        let response = warp::test::request()
+            .method("PATCH")
+            .path("/records/id/0")
+            .header("content-type", "application/json")
+            .body(r#"{"favorite_color": "blue", "dob": "8/20/1982"}"#)
+            .reply(&records(db.clone(), Rules::default()))
+            .await;
+
+        assert_eq!(response.status(), 200);
+
+        let person = db.read().await.get(0).unwrap().person;
+
+        assert_eq_person(&person, &Person::new(LAST_NAME, FIRST_NAME, EMAIL, "blue", "8/20/1982"));
+    }
+
+
+    #[tokio::test]
+    async fn patch_rejects_non_object() {
+        let response = warp::test::request()
+            .method("PATCH")
+            .path("/records/id/0")
+            .header("content-type", "application/json")
+            .body("[]")
+            .reply(&records(init_db(), Rules::default()))
+            .await;
+
+        assert_eq!(response.status(), 400);
+    }
+
+
+    #[tokio::test]
+    async fn patch_rejects_malformed_json() {
+        let response = warp::test::request()
+            .method("PATCH")
+            .path("/records/id/0")
+            .header("content-type", "application/json")
+            .body("{bad")
+            .reply(&records(init_db(), Rules::default()))
+            .await;
+
+        assert_eq!(response.status(), 400);
+
+        let error = serde_json::from_slice::<crate::api::handlers::APIError>(response.body()).unwrap();
+
+        assert_eq!(error.reason, "Unable to parse JSON body");
+        assert!(!error.context.contains("Rejection"));
+    }
+
+
+    #[tokio::test]
+    async fn method_not_allowed() {
+        let response = warp::test::request()
+            .method("TRACE")
+            .path("/records/id/0")
+            .reply(&records(init_db(), Rules::default()))
+            .await;
+
+        let error = serde_json::from_slice::<crate::api::handlers::APIError>(response.body()).unwrap();
+
+        assert_eq!(response.status(), 405);
+        assert!(!error.reason.contains("Rejection"));
+    }
+
+
+    #[tokio::test]
+    async fn delete_removes_record() {
+        let db = init_db();
+        let filter = records(db.clone(), Rules::default());
+        let response = warp::test::request()
+            .method("DELETE")
+            .path("/records/id/0")
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), 204);
+        assert_eq!(db.read().await.len(), 1);
+
+        let response = warp::test::request()
+            .method("DELETE")
+            .path("/records/id/0")
+            .reply(&filter)
+            .await;
+
+        assert_record_not_found(&response);
+    }
+
+
+    #[tokio::test]
+    async fn get_with_accept_date_format() {
+        let response = warp::test::request()
+            .path("/records/id/1")
+            .header("accept", "application/json; date-format=iso")
+            .reply(&records(init_db(), Rules::default()))
+            .await;
+
+        assert_eq!(response.status(), 200);
+
+        let record = serde_json::from_slice::<serde_json::Value>(response.body()).unwrap();
+
+        assert_eq!(record["dob"], "1970-08-10");
+    }
+
+
+    #[tokio::test]
+    async fn post_with_content_type_date_format() {
+        use chrono::NaiveDate;
+
+        let db = init_db();
+        let response = warp::test::request()
+            .method("POST")
+            .path("/records")
+            .header("content-type", "text/csv; date-format=\"%d.%m.%Y\"")
+            .body("Brennan, June, , , 1.6.1990")
+            .reply(&records(db.clone(), Rules::default()))
+            .await;
+
+        assert_eq!(response.status(), 201);
+        assert_eq!(db.read().await.get(2).unwrap().person.dob, NaiveDate::from_ymd_opt(1990, 6, 1));
+    }
+
+
+    #[tokio::test]
+    async fn get_with_invalid_date_format() {
+        let response = warp::test::request()
+            .path("/records/id/1")
+            .header("accept", "application/json; date-format=%Q")
+            .reply(&records(init_db(), Rules::default()))
+            .await;
+
+        assert_eq!(response.status(), 400);
+    }
+
+
+    #[tokio::test]
+    async fn post_invalid_record() {
+        let db = init_db();
+        let response = warp::test::request()
+            .method("POST")
+            .path("/records")
+            .header("content-type", "text/csv")
+            .body(", June, june, , ")
+            .reply(&records(db.clone(), Rules::default()))
+            .await;
+
+        assert_eq!(response.status(), 422);
+        assert_eq!(db.read().await.len(), 2);
+
+        let api_error = serde_json::from_slice::<handlers::APIError>(response.body()).unwrap();
+        let fields: Vec<&str> = api_error.errors.iter().map(|e| e.field.as_str()).collect();
+
+        assert_eq!(fields, vec!["last_name", "email"]);
+    }
+
+
+    #[tokio::test]
+    async fn patch_invalid_record() {
+        let db = init_db();
+        let response = warp::test::request()
+            .method("PATCH")
+            .path("/records/id/1")
+            .header("content-type", "application/json")
+            .body(r#"{"favorite_color": "mauve"}"#)
+            .reply(&records(db.clone(), Rules {
+                favorite_colors: Some(vec!["red".into(), "green".into()]),
+                ..Rules::default()
+            }))
+            .await;
+
+        assert_eq!(response.status(), 422);
+        assert!(db.read().await.get(1).unwrap().person.favorite_color.eq("green"));
+    }
+}
+
+#[cfg(test)]
+mod batch {
+    use crate::api::handlers::{BatchRow, BatchSummary};
+    use crate::api::storage::MemoryStorage;
+    use crate::validation::Rules;
+    use super::*;
+
+    fn init_db() -> Db {
+        models::new_db(MemoryStorage::from(vec![
+            Person::new(LAST_NAME, FIRST_NAME, EMAIL, FAVORITE_COLOR, DOB),
+        ]))
+    }
+
+    async fn post(db: &Db, query: &str, content_type: &str, body: &str) -> (u16, BatchSummary) {
+        let response = warp::test::request()
+            .method("POST")
+            .path(&format!("/records/batch{}", query))
+            .header("content-type", content_type)
+            .body(body)
+            .reply(&records(db.clone(), Rules::default()))
+            .await;
+
+        (response.status().as_u16(), serde_json::from_slice(response.body()).unwrap())
+    }
+
+    const CSV: &str = "last_name|first_name|email|favorite_color|dob
+Fuller|Rachel||green|8/10/1970
+Brennan|June|june|blue|
+Brennan|Chester||red|
+";
+
+    #[tokio::test]
+    async fn csv_best_effort() {
+        let db = init_db();
+        let (status, summary) = post(&db, "?header=true&separator=%7C", "text/csv", CSV).await;
+
+        assert_eq!(status, 201);
+        assert!(summary.committed);
+        assert_eq!((summary.accepted, summary.rejected), (2, 1));
+        assert_eq!(summary.rows[0], BatchRow::Accepted { record: 1, id: Some(1) });
+        assert_eq!(summary.rows[2], BatchRow::Accepted { record: 3, id: Some(2) });
+
+        match &summary.rows[1] {
+            BatchRow::Rejected { record, line, field, .. } => {
+                assert_eq!(*record, Some(2));
+                assert_eq!(*line, Some(3));
+                assert_eq!(field.as_deref(), Some("email"));
+            },
+            row => panic!("Expected a rejected row, got {:?}", row),
+        }
+
+        assert_eq!(db.read().await.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn csv_all_or_nothing() {
+        let db = init_db();
+        let (status, summary) = post(&db, "?header=true&separator=%7C&mode=all-or-nothing", "text/csv", CSV).await;
+
+        assert_eq!(status, 422);
+        assert!(!summary.committed);
+        assert_eq!((summary.accepted, summary.rejected), (2, 1));
+        assert_eq!(summary.rows[0], BatchRow::Accepted { record: 1, id: None });
+        assert_eq!(db.read().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn json_and_ndjson() {
+        let db = init_db();
+        let people = vec![
+            Person::new("Fuller", "Rachel", "", "green", "8/10/1970"),
+            Person::new("Brennan", "June", "", "blue", ""),
+        ];
+
+        let (status, summary) = post(&db, "", "application/json", &serde_json::to_string(&people).unwrap()).await;
+
+        assert_eq!(status, 201);
+        assert_eq!(summary.accepted, 2);
+
+        let ndjson: String = people.iter()
+            .map(|p| format!("{}\n", serde_json::to_string(p).unwrap()))
+            .collect();
+        let (status, summary) = post(&db, "?mode=all-or-nothing", "application/x-ndjson", &ndjson).await;
+
+        assert_eq!(status, 201);
+        assert_eq!(summary.rows[1], BatchRow::Accepted { record: 2, id: Some(4) });
+        assert_eq!(db.read().await.len(), 5);
+    }
+
+    #[tokio::test]
+    async fn malformed_json() {
+        let db = init_db();
+        let (status, summary) = post(&db, "?mode=all-or-nothing", "application/json", "[{\"last_name\": ").await;
+
+        assert_eq!(status, 422);
+        assert_eq!(summary.rejected, 1);
+        assert_eq!(db.read().await.len(), 1);
+    }
+}
+
+
+#[cfg(test)]
+mod export {
+    use crate::api::storage::MemoryStorage;
+    use crate::validation::Rules;
+    use super::*;
+
+    fn init_db() -> Db {
+        models::new_db(MemoryStorage::from(vec![
+            Person::new(LAST_NAME, FIRST_NAME, EMAIL, FAVORITE_COLOR, DOB),
+            Person::new("Brennan", "Chester", "", "green", ""),
+            Person::new("Fuller", "Rachel", "", "green", "8/10/1970"),
+        ]))
+    }
+
+    async fn export(query: &str, accept: Option<&str>) -> (u16, String, String) {
+        let mut request = warp::test::request()
+            .method("GET")
+            .path(&format!("/records/export{}", query));
+
+        if let Some(accept) = accept {
+            request = request.header("accept", accept);
+        }
+
+        let response = request.reply(&records(init_db(), Rules::default())).await;
+        let content_type = response.headers().get("content-type")
+            .map(|value| value.to_str().unwrap().to_string())
+            .unwrap_or_default();
+
+        (response.status().as_u16(), content_type, String::from_utf8(response.body().to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn csv_with_header_and_separator() {
+        let (status, content_type, body) = export(
+            "?sort=first_name&header=true&separator=%7C", Some("text/csv")
+        ).await;
+
+        assert_eq!(status, 200);
+        assert_eq!(content_type, "text/csv");
+        assert_eq!(body, [
+            "last_name|first_name|email|favorite_color|dob",
+            "Brennan|Chester||green|",
+            "Fuller|Rachel||green|8/10/1970",
+            "Brennan|Tom|tjb1982@gmail.com|red|8/19/1982",
+            "",
+        ].join("\r\n"));
+    }
+
+    #[tokio::test]
+    async fn csv_defaults_to_cli_order() {
+        let (_, _, body) = export("?where=favorite_color%3Dgreen", Some("text/*")).await;
+        let names: Vec<&str> = body.lines().map(|line| line.split(',').nth(1).unwrap()).collect();
+
+        assert_eq!(names, vec!["Chester", "Rachel"]);
+    }
+
+    #[tokio::test]
+    async fn json_array() {
+        let (status, content_type, body) = export("?sort=dob:desc", None).await;
+        let people: Vec<Person> = serde_json::from_str(&body).unwrap();
+
+        assert_eq!(status, 200);
+        assert_eq!(content_type, "application/json");
+        assert_eq!(people.len(), 3);
+        assert_eq!(people[0].first_name, FIRST_NAME);
+    }
+
+    #[tokio::test]
+    async fn ndjson() {
+        let (status, content_type, body) = export("?sort=last_name:desc", Some("application/x-ndjson, */*")).await;
+        let people: Vec<Person> = body.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+
+        assert_eq!(status, 200);
+        assert_eq!(content_type, "application/x-ndjson");
+        assert_eq!(people[0].last_name, "Fuller");
+        assert_eq!(people.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn more_records_than_a_chunk() {
+        let people: Vec<Person> = (0..2500)
+            .map(|n| Person::new(["Brennan", "fuller", "Zimmer"][n % 3], &format!("N{:04}", n), "", ["red", "green"][n % 2], ""))
+            .collect();
+        let filter = records(models::new_db(MemoryStorage::from(people)), Rules::default());
+
+        for query in ["?sort=favorite_color,first_name:desc", "?sort=last_name:nocase:desc"].iter() {
+            let response = warp::test::request()
+                .path(&format!("/records/export{}", query))
+                .header("accept", "application/x-ndjson")
+                .reply(&filter)
+                .await;
+            let body = String::from_utf8(response.body().to_vec()).unwrap();
+            let people: Vec<Person> = body.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+            let mut names: Vec<&str> = people.iter().map(|person| person.first_name.as_str()).collect();
+
+            assert_eq!(people.len(), 2500, "{}", query);
+            assert!(people.windows(2).all(|pair| match *query {
+                "?sort=favorite_color,first_name:desc" => (&pair[0].favorite_color, &pair[1].first_name) <= (&pair[1].favorite_color, &pair[0].first_name),
+                _ => pair[0].last_name.to_lowercase() >= pair[1].last_name.to_lowercase(),
+            }), "{}", query);
+
+            names.sort_unstable();
+            names.dedup();
+            assert_eq!(names.len(), 2500, "{}", query);
+        }
+    }
+
+    #[tokio::test]
+    async fn csv_rows_with_columns_that_are_not_kept() {
+        let mut person = Person::new("Fuller", "Rachel", "", "green", "8/10/1970");
+        person.extra.insert("phone".to_string(), "555-0100".to_string());
+
+        let filter = records(models::new_db(MemoryStorage::from(vec![person])), Rules::default());
+        let response = warp::test::request()
+            .path("/records/export")
+            .header("accept", "text/csv")
+            .reply(&filter)
+            .await;
+
+        assert_eq!(String::from_utf8(response.body().to_vec()).unwrap(), "Fuller,Rachel,,green,8/10/1970\r\n");
+    }
+
+    #[tokio::test]
+    async fn not_acceptable() {
+        let (status, _, body) = export("", Some("application/xml")).await;
+
+        assert_eq!(status, 406);
+
+        let api_error = serde_json::from_str::<handlers::APIError>(&body).unwrap();
+        assert!(api_error.reason.eq("Not acceptable"), "Bad `reason`: {}", api_error.reason);
+    }
+}
+
+
+#[cfg(test)]
+mod stats {
+    use crate::api::storage::MemoryStorage;
+    use crate::validation::Rules;
+    use super::*;
+
+    fn init_db() -> Db {
+        models::new_db(MemoryStorage::from(vec![
+            Person::new(LAST_NAME, FIRST_NAME, EMAIL, FAVORITE_COLOR, DOB),
+            Person::new("Brennan", "Chester", "chester@example.com", "green", ""),
+            Person::new("Fuller", "Rachel", "rf@Example.com", "green", "8/10/1970"),
+        ]))
+    }
+
+    async fn stats(query: &str, accept: Option<&str>) -> (u16, String) {
+        let mut request = warp::test::request()
+            .method("GET")
+            .path(&format!("/records/stats{}", query));
+
+        if let Some(accept) = accept {
+            request = request.header("accept", accept);
+        }
+
+        let response = request.reply(&records(init_db(), Rules::default())).await;
+
+        (response.status().as_u16(), String::from_utf8(response.body().to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn by_field() {
+        let (status, body) = stats("?group_by=color", None).await;
+
+        assert_eq!(status, 200);
+        assert_eq!(body, "[{\"favorite_color\":\"green\",\"count\":2},{\"favorite_color\":\"red\",\"count\":1}]\n");
+    }
+
+    #[tokio::test]
+    async fn by_buckets_as_csv() {
+        let (status, body) = stats("?group_by=email:domain,dob:year&where=last_name%3DFuller&header=true", Some("text/csv")).await;
+
+        assert_eq!(status, 200);
+        assert_eq!(body, "email:domain,dob:year,count\r\nexample.com,1970,1\r\n");
+    }
+
+    #[tokio::test]
+    async fn invalid() {
+        assert_eq!(stats("?group_by=nope", None).await.0, 404);
+        assert_eq!(stats("?group_by=dob:domain", None).await.0, 400);
+        assert_eq!(stats("", None).await.0, 400);
+    }
+}
+
+
+#[cfg(test)]
+mod concurrency {
+    use crate::api::handlers::ResultSet;
+    use crate::api::storage::MemoryStorage;
+    use crate::validation::Rules;
+    use super::*;
+
+    const WRITERS: usize = 8;
+    const READERS: usize = 8;
+    const RECORDS_PER_WRITER: usize = 25;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn create_while_listing_sorted() {
+        let db = models::new_db(MemoryStorage::from(vec![
+            Person::new(LAST_NAME, FIRST_NAME, EMAIL, FAVORITE_COLOR, DOB),
+        ]));
+        let filter = records(db.clone(), Rules::default());
+        let mut tasks = vec![];
+
+        for writer in 0..WRITERS {
+            let filter = filter.clone();
+
+            tasks.push(tokio::spawn(async move {
+                for n in 0..RECORDS_PER_WRITER {
+                    let person = Person::new(&format!("Writer{}", writer), &format!("N{:03}", n), "", "", "");
+                    let response = warp::test::request()
+                        .method("POST")
+                        .path("/records")
+                        .json(&person)
+                        .reply(&filter)
+                        .await;
+
+                    assert_eq!(response.status(), 201);
+                }
+            }));
+        }
+
+        for _ in 0..READERS {
+            let filter = filter.clone();
+
+            tasks.push(tokio::spawn(async move {
+                let mut seen = 0;
+
+                for _ in 0..RECORDS_PER_WRITER {
+                    let response = warp::test::request()
+                        .path("/records/last_name?direction=desc&per-page=50")
+                        .reply(&filter)
+                        .await;
+
+                    assert_eq!(response.status(), 200);
+
+                    let set = serde_json::from_slice::<ResultSet>(response.body()).unwrap();
+                    let people: Vec<&Person> = set.results.iter().map(|record| &record.person).collect();
+
+                    // Every listing is a consistent snapshot: a full page, sorted, and never older than the last one.
+                    assert_eq!(set.length, set.count.min(50));
+                    assert!(set.count >= seen);
+                    assert!(people.windows(2).all(|pair| pair[0].last_name >= pair[1].last_name));
+                    seen = set.count;
+                }
+            }));
+        }
+
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        assert_eq!(db.read().await.len(), 1 + WRITERS * RECORDS_PER_WRITER);
+    }
+}
+
+
+#[cfg(test)]
+mod resource {
+    use serde::{Serialize, Deserialize};
+    use crate::api::handlers::{APIError, ResultSet};
+    use crate::api::storage::{MemoryStorage, Record};
+    use crate::dedupe::{DedupeOpts, Deduper, Policy};
+    use crate::filtering::FieldsFilter;
+    use crate::io::Origin;
+    use crate::serialization::StructFields;
+    use crate::sorting::FieldsOrd;
+    use crate::validation::Rules;
+    use super::*;
+
+    /// A second kind of record, served next to the people
+    #[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, FieldsOrd, FieldsFilter, StructFields)]
+    struct Account {
+        number: String,
+        owner: String,
+        currency: Option<String>,
+    }
+
+    impl Resource for Account {}
+
+    fn account(number: &str, owner: &str, currency: Option<&str>) -> Account {
+        Account { number: number.into(), owner: owner.into(), currency: currency.map(String::from) }
+    }
+
+    fn init_db_accounts() -> Vec<Account> {
+        vec![
+            account("003", "Tom", Some("USD")),
+            account("001", "Rachel", None),
+            account("002", "Tom", Some("EUR")),
+        ]
+    }
+
+    fn init_db() -> Db<Account> {
+        models::new_db(MemoryStorage::from(init_db_accounts()))
+    }
+
+    fn api(accounts: Db<Account>) -> impl Filter<Extract = impl warp::Reply, Error = Rejection> + Clone {
+        let people = models::new_db(MemoryStorage::from(vec![
+            Person::new(LAST_NAME, FIRST_NAME, EMAIL, FAVORITE_COLOR, DOB),
+        ]));
+        let rules = Arc::new(Rules { required: vec!["owner".into()], ..Rules::none() });
+
+        super::people(people, Arc::new(Rules::default()))
+            .or(super::resource("accounts", accounts, rules))
+            .recover(handlers::handle_rejection)
+    }
+
+    #[tokio::test]
+    async fn list_sorted_and_filtered() {
+        let response = warp::test::request()
+            .path("/accounts?sort=owner:desc,number&where=currency!=")
+            .reply(&api(init_db()))
+            .await;
+
+        assert_eq!(response.status(), 200);
+
+        let set = serde_json::from_slice::<ResultSet<Account>>(response.body()).unwrap();
+        let numbers: Vec<&str> = set.results.iter().map(|record| record.person.number.as_str()).collect();
+
+        assert_eq!(numbers, ["002", "003"]);
+    }
+
+    #[tokio::test]
+    async fn sorted_by_column_as_csv() {
+        let response = warp::test::request()
+            .path("/accounts/number?header=true")
+            .header("accept", "text/csv")
+            .reply(&api(init_db()))
+            .await;
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.body(), "number,owner,currency\r\n001,Rachel,\r\n002,Tom,EUR\r\n003,Tom,USD\r\n");
+    }
+
+    #[tokio::test]
+    async fn created_and_validated() {
+        let db = init_db();
+        let filter = api(db.clone());
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/accounts")
+            .json(&account("004", "June", Some("GBP")))
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), 201);
+
+        let record = serde_json::from_slice::<Record<Account>>(response.body()).unwrap();
+
+        assert_eq!(db.read().await.get(record.id), Some(record));
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/accounts")
+            .header("content-type", "text/csv")
+            .body("005,,CHF")
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), 422);
+        assert_eq!(db.read().await.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn unknown_field() {
+        let response = warp::test::request()
+            .path("/accounts/last_name")
+            .reply(&api(init_db()))
+            .await;
+
+        assert_eq!(response.status(), 404);
+
+        let error = serde_json::from_slice::<APIError>(response.body()).unwrap();
+
+        assert_eq!(error.context, "Available fields: number, owner, currency");
+    }
+
+    #[tokio::test]
+    async fn people_still_served() {
+        let response = warp::test::request()
+            .path("/records/id/0")
+            .reply(&api(init_db()))
+            .await;
+
+        assert_eq!(response.status(), 200);
+
+        let record = serde_json::from_slice::<Record>(response.body()).unwrap();
+
+        assert_eq!(record.person.last_name, LAST_NAME);
+    }
+
+    #[tokio::test]
+    async fn stats() {
+        let response = warp::test::request()
+            .path("/accounts/stats?group_by=owner")
+            .header("accept", "text/csv")
+            .reply(&api(init_db()))
+            .await;
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.body(), "Tom,2\r\nRachel,1\r\n");
+
+        let response = warp::test::request()
+            .path("/accounts/stats?group_by=favorite_color")
+            .reply(&api(init_db()))
+            .await;
+
+        assert_eq!(response.status(), 404);
+    }
+
+    #[test]
+    fn deduped() {
+        let mut deduper = Deduper::<Account>::new(&DedupeOpts::new::<Account>(&["owner".into()], Policy::PreferNonEmpty).unwrap().unwrap());
+
+        for (record, account) in init_db_accounts().into_iter().enumerate() {
+            deduper.push(account, Origin { file: "accounts.csv".into(), record: record as u64 + 1 });
+        }
+
+        let (accounts, duplicates) = deduper.finish();
+
+        assert_eq!(accounts, [account("003", "Tom", Some("USD")), account("001", "Rachel", None)]);
+        assert_eq!(duplicates[0].key, "owner=tom");
+        assert!(DedupeOpts::new::<Account>(&["email".into()], Policy::FirstWins).is_err());
+    }
+}
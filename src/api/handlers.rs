@@ -1,16 +1,29 @@
-use warp::{Rejection, Reply, hyper::StatusCode, reply::with_status};
+use std::{io::{self, Write}, sync::Arc};
+use futures::stream;
+use tokio::sync::mpsc;
+use warp::{Rejection, Reply, http::header, hyper::{Body, StatusCode, body::Bytes}, reply::{Response, with_status}};
 use serde::{Serialize, Deserialize};
 
-use crate::{api::models::{ListOptions, Db}, sorting::SortDirection};
+use crate::{api::models::{BatchMode, BatchOptions, ExportOptions, ListOptions, StatsOptions, Db}, sorting::{SortDirection, SortSpec}};
+use crate::io::{self as input, Format, Reject};
+use crate::api::cursor::Cursor;
+use crate::api::storage::{Record, RecordId, Storage};
 use crate::person::Person;
-use crate::sorting::FieldsOrd;
+use crate::resource::Resource;
+use crate::filtering::Predicate;
+use crate::serialization::date_format::{self, DateFormats};
+use crate::stats::{self, Stats};
+use crate::validation::{FieldError, Rules};
 
 
 const MAX_PER_PAGE: usize = 50;
 
+/// Records read from the storage at a time by an export (see `select_records`)
+const EXPORT_CHUNK: usize = 1000;
+
 
 #[derive(Serialize, Deserialize)]
-pub struct ResultSet {
+pub struct ResultSet<T = Person> {
     curr: usize,
     next: Option<usize>,
     prev: Option<usize>,
@@ -18,14 +31,145 @@ pub struct ResultSet {
     last: usize,
     pub count: usize,
     pub length: usize,
-    pub results: Vec<Person>,
+    pub results: Vec<Record<T>>,
+    /// Token for the `cursor` query param that lists the records after this
+    /// page, if there are any (see `cursor::Cursor`)
+    #[serde(default)]
+    pub next_cursor: Option<String>,
+}
+
+
+/// The path and query-string of a listing request, used to link to its other pages
+#[derive(Debug, Clone, Default)]
+pub struct PageUrl {
+    pub path: String,
+    pub query: String,
+}
+
+
+impl PageUrl {
+
+    /// This URL with its `page` and `cursor` parameters replaced by `param=value`
+    fn with(&self, param: &str, value: &str) -> String {
+        let mut params: Vec<(String, String)> = serde_urlencoded::from_str(&self.query).unwrap_or_default();
+
+        params.retain(|(key, _)| key != "page" && key != "cursor");
+        params.push((param.into(), value.into()));
+
+        format!("{}?{}", self.path, serde_urlencoded::to_string(&params).unwrap_or_default())
+    }
+}
+
+
+/// What happened to a single row of a `POST /records/batch` body
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum BatchRow {
+    Accepted {
+        record: u64,
+        /// Absent when the batch wasn't stored
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<RecordId>,
+    },
+    Rejected {
+        record: Option<u64>,
+        line: Option<u64>,
+        byte: Option<u64>,
+        field: Option<String>,
+        reason: String,
+        raw: Option<String>,
+    },
+}
+
+
+impl From<Reject> for BatchRow {
+    fn from(reject: Reject) -> Self {
+        BatchRow::Rejected {
+            record: reject.record,
+            line: reject.line,
+            byte: reject.byte,
+            field: reject.field,
+            reason: reject.reason,
+            raw: reject.raw,
+        }
+    }
+}
+
+
+/// Response of `POST /records/batch`: a row for every record in the body, in order
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchSummary {
+    /// Whether the accepted rows were stored
+    pub committed: bool,
+    pub accepted: usize,
+    pub rejected: usize,
+    pub rows: Vec<BatchRow>,
+}
+
+
+/// Rejection for a write that the storage backend failed to persist
+#[derive(Debug)]
+pub struct StorageFailure {
+    pub reason: String,
+}
+impl warp::reject::Reject for StorageFailure {}
+
+
+/// Rejection for an id that doesn't match any stored record
+#[derive(Debug)]
+pub struct RecordNotFound {
+    pub id: RecordId,
 }
+impl warp::reject::Reject for RecordNotFound {}
+
+
+/// Rejection for a `PATCH` body that can't be merged into a record
+#[derive(Debug)]
+pub struct InvalidPatch {
+    pub reason: String,
+}
+impl warp::reject::Reject for InvalidPatch {}
+
+
+/// Rejection for a record that breaks any of the validation `Rules`
+#[derive(Debug)]
+pub struct InvalidRecord {
+    pub errors: Vec<FieldError>,
+}
+impl warp::reject::Reject for InvalidRecord {}
+
+
+/// Rejection for a `cursor` query param that can't be used to list records
+#[derive(Debug)]
+pub struct InvalidCursor {
+    pub reason: String,
+}
+impl warp::reject::Reject for InvalidCursor {}
+
+
+/// Rejection for a `page` query param that isn't a page, i.e., 0
+#[derive(Debug)]
+pub struct InvalidPage {
+    pub page: usize,
+}
+impl warp::reject::Reject for InvalidPage {}
+
+
+/// Rejection for an `Accept` header that doesn't include any of the formats we can respond with
+#[derive(Debug)]
+pub struct NotAcceptable {
+    pub accept: String,
+}
+impl warp::reject::Reject for NotAcceptable {}
 
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct APIError {
     pub reason: String,
     pub context: String,
+    /// The fields of a record that failed validation, if any
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
 }
 
 
@@ -35,6 +179,7 @@ pub fn not_found(context: String) -> Result<impl Reply, Rejection>
     let err = APIError {
         reason: status.canonical_reason().unwrap().to_string(),
         context,
+        errors: vec![],
     };
 
     Ok(with_status(warp::reply::json(&err), status))
@@ -48,6 +193,7 @@ pub async fn handle_rejection(err: Rejection) -> Result<impl Reply, Rejection>
     let reason: String;
     let code;
     let mut context = String::from("(None)");
+    let mut errors = vec![];
 
     if err.is_not_found() {
         code = StatusCode::NOT_FOUND;
@@ -55,95 +201,656 @@ pub async fn handle_rejection(err: Rejection) -> Result<impl Reply, Rejection>
     } else if let Some(filters::InvalidCSV) = err.find() {
         code = StatusCode::BAD_REQUEST;
         reason = "Unable to parse CSV body".into();
+    } else if let Some(RecordNotFound { id }) = err.find() {
+        code = StatusCode::NOT_FOUND;
+        reason = "Record not found".into();
+        context = format!("No record with id {}", id);
+    } else if let Some(NotAcceptable { accept }) = err.find() {
+        code = StatusCode::NOT_ACCEPTABLE;
+        reason = "Not acceptable".into();
+        context = format!("Unable to respond with \"{}\". Available formats: {}", accept,
+            [Format::Json, Format::Csv, Format::Ndjson].iter().map(Format::media_type).collect::<Vec<&str>>().join(", "));
+    } else if let Some(InvalidRecord { errors: invalid }) = err.find() {
+        code = StatusCode::UNPROCESSABLE_ENTITY;
+        reason = "Invalid record".into();
+        context = invalid.iter().map(FieldError::to_string).collect::<Vec<String>>().join("; ");
+        errors = invalid.clone();
+    } else if let Some(InvalidCursor { reason: invalid }) = err.find() {
+        code = StatusCode::BAD_REQUEST;
+        reason = "Invalid cursor".into();
+        context = invalid.clone();
+    } else if let Some(InvalidPage { page }) = err.find() {
+        code = StatusCode::BAD_REQUEST;
+        reason = "Invalid page".into();
+        context = format!("Pages start at 1, got {}", page);
+    } else if let Some(InvalidPatch { reason: invalid }) = err.find() {
+        code = StatusCode::BAD_REQUEST;
+        reason = "Unable to apply patch".into();
+        context = invalid.clone();
+    } else if let Some(filters::InvalidJSON { reason: invalid }) = err.find() {
+        code = StatusCode::BAD_REQUEST;
+        reason = "Unable to parse JSON body".into();
+        context = invalid.clone();
+    } else if let Some(filters::InvalidDateFormat { reason: invalid }) = err.find() {
+        code = StatusCode::BAD_REQUEST;
+        reason = "Invalid date format".into();
+        context = invalid.clone();
+    } else if let Some(filters::InvalidPredicate { reason: invalid }) = err.find() {
+        code = StatusCode::BAD_REQUEST;
+        reason = "Invalid filter".into();
+        context = invalid.clone();
+    } else if let Some(filters::InvalidGroupBy { reason: invalid }) = err.find() {
+        code = StatusCode::BAD_REQUEST;
+        reason = "Invalid group_by".into();
+        context = invalid.clone();
+    } else if let Some(filters::InvalidSortDirection { reason: invalid }) = err.find() {
+        code = StatusCode::BAD_REQUEST;
+        reason = "Invalid sort direction".into();
+        context = invalid.clone();
+    } else if let Some(filters::InvalidSortModifier { reason: invalid }) = err.find() {
+        code = StatusCode::BAD_REQUEST;
+        reason = "Invalid sort modifier".into();
+        context = invalid.clone();
     } else if let Some(filters::InvalidFilterField { available}) = err.find() {
         code = StatusCode::NOT_FOUND;
         reason = "Field not found".into();
         context = format!("Available fields: {}", available.join(", "));
+    } else if let Some(StorageFailure { reason: failure }) = err.find() {
+        code = StatusCode::INTERNAL_SERVER_ERROR;
+        reason = "Unable to store record".into();
+        context = failure.clone();
+    } else if let Some(e) = err.find::<warp::reject::PayloadTooLarge>() {
+        code = StatusCode::PAYLOAD_TOO_LARGE;
+        reason = "Payload too large".into();
+        context = e.to_string();
+    } else if let Some(e) = err.find::<warp::reject::UnsupportedMediaType>() {
+        code = StatusCode::UNSUPPORTED_MEDIA_TYPE;
+        reason = "Unsupported media type".into();
+        context = e.to_string();
+    } else if let Some(e) = err.find::<warp::reject::InvalidQuery>() {
+        code = StatusCode::BAD_REQUEST;
+        reason = "Invalid query".into();
+        context = e.to_string();
+    } else if let Some(e) = err.find::<warp::reject::MethodNotAllowed>() {
+        code = StatusCode::METHOD_NOT_ALLOWED;
+        reason = "Method not allowed".into();
+        context = e.to_string();
     } else {
-        // reason = "Unknown".into();
-        reason = format!("{:?}", err);
+        // The rejection is only logged: its details aren't for the client.
+        log::error!("Unhandled rejection: {:?}", err);
+        reason = "Internal server error".into();
         code = StatusCode::INTERNAL_SERVER_ERROR;
     }
 
     let json = warp::reply::json(&APIError {
-        reason, context
+        reason, context, errors
     });
 
     Ok(with_status(json, code))
 }
 
 
-fn pagination(opts: &ListOptions) -> (usize, usize, usize)
+/// The page, offset and number of records per page selected by `opts`, with
+/// `per-page` clamped to `1..=MAX_PER_PAGE`. Pages start at 1.
+fn pagination(opts: &ListOptions) -> Result<(usize, usize, usize), Rejection>
 {
-    let per_page = opts.per_page.unwrap_or(MAX_PER_PAGE);
+    let per_page = opts.per_page.unwrap_or(MAX_PER_PAGE).clamp(1, MAX_PER_PAGE);
     let page = opts.page.unwrap_or(1);
-    let idx = page - 1;
+    let idx = page.checked_sub(1).ok_or_else(|| warp::reject::custom(InvalidPage { page }))?;
 
-    (page, idx * per_page, per_page)
+    Ok((page, idx.saturating_mul(per_page), per_page))
 }
 
 
-pub fn resultset(people: Vec<Person>, opts: ListOptions) -> ResultSet
+/// The page of the records in `storage` that match `predicates`, in the order
+/// of `sort`, selected by `opts`: either the `per-page` records after
+/// `opts.cursor`, or those of page `opts.page`. Only the records up to the end
+/// of the page are read from the storage's indexes (see `Storage::scan`), but
+/// with any `predicates`, the `count` of the records that match them is read
+/// from every stored record (see `Storage::count`), on every call.
+/// With a cursor, `curr` is the page that its records were on when it was made.
+pub fn resultset<T: Resource>(storage: &dyn Storage<T>, predicates: &[Predicate], opts: &ListOptions, sort: &SortSpec)
+    -> Result<ResultSet<T>, Rejection>
 {
-    let (curr, offset, limit) = pagination(&opts);
+    let (_, offset, limit) = pagination(opts)?;
 
-    let count = people.len();
+    let cursor = match &opts.cursor {
+        Some(token) => Some(Cursor::decode(token, sort)
+            .map_err(|reason| warp::reject::custom(InvalidCursor { reason }))?),
+        None => None,
+    };
+    let (offset, skip) = match &cursor {
+        Some(cursor) => (cursor.offset(), 0),
+        None => (offset, offset),
+    };
 
-    let subset: Vec<Person> = people
-        .into_iter()
-        .skip(offset)
-        .take(limit)
+    let mut subset: Vec<Record<T>> = storage.scan(sort, cursor.as_ref())
+        .filter(|(_, person)| person.matches_all(predicates))
+        .skip(skip)
+        .take(limit + 1)
+        .map(|(id, person)| Record { id, person: person.clone() })
         .collect();
 
+    let more = subset.len() > limit;
+    subset.truncate(limit);
+
+    let count = match predicates.len() {
+        0 => storage.len(),
+        _ => storage.count(&|person| person.matches_all(predicates)),
+    };
+    let curr = (offset / limit).saturating_add(1);
+
+    let next_cursor = match subset.last() {
+        Some(record) if more => Some(Cursor::after(record, sort, offset.saturating_add(subset.len())).encode()),
+        _ => None,
+    };
+
     let last = count / limit + match count % limit { 0 => 0, _ => 1 };
     let next = if curr < last { Some(curr + 1) } else { None };
     let prev = if curr > 1 { Some(curr - 1) } else { None };
 
-    ResultSet {
+    Ok(ResultSet {
         curr,
         first: 1,
         last,
         next,
         prev,
-        count: count,
+        count,
         length: subset.len(),
         results: subset,
+        next_cursor,
+    })
+}
+
+
+/// Serializes `value` as JSON, rendering dates with `formats`.
+fn json_reply<T: Serialize>(value: &T, formats: &DateFormats) -> warp::reply::Json
+{
+    date_format::with_formats(formats, || warp::reply::json(value))
+}
+
+
+/// Parses the `sort` and `direction` query params shared by the listing endpoints
+fn sort_params<T: Resource>(sort: &Option<String>, direction: Option<SortDirection>) -> Result<SortSpec, Rejection>
+{
+    let direction = direction.unwrap_or(SortDirection::Asc);
+
+    match sort {
+        Some(sort) => crate::api::filters::sort_fields::<T>(sort, direction),
+        None => Ok(SortSpec::default()),
+    }
+}
+
+
+/// Every stored record that matches all of the `predicates`, sorted by `sort`
+/// the same way the CLI sorts its output. The ids of the records are sorted
+/// once, under a single read lock, and the records themselves are then copied
+/// `EXPORT_CHUNK` at a time, with the read lock only held while a chunk is copied:
+/// exporting holds at most a chunk of records in memory, however many are stored,
+/// and doesn't keep writers waiting. Since it isn't a snapshot, records deleted
+/// or no longer matching by the time their chunk is copied are left out, and
+/// records inserted during an export aren't in it.
+///
+/// With `with_columns`, the extra columns of the records (see `io::extra_columns`)
+/// are read along with their ids, if `T` keeps any, and are returned with them.
+/// A record given any others since is copied without them, so that it lines up
+/// with the header rather than being left out.
+///
+/// Reading blocks the current thread, so it's only meant for `spawn_blocking`.
+fn select_records<T: Resource>(db: Db<T>, predicates: Vec<Predicate>, sort: SortSpec, with_columns: bool)
+    -> (Vec<String>, impl Iterator<Item = Record<T>>)
+{
+    let keeps_extra = with_columns && T::keeps_extra();
+    let mut columns = vec![];
+    let ids: Vec<RecordId> = futures::executor::block_on(db.read())
+        .scan(&sort, None)
+        .filter(|(_, person)| person.matches_all(&predicates))
+        .inspect(|(_, person)| if keeps_extra { input::add_extra_columns(&mut columns, *person) })
+        .map(|(id, _)| id)
+        .collect();
+    let mut chunks = ids.into_iter();
+    let known = columns.clone();
+
+    let records = std::iter::from_fn(move || {
+        let ids: Vec<RecordId> = chunks.by_ref().take(EXPORT_CHUNK).collect();
+
+        if ids.is_empty() {
+            return None
+        }
+
+        let storage = futures::executor::block_on(db.read());
+
+        Some(ids.into_iter()
+            .filter_map(|id| storage.get(id))
+            .filter(|record| record.person.matches_all(&predicates))
+            .map(|mut record| {
+                if let Some(extra) = record.person.extra_mut().filter(|_| with_columns) {
+                    extra.retain(|column| known.iter().any(|c| c == column));
+                }
+                record
+            })
+            .collect::<Vec<_>>())
+    });
+
+    (columns, records.flatten())
+}
+
+
+/// The `Link` header of a page of `set`: its first, previous, next and last
+/// pages. A page listed with a cursor links to the next one with `next_cursor`,
+/// and has no previous page.
+fn link_header<T>(set: &ResultSet<T>, url: &PageUrl, by_cursor: bool) -> String
+{
+    let page = |page: usize| url.with("page", &page.to_string());
+    let mut links = vec![(page(set.first), "first")];
+
+    if by_cursor {
+        links.extend(set.next_cursor.as_ref().map(|cursor| (url.with("cursor", cursor), "next")));
+    } else {
+        links.extend(set.prev.map(|prev| (page(prev), "prev")));
+        links.extend(set.next.map(|next| (page(next), "next")));
     }
+
+    links.push((page(set.last.max(set.first)), "last"));
+
+    links.iter()
+        .map(|(url, rel)| format!("<{}>; rel=\"{}\"", url, rel))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+
+/// Replies with the page `set`, selected by `opts`, in `format`.
+///
+/// JSON replies with the whole `ResultSet`. CSV (written like the CLI's output,
+/// without ids) and NDJSON only contain the records, so the pagination metadata
+/// is also sent as `Link` and `X-Total-Count` headers, whatever the format.
+fn page_reply<T: Resource>(format: Format, set: ResultSet<T>, opts: &ListOptions, url: &PageUrl, formats: &DateFormats)
+    -> Response
+{
+    let separator = opts.separator.unwrap_or(',');
+    let has_header = opts.header.unwrap_or(false);
+
+    let mut response = match format {
+        Format::Json => json_reply(&set, formats).into_response(),
+        _ => {
+            let body = date_format::with_formats(formats, || {
+                let mut body = vec![];
+                let result = match format {
+                    Format::Csv => input::write_output::<_, T>(
+                        &mut body,
+                        separator,
+                        has_header,
+                        &input::extra_columns(set.results.iter().map(|record| &record.person)),
+                        set.results.iter().map(|record| &record.person),
+                    ),
+                    _ => input::write_ndjson_output(&mut body, set.results.iter()),
+                };
+
+                // Writing to a `Vec` only fails if a record can't be serialized.
+                result.map(|_| body).expect("records are serializable")
+            });
+
+            let mut response = Response::new(Body::from(body));
+            response.headers_mut().insert(header::CONTENT_TYPE, header::HeaderValue::from_static(format.media_type()));
+            response
+        },
+    };
+
+    let headers = response.headers_mut();
+
+    headers.insert("x-total-count", header::HeaderValue::from(set.count));
+
+    if let Ok(link) = header::HeaderValue::from_str(&link_header(&set, url, opts.cursor.is_some())) {
+        headers.insert(header::LINK, link);
+    }
+
+    response
 }
 
 
-pub async fn list_records(opts: ListOptions, db: Db)
+pub async fn list_records<T: Resource>(format: Format, opts: ListOptions, url: PageUrl, predicates: Vec<Predicate>, formats: DateFormats, db: Db<T>)
     -> Result<impl Reply, Rejection>
 {
-    let mut people = db.lock().await.clone();
-    
-    // people.sort_by(|a, b| a.cmp_order_by_fields(b, &vec![]));
-    people.sort();
+    let sort = sort_params::<T>(&opts.sort, opts.direction)?;
+    let set = resultset(db.read().await.as_ref(), &predicates, &opts, &sort)?;
 
-    Ok(warp::reply::json(&resultset(people, opts)))
+    Ok(page_reply(format, set, &opts, &url, &formats))
 }
 
 
-pub async fn list_records_sorted_by_field(field: String, opts: ListOptions, db: Db)
+pub async fn list_records_sorted_by_field<T: Resource>(field: String, format: Format, opts: ListOptions, url: PageUrl, predicates: Vec<Predicate>, formats: DateFormats, db: Db<T>)
     -> Result<impl Reply, Rejection>
 {
-    let fields = vec![
-        (field.as_str(), opts.direction.unwrap_or(SortDirection::Asc))
-    ];
+    let sort = crate::api::filters::sort_fields::<T>(&field, opts.direction.unwrap_or(SortDirection::Asc))?;
+    let set = resultset(db.read().await.as_ref(), &predicates, &opts, &sort)?;
 
-    let mut people: Vec<Person> = db.lock().await.clone();
+    Ok(page_reply(format, set, &opts, &url, &formats))
+}
+
+
+/// A `Write` that sends what's written to it down a channel in chunks, to be
+/// streamed as a response body. Writes fail with `BrokenPipe` once the
+/// receiver (i.e., the client) has gone away.
+struct ChannelWriter {
+    tx: mpsc::Sender<io::Result<Bytes>>,
+    buf: Vec<u8>,
+}
+
+
+impl ChannelWriter {
+
+    /// Size of each chunk sent down the channel, i.e., 64 KiB
+    const CHUNK_SIZE: usize = 1 << 16;
+
+    fn new(tx: mpsc::Sender<io::Result<Bytes>>) -> Self {
+        Self { tx, buf: Vec::with_capacity(Self::CHUNK_SIZE) }
+    }
+
+    fn send(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(())
+        }
+
+        let chunk = Bytes::from(std::mem::replace(&mut self.buf, Vec::with_capacity(Self::CHUNK_SIZE)));
+
+        self.tx.blocking_send(Ok(chunk))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "client went away"))
+    }
+}
+
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+
+        if self.buf.len() >= Self::CHUNK_SIZE {
+            self.send()?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send()
+    }
+}
+
+
+/// Streams every matching record, sorted, as a chunked response in the
+/// format asked for by the `Accept` header. Records are written with the
+/// same functions as the CLI's output, so they don't include their ids. CSV
+/// has the extra columns of every matching record (see `select_records`).
+pub async fn export_records<T: Resource>(format: Format, opts: ExportOptions, predicates: Vec<Predicate>, formats: DateFormats, db: Db<T>)
+    -> Result<impl Reply, Rejection>
+{
+    let sort = sort_params::<T>(&opts.sort, opts.direction)?;
+    let (tx, rx) = mpsc::channel::<io::Result<Bytes>>(16);
+
+    tokio::task::spawn_blocking(move || date_format::with_formats(&formats, || {
+        let mut writer = ChannelWriter::new(tx);
+        let (columns, records) = select_records(db, predicates, sort, format == Format::Csv);
+        let people = records.map(|record| record.person);
+
+        let result = match format {
+            Format::Csv => input::write_output::<_, T>(
+                &mut writer,
+                opts.separator.unwrap_or(','),
+                opts.header.unwrap_or(false),
+                &columns,
+                people,
+            ),
+            Format::Json => input::write_json_output(&mut writer, people),
+            Format::Ndjson => input::write_ndjson_output(&mut writer, people),
+        };
+
+        if let Err(e) = result.and_then(|_| writer.flush()) {
+            log::warn!("Export failed: {}", e);
+        }
+    }));
+
+    let body = Body::wrap_stream(stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    }));
+
+    let mut response = Response::new(body);
+    response.headers_mut().insert(header::CONTENT_TYPE, header::HeaderValue::from_static(format.media_type()));
+
+    Ok(response)
+}
+
+
+/// Counts the records that match all of the `predicates` in each of the groups
+/// of `opts.group_by`, and replies in `format`.
+pub async fn record_stats<T: Resource>(format: Format, opts: StatsOptions, predicates: Vec<Predicate>, db: Db<T>)
+    -> Result<impl Reply, Rejection>
+{
+    let group_by = crate::api::filters::group_by_fields::<T>(opts.group_by.as_deref().unwrap_or_default())?;
+    let mut stats = Stats::new(group_by.clone());
+
+    for (_, person) in db.read().await.iter() {
+        if person.matches_all(&predicates) {
+            stats.push(person);
+        }
+    }
+
+    let groups = stats.finish();
+    let mut body = vec![];
+    let result = match format {
+        Format::Csv => stats::write_stats(
+            &mut body,
+            opts.separator.unwrap_or(','),
+            opts.header.unwrap_or(false),
+            &group_by,
+            groups,
+        ),
+        Format::Json => input::write_json_output(&mut body, groups),
+        Format::Ndjson => input::write_ndjson_output(&mut body, groups),
+    };
+
+    // Writing to a `Vec` only fails if a group can't be serialized.
+    let body = result.map(|_| body).expect("groups are serializable");
+    let mut response = Response::new(Body::from(body));
+    response.headers_mut().insert(header::CONTENT_TYPE, header::HeaderValue::from_static(format.media_type()));
+
+    Ok(response)
+}
 
-    people.sort_by(|a, b| a.cmp_order_by_fields(b, &fields));
 
-    Ok(warp::reply::json(&resultset(people, opts)))
+fn storage_failure(e: io::Error) -> Rejection
+{
+    warp::reject::custom(StorageFailure { reason: e.to_string() })
+}
+
+
+pub async fn get_record<T: Resource>(id: RecordId, formats: DateFormats, db: Db<T>)
+    -> Result<impl Reply, Rejection>
+{
+    let record = db.read().await.get(id);
+
+    match record {
+        Some(record) => Ok(json_reply(&record, &formats)),
+        None => Err(warp::reject::custom(RecordNotFound { id })),
+    }
+}
+
+
+/// Rejects `person` if it breaks any of the `rules`
+fn validate<T: Resource>(person: &T, rules: &Rules) -> Result<(), Rejection>
+{
+    person.validate(rules).map_err(|errors| warp::reject::custom(InvalidRecord { errors }))
 }
 
 
-pub async fn create_record(record: Person, db: Db)
+pub async fn create_record<T: Resource>(record: T, formats: DateFormats, rules: Arc<Rules>, db: Db<T>)
     -> Result<impl Reply, Rejection>
 {
-    let mut people = db.lock().await;
-    people.push(record);
+    validate(&record, &rules)?;
 
-    Ok(StatusCode::CREATED)
-}
\ No newline at end of file
+    let record = db.write().await.insert(record).map_err(storage_failure)?;
+
+    Ok(with_status(json_reply(&record, &formats), StatusCode::CREATED))
+}
+
+
+/// Reads every row of a batch body and stores the accepted ones: all of them in
+/// `BatchMode::BestEffort`, or only if there are no rejected rows in
+/// `BatchMode::AllOrNothing`. Responds with a `BatchSummary`: `201` if anything was
+/// stored, `422` if there were rejected rows and nothing was stored, and `200` otherwise.
+pub async fn create_records_batch<T: Resource>(
+    format: Format,
+    opts: BatchOptions,
+    body: warp::hyper::body::Bytes,
+    formats: DateFormats,
+    rules: Arc<Rules>,
+    db: Db<T>,
+) -> Result<impl Reply, Rejection>
+{
+    let (separator, header) = (opts.separator.unwrap_or(','), opts.header.unwrap_or(false));
+
+    // Parsing a body of up to `MAX_BATCH_BYTES` would hold up the executor, as writing an export would.
+    let rows = tokio::task::spawn_blocking(move || date_format::with_formats(&formats, || {
+        let mut rows = vec![];
+
+        input::read_people::<T>(&body[..], format, separator, header, &rules, "body", |row| { rows.push(row); true });
+        rows
+    }))
+        .await
+        .map_err(|e| warp::reject::custom(StorageFailure { reason: format!("Unable to read batch: {}", e) }))?;
+
+    let all_or_nothing = opts.mode.unwrap_or_default() == BatchMode::AllOrNothing;
+    let rejected = rows.iter().filter(|row| row.is_err()).count();
+    let committed = !(all_or_nothing && rejected > 0);
+
+    let mut summary = BatchSummary { committed, accepted: 0, rejected, rows: vec![] };
+    let mut people = vec![];
+
+    for (idx, row) in rows.into_iter().enumerate() {
+        match row {
+            Ok(person) => {
+                people.push((summary.rows.len(), idx as u64 + 1, person));
+                summary.rows.push(BatchRow::Accepted { record: idx as u64 + 1, id: None });
+            },
+            Err(reject) => summary.rows.push(BatchRow::from(reject)),
+        }
+    }
+
+    if !committed || people.is_empty() {
+        summary.accepted = people.len();
+    } else {
+        let (positions, records): (Vec<(usize, u64)>, Vec<T>) = people.into_iter()
+            .map(|(pos, record, person)| ((pos, record), person))
+            .unzip();
+
+        // Every accepted row is stored with a single write, so that a store that logs to disk
+        // syncs once per batch, and a failed write leaves none of them stored.
+        match db.write().await.insert_all(records) {
+            Ok(stored) => {
+                summary.accepted = stored.len();
+
+                for ((pos, record), stored) in positions.into_iter().zip(stored) {
+                    summary.rows[pos] = BatchRow::Accepted { record, id: Some(stored.id) };
+                }
+            },
+            Err(e) if all_or_nothing => return Err(storage_failure(e)),
+            Err(e) => {
+                summary.rejected += positions.len();
+
+                for (pos, record) in positions {
+                    summary.rows[pos] = BatchRow::Rejected {
+                        record: Some(record),
+                        line: None,
+                        byte: None,
+                        field: None,
+                        reason: e.to_string(),
+                        raw: None,
+                    };
+                }
+            },
+        }
+    }
+
+    let status = match (summary.accepted, summary.rejected) {
+        (accepted, _) if committed && accepted > 0 => StatusCode::CREATED,
+        (_, rejected) if rejected > 0 => StatusCode::UNPROCESSABLE_ENTITY,
+        _ => StatusCode::OK,
+    };
+
+    Ok(with_status(warp::reply::json(&summary), status))
+}
+
+
+pub async fn update_record<T: Resource>(id: RecordId, person: T, formats: DateFormats, rules: Arc<Rules>, db: Db<T>)
+    -> Result<impl Reply, Rejection>
+{
+    validate(&person, &rules)?;
+
+    let updated = db.write().await.update(id, person).map_err(storage_failure)?;
+
+    match updated {
+        Some(record) => Ok(json_reply(&record, &formats)),
+        None => Err(warp::reject::custom(RecordNotFound { id })),
+    }
+}
+
+
+/// Merges the keys of a JSON object into the stored record. Keys that aren't
+/// fields of the record are ignored. Dates in the patch may be in any of the
+/// request's input formats.
+pub async fn patch_record<T: Resource>(id: RecordId, patch: serde_json::Value, formats: DateFormats, rules: Arc<Rules>, db: Db<T>)
+    -> Result<impl Reply, Rejection>
+{
+    let invalid = |reason: String| warp::reject::custom(InvalidPatch { reason });
+
+    // Held from reading the stored record to writing the merged one, so no other write is lost.
+    let mut storage = db.write().await;
+
+    let stored = match storage.get(id) {
+        Some(record) => record.person,
+        None => return Err(warp::reject::custom(RecordNotFound { id })),
+    };
+
+    let patch = match patch {
+        serde_json::Value::Object(patch) => patch,
+        _ => return Err(invalid("Body must be a JSON object".into())),
+    };
+
+    // The stored record is written in the first input format, so that it reads back unchanged.
+    let round_trip = DateFormats {
+        output: formats.input.first().cloned().unwrap_or_else(|| formats.output.clone()),
+        ..formats.clone()
+    };
+
+    let mut merged = date_format::with_formats(&round_trip, || serde_json::to_value(stored))
+        .map_err(|e| invalid(e.to_string()))?;
+
+    if let Some(fields) = merged.as_object_mut() {
+        fields.extend(patch);
+    }
+
+    let mut person: T = date_format::with_formats(&round_trip, || serde_json::from_value(merged))
+        .map_err(|e| invalid(e.to_string()))?;
+
+    if let Some(extra) = person.extra_mut() {
+        extra.remove("id");
+    }
+
+    validate(&person, &rules)?;
+
+    match storage.update(id, person).map_err(storage_failure)? {
+        Some(record) => Ok(json_reply(&record, &formats)),
+        None => Err(warp::reject::custom(RecordNotFound { id })),
+    }
+}
+
+
+pub async fn delete_record<T: Resource>(id: RecordId, db: Db<T>)
+    -> Result<impl Reply, Rejection>
+{
+    let deleted = db.write().await.delete(id).map_err(storage_failure)?;
+
+    match deleted {
+        Some(_) => Ok(StatusCode::NO_CONTENT),
+        None => Err(warp::reject::custom(RecordNotFound { id })),
+    }
+}
@@ -1,3 +1,5 @@
+pub mod cursor;
 pub mod filters;
 pub mod handlers;
 pub mod models;
+pub mod storage;
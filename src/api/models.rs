@@ -1,65 +1,204 @@
 use serde::{Deserialize};
-use std::{path::PathBuf, sync::Arc};
-use tokio::sync::Mutex;
-use crate::{person::Person, sorting::SortDirection};
-use crate::io::read_input_files;
+use std::{io, path::PathBuf, sync::Arc};
+use tokio::sync::RwLock;
+use crate::sorting::SortDirection;
+use crate::api::storage::{Storage, MemoryStorage, AppendLogStorage};
+use crate::person::Person;
+use crate::resource::Resource;
+use futures::StreamExt;
+use crate::io::{read_input_stream, read_input_stream_with_origins, valid_people, InputOpts};
+use crate::dedupe::{Deduper, DedupeOpts, DuplicateWriter};
 
 
-/// In-memory "database"
-pub type Db = Arc<Mutex<Vec<Person>>>;
+/// The "database": a shared handle to whichever `Storage` backend was opened.
+/// Any number of readers hold the lock at once, each seeing a consistent
+/// snapshot of the records; a writer waits only for the readers already in,
+/// and readers that arrive after it wait for its write. Holds people unless
+/// it's the `Db` of another `Resource` (see `filters::resource`).
+pub type Db<T = Person> = Arc<RwLock<Box<dyn Storage<T>>>>;
 
 
 pub struct DbOpts {
     files: Vec<PathBuf>,
-    input_field_separator: char,
-    input_field_separator_mappings: Vec<char>,
-    input_has_header: bool,
-    input_has_header_mappings: Vec<bool>,
+    input: InputOpts,
+    database: Option<PathBuf>,
+    /// Merge duplicates across the input files before seeding
+    dedupe: Option<DedupeOpts>,
+    /// Where to report the merged duplicates, if anywhere
+    duplicates: Option<PathBuf>,
 }
 
 
 impl DbOpts {
     pub fn new(
         files: Vec<PathBuf>,
-        input_field_separator: char,
-        input_field_separator_mappings: Vec<char>,
-        input_has_header: bool,
-        input_has_header_mappings: Vec<bool>,
+        input: InputOpts,
+        database: Option<PathBuf>,
+        dedupe: Option<DedupeOpts>,
+        duplicates: Option<PathBuf>,
     ) -> Self
     {
         Self {
             files,
-            input_field_separator,
-            input_field_separator_mappings,
-            input_has_header,
-            input_has_header_mappings,
+            input,
+            database,
+            dedupe,
+            duplicates,
         }
     }
 }
 
 
-#[derive(Debug, Deserialize)]
+/// Query-string of the listing endpoints. A `cursor` (the `next_cursor` of a
+/// previous page) takes precedence over `page`. `separator` and `header` only
+/// apply to a CSV response (see `filters::accept_format`).
+#[derive(Debug, Default, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct ListOptions {
+    pub sort: Option<String>,
     pub direction: Option<SortDirection>,
     pub page: Option<usize>,
     pub per_page: Option<usize>,
+    pub cursor: Option<String>,
+    pub separator: Option<char>,
+    pub header: Option<bool>,
 }
 
 
-pub async fn init_db (
+/// Query-string of `GET /records/export`. `sort` and `direction` work as in
+/// `ListOptions`; `separator` and `header` only apply to CSV.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ExportOptions {
+    pub sort: Option<String>,
+    pub direction: Option<SortDirection>,
+    pub separator: Option<char>,
+    pub header: Option<bool>,
+}
+
+
+/// Query-string of `GET /records/stats`: what to group records by (see
+/// `stats::GroupBy`), e.g., `favorite_color,dob:age`. `separator` and `header`
+/// only apply to CSV.
+#[derive(Debug, Default, Deserialize)]
+pub struct StatsOptions {
+    #[serde(alias = "group-by")]
+    pub group_by: Option<String>,
+    pub separator: Option<char>,
+    pub header: Option<bool>,
+}
+
+
+/// How `POST /records/batch` treats a body with rejected rows
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BatchMode {
+    /// Store nothing unless every row is accepted
+    AllOrNothing,
+    /// Store every accepted row
+    #[default]
+    BestEffort,
+}
+
+
+/// Query-string of `POST /records/batch`. `header` and `separator` only apply to CSV.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct BatchOptions {
+    pub header: Option<bool>,
+    pub separator: Option<char>,
+    pub mode: Option<BatchMode>,
+}
+
+
+/// Wraps a `Storage` backend in a `Db` handle
+pub fn new_db<T: Resource>(storage: impl Storage<T> + 'static) -> Db<T> {
+    Arc::new(RwLock::new(Box::new(storage)))
+}
+
+
+/// Streams the people (or any other `Resource`) from the input files into
+/// `storage`, one at a time. With `dedupe`, they're all read and merged first.
+async fn seed<T: Resource>(storage: &mut dyn Storage<T>, opts: &DbOpts) -> io::Result<()> {
+    let dedupe = match &opts.dedupe {
+        Some(dedupe) => dedupe,
+        None => {
+            let mut people = valid_people(read_input_stream(&opts.files, &opts.input)?);
+
+            while let Some(person) = people.next().await {
+                storage.insert(person)?;
+            }
+
+            return Ok(())
+        }
+    };
+
+    let mut deduper = Deduper::<T>::new(dedupe);
+    let mut stream = read_input_stream_with_origins(&opts.files, &opts.input)?;
+
+    while let Some((origin, result)) = stream.next().await {
+        match result {
+            Ok(person) => { deduper.push(person, origin); },
+            Err(reject) => log::warn!("Rejected {}", reject),
+        }
+    }
+
+    let (people, duplicates) = deduper.finish();
+    let mut report = match &opts.duplicates {
+        Some(path) => Some(DuplicateWriter::create(path)?),
+        None => None,
+    };
+
+    for duplicate in duplicates.iter() {
+        log::info!("Merged {}", duplicate);
+
+        if let Some(writer) = report.as_mut() {
+            writer.write(duplicate)?;
+        }
+    }
+
+    if let Some(mut writer) = report {
+        writer.flush()?;
+    }
+
+    log::info!("Merged {} duplicate(s)", duplicates.len());
+
+    for person in people {
+        storage.insert(person)?;
+    }
+
+    Ok(())
+}
+
+
+/// Opens the database of people (or any other `Resource`) described by `opts`.
+///
+/// Without a `database` path, the records are read from the input files into
+/// memory, as before. With one, the records are kept in an append-only log at
+/// that path; the input files are only used to seed the log when it is first
+/// created, and are ignored once it exists.
+pub async fn init_db<T: Resource> (
     opts: DbOpts
-) -> Db {
-    let mut people: Vec<Person> = vec![];
-
-    let _ = read_input_files(
-        &opts.files,
-        opts.input_field_separator,
-        &opts.input_field_separator_mappings,
-        opts.input_has_header,
-        &opts.input_has_header_mappings,
-        &mut people
-    ).await;
-
-    Arc::new(Mutex::new(people))
+) -> io::Result<Db<T>> {
+
+    let path = match &opts.database {
+        None => {
+            let mut storage = MemoryStorage::default();
+            seed(&mut storage, &opts).await?;
+            return Ok(new_db(storage))
+        },
+        Some(path) => path,
+    };
+
+    let exists = path.exists();
+    let mut storage = AppendLogStorage::open(path)?;
+
+    if exists {
+        log::info!("Opened {} record(s) from {}", storage.len(), path.display());
+    } else {
+        seed(&mut storage, &opts).await?;
+        log::info!("Seeded {} with {} record(s)", path.display(), storage.len());
+    }
+
+    Ok(new_db(storage))
 }
@@ -0,0 +1,191 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+
+use crate::api::cursor::Cursor;
+use crate::person::Person;
+use crate::resource::Resource;
+use crate::serialization::date_format::{self, DateFormats};
+use crate::sorting::SortSpec;
+use super::{Storage, MemoryStorage, Record, RecordId};
+
+
+/// A single line of the log
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum Entry<T> {
+    Insert { id: RecordId, record: T },
+    Update { id: RecordId, record: T },
+    Delete { id: RecordId },
+}
+
+
+/// Durable storage: every write is appended to a newline-delimited JSON log
+/// on disk before it is applied to an in-memory copy. Opening an existing
+/// log replays it, so records survive a restart. Dates are always logged in
+/// the default `DateFormats`, whatever the process has been configured with.
+#[derive(Debug)]
+pub struct AppendLogStorage<T = Person> {
+    path: PathBuf,
+    file: File,
+    memory: MemoryStorage<T>,
+}
+
+
+impl<T: Resource> AppendLogStorage<T> {
+
+    /// Opens the log at `path`, creating it if it doesn't exist, and
+    /// replays any entries it already contains.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut memory = MemoryStorage::default();
+
+        if path.exists() {
+            let reader = BufReader::new(File::open(path)?);
+
+            for (idx, line) in reader.lines().enumerate() {
+                let line = line?;
+
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                let entry = date_format::with_formats(&DateFormats::default(), || {
+                    serde_json::from_str::<Entry<T>>(&line)
+                });
+
+                match entry {
+                    Ok(entry) => { Self::apply(&mut memory, entry)?; },
+                    Err(e) => log::warn!(
+                        "{}:{}: skipping unreadable log entry: {}", path.display(), idx + 1, e),
+                }
+            }
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            memory,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn apply(memory: &mut MemoryStorage<T>, entry: Entry<T>) -> io::Result<Option<Record<T>>> {
+        match entry {
+            Entry::Insert { id, record } => Ok(Some(memory.insert_with_id(id, record))),
+            Entry::Update { id, record } => memory.update(id, record),
+            Entry::Delete { id } => memory.delete(id),
+        }
+    }
+
+    fn append(&mut self, entry: &Entry<T>) -> io::Result<()> {
+        self.append_all(std::slice::from_ref(entry))
+    }
+
+    /// Appends every one of `entries` with a single write and sync. If any of
+    /// them can't be written, the log is cut back to where it was, so that none
+    /// of them are replayed.
+    fn append_all(&mut self, entries: &[Entry<T>]) -> io::Result<()> {
+        let mut lines = date_format::with_formats(&DateFormats::default(), || {
+            entries.iter()
+                .map(serde_json::to_string)
+                .collect::<Result<Vec<String>, _>>()
+        })?
+        .join("\n");
+        lines.push('\n');
+
+        let len = self.file.metadata()?.len();
+        let result = self.file.write_all(lines.as_bytes())
+            .and_then(|_| self.file.flush())
+            .and_then(|_| self.file.sync_data());
+
+        if result.is_err() {
+            if let Err(e) = self.file.set_len(len).and_then(|_| self.file.sync_data()) {
+                log::error!("{}: unable to cut back a partial write: {}", self.path.display(), e);
+            }
+        }
+
+        result
+    }
+}
+
+
+impl<T: Resource> Storage<T> for AppendLogStorage<T> {
+
+    fn records(&self) -> Vec<Record<T>> {
+        self.memory.records()
+    }
+
+    fn get(&self, id: RecordId) -> Option<Record<T>> {
+        self.memory.get(id)
+    }
+
+    fn insert(&mut self, person: T) -> io::Result<Record<T>> {
+        let id = self.memory.next_id();
+
+        self.append(&Entry::Insert { id, record: person.clone() })?;
+        Ok(self.memory.insert_with_id(id, person))
+    }
+
+    /// Logs every insert with a single write and sync, instead of one each
+    fn insert_all(&mut self, people: Vec<T>) -> io::Result<Vec<Record<T>>> {
+        let people: Vec<(RecordId, T)> = (self.memory.next_id()..).zip(people).collect();
+        let entries: Vec<Entry<T>> = people.iter()
+            .map(|(id, person)| Entry::Insert { id: *id, record: person.clone() })
+            .collect();
+
+        self.append_all(&entries)?;
+
+        Ok(people.into_iter()
+            .map(|(id, person)| self.memory.insert_with_id(id, person))
+            .collect())
+    }
+
+    fn update(&mut self, id: RecordId, person: T) -> io::Result<Option<Record<T>>> {
+        if self.memory.get(id).is_none() {
+            return Ok(None)
+        }
+
+        let entry = Entry::Update { id, record: person };
+
+        self.append(&entry)?;
+        Self::apply(&mut self.memory, entry)
+    }
+
+    fn delete(&mut self, id: RecordId) -> io::Result<Option<Record<T>>> {
+        if self.memory.get(id).is_none() {
+            return Ok(None)
+        }
+
+        let entry = Entry::Delete { id };
+
+        self.append(&entry)?;
+        Self::apply(&mut self.memory, entry)
+    }
+
+    fn scan<'a>(&'a self, sort: &'a SortSpec, after: Option<&'a Cursor<T>>)
+        -> Box<dyn Iterator<Item = (RecordId, &'a T)> + 'a>
+    {
+        self.memory.scan(sort, after)
+    }
+
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (RecordId, &'a T)> + 'a> {
+        self.memory.iter()
+    }
+
+    fn count(&self, matches: &dyn Fn(&T) -> bool) -> usize {
+        self.memory.count(matches)
+    }
+
+    fn len(&self) -> usize {
+        self.memory.len()
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::ops::Bound;
+use chrono::NaiveDate;
+
+use crate::api::cursor::Cursor;
+use crate::filtering::FieldValue;
+use crate::resource::Resource;
+use crate::sorting::{Modifiers, SortDirection, SortKey, SortSpec, cmp_by_keys};
+use super::RecordId;
+
+
+/// The value of a field as kept in an index, ordered the same way as
+/// `FieldsOrd::cmp_field` orders it ascending
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Key {
+    Str(String),
+    Date(Option<NaiveDate>),
+}
+
+
+impl Key {
+    fn of<T: Resource>(person: &T, field: &str) -> Option<Self> {
+        match person.field_value(field)? {
+            FieldValue::Str(value) => Some(Key::Str(value.to_string())),
+            FieldValue::Date(value) => Some(Key::Date(value)),
+        }
+    }
+}
+
+
+/// Whether the record stored under `id` comes after the `cursor`, if there is one
+fn is_after<T: Resource>(cursor: Option<&Cursor<T>>, id: RecordId, person: &T) -> bool {
+    match cursor {
+        Some(cursor) => cursor.cmp_record(id, person).is_gt(),
+        None => true,
+    }
+}
+
+
+/// A sorted index of the stored records for every field of a `Resource`, kept
+/// up to date on every write so that listing a page doesn't need to sort.
+#[derive(Debug, Default)]
+pub(super) struct Indexes {
+    fields: BTreeMap<&'static str, BTreeSet<(Key, RecordId)>>,
+}
+
+
+impl Indexes {
+
+    pub fn insert<T: Resource>(&mut self, id: RecordId, person: &T) {
+        for &field in T::struct_fields() {
+            if let Some(key) = Key::of(person, field) {
+                self.fields.entry(field).or_default().insert((key, id));
+            }
+        }
+    }
+
+    pub fn remove<T: Resource>(&mut self, id: RecordId, person: &T) {
+        for &field in T::struct_fields() {
+            if let (Some(index), Some(key)) = (self.fields.get_mut(field), Key::of(person, field)) {
+                index.remove(&(key, id));
+            }
+        }
+    }
+
+    /// The ids of the records in the order of `field`, grouped by value,
+    /// starting at the group of `from` (see `Scan`)
+    fn ids<'a, T: Resource>(&'a self, field: &str, direction: SortDirection, from: Option<&T>)
+        -> Option<Box<dyn Iterator<Item = &'a (Key, RecordId)> + 'a>>
+    {
+        let index = self.fields.get(field)?;
+        let from = match from {
+            Some(person) => Some(Key::of(person, field)?),
+            None => None,
+        };
+
+        Some(match (direction, from) {
+            (SortDirection::Asc, Some(key)) => Box::new(index.range((Bound::Included((key, RecordId::MIN)), Bound::Unbounded))),
+            (SortDirection::Desc, Some(key)) => Box::new(index.range((Bound::Unbounded, Bound::Included((key, RecordId::MAX)))).rev()),
+            (SortDirection::Asc, None) => Box::new(index.iter()),
+            (SortDirection::Desc, None) => Box::new(index.iter().rev()),
+        })
+    }
+
+    /// The records of `people` in listing order (see `Storage::scan`)
+    pub fn scan<'a, T: Resource>(
+        &'a self,
+        people: &'a BTreeMap<RecordId, T>,
+        sort: &'a SortSpec,
+        after: Option<&'a Cursor<T>>,
+    ) -> Box<dyn Iterator<Item = (RecordId, &'a T)> + 'a>
+    {
+        // Without a sort, records are listed by every field in turn, e.g., in `Person`'s own order.
+        let keys = match sort.is_empty() {
+            true => SortSpec::all::<T>().keys().to_vec(),
+            false => sort.keys().to_vec(),
+        };
+        let first = &keys[0];
+        let indexed = match first.modifiers == Modifiers::default() {
+            true => self.ids(&first.field, first.direction, after.map(Cursor::person)),
+            false => None,
+        };
+
+        match indexed {
+            Some(ids) => Box::new(Scan {
+                ids: ids.peekable(),
+                people,
+                rest: keys[1..].to_vec(),
+                after,
+                group: VecDeque::new(),
+            }),
+            // Not an indexed field, i.e., an extra column, or one with modifiers (the indexes
+            // are kept in `Binary` order, nulls first), so every record is sorted
+            None => {
+                let mut records: Vec<(RecordId, &T)> = people.iter()
+                    .map(|(&id, person)| (id, person))
+                    .filter(|&(id, person)| is_after(after, id, person))
+                    .collect();
+
+                records.sort_by(|(a_id, a), (b_id, b)| cmp_by_keys(&keys, *a, *b).then(a_id.cmp(b_id)));
+                Box::new(records.into_iter())
+            },
+        }
+    }
+}
+
+
+/// Iterator over the records in listing order. The index of the first sort
+/// field is scanned one group of equal values at a time; only a group is
+/// sorted by the rest of the fields (and by id), which is what keeps listing
+/// a page proportional to the size of the page rather than of the dataset.
+struct Scan<'a, T> {
+    ids: std::iter::Peekable<Box<dyn Iterator<Item = &'a (Key, RecordId)> + 'a>>,
+    people: &'a BTreeMap<RecordId, T>,
+    rest: Vec<SortKey>,
+    after: Option<&'a Cursor<T>>,
+    group: VecDeque<(RecordId, &'a T)>,
+}
+
+
+impl<'a, T: Resource> Scan<'a, T> {
+
+    /// Reads the next group of records with the same value of the first field
+    fn next_group(&mut self) -> bool {
+        let (key, id) = match self.ids.next() {
+            Some(entry) => entry,
+            None => return false,
+        };
+        let mut ids = vec![*id];
+
+        while let Some((_, id)) = self.ids.next_if(|(next, _)| next == key) {
+            ids.push(*id);
+        }
+
+        let people = self.people;
+        let after = self.after;
+        let mut group: Vec<(RecordId, &T)> = ids.into_iter()
+            .filter_map(|id| people.get(&id).map(|person| (id, person)))
+            .filter(|&(id, person)| is_after(after, id, person))
+            .collect();
+        let rest = &self.rest;
+
+        group.sort_by(|(a_id, a), (b_id, b)| cmp_by_keys(rest, *a, *b).then(a_id.cmp(b_id)));
+        self.group.extend(group);
+
+        true
+    }
+}
+
+
+impl<'a, T: Resource> Iterator for Scan<'a, T> {
+    type Item = (RecordId, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.group.is_empty() {
+            if !self.next_group() {
+                return None
+            }
+        }
+
+        self.group.pop_front()
+    }
+}
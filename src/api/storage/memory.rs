@@ -0,0 +1,117 @@
+use std::{collections::BTreeMap, io};
+
+use crate::api::cursor::Cursor;
+use crate::person::Person;
+use crate::resource::Resource;
+use crate::sorting::SortSpec;
+use super::{Storage, Record, RecordId, index::Indexes};
+
+
+/// The original, volatile storage: the records are lost when the process exits.
+/// Ids are handed out sequentially, so iterating the map by id yields the
+/// records in insertion order. Every field is also indexed, to list the
+/// records in any order without sorting them all.
+#[derive(Debug)]
+pub struct MemoryStorage<T = Person> {
+    people: BTreeMap<RecordId, T>,
+    indexes: Indexes,
+    next_id: RecordId,
+}
+
+
+impl<T> Default for MemoryStorage<T> {
+    fn default() -> Self {
+        Self { people: BTreeMap::new(), indexes: Indexes::default(), next_id: 0 }
+    }
+}
+
+
+impl<T: Resource> MemoryStorage<T> {
+
+    /// Stores `person` under a specific id. Used when replaying a log, where
+    /// the ids have already been assigned.
+    pub(super) fn insert_with_id(&mut self, id: RecordId, person: T) -> Record<T> {
+        self.next_id = self.next_id.max(id + 1);
+
+        if let Some(replaced) = self.people.insert(id, person.clone()) {
+            self.indexes.remove(id, &replaced);
+        }
+        self.indexes.insert(id, &person);
+
+        Record { id, person }
+    }
+
+    /// The id the next inserted record will receive
+    pub(super) fn next_id(&self) -> RecordId {
+        self.next_id
+    }
+}
+
+
+impl<T: Resource> From<Vec<T>> for MemoryStorage<T> {
+    fn from(people: Vec<T>) -> Self {
+        let mut storage = Self::default();
+
+        for person in people {
+            let id = storage.next_id;
+            storage.insert_with_id(id, person);
+        }
+        storage
+    }
+}
+
+
+impl<T: Resource> Storage<T> for MemoryStorage<T> {
+
+    fn records(&self) -> Vec<Record<T>> {
+        self.people.iter()
+            .map(|(&id, person)| Record { id, person: person.clone() })
+            .collect()
+    }
+
+    fn get(&self, id: RecordId) -> Option<Record<T>> {
+        self.people.get(&id)
+            .map(|person| Record { id, person: person.clone() })
+    }
+
+    fn insert(&mut self, person: T) -> io::Result<Record<T>> {
+        let id = self.next_id;
+        Ok(self.insert_with_id(id, person))
+    }
+
+    fn update(&mut self, id: RecordId, person: T) -> io::Result<Option<Record<T>>> {
+        let indexes = &mut self.indexes;
+
+        Ok(self.people.get_mut(&id).map(|stored| {
+            indexes.remove(id, stored);
+            indexes.insert(id, &person);
+            *stored = person.clone();
+            Record { id, person }
+        }))
+    }
+
+    fn delete(&mut self, id: RecordId) -> io::Result<Option<Record<T>>> {
+        Ok(self.people.remove(&id).map(|person| {
+            self.indexes.remove(id, &person);
+            Record { id, person }
+        }))
+    }
+
+    fn scan<'a>(&'a self, sort: &'a SortSpec, after: Option<&'a Cursor<T>>)
+        -> Box<dyn Iterator<Item = (RecordId, &'a T)> + 'a>
+    {
+        self.indexes.scan(&self.people, sort, after)
+    }
+
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (RecordId, &'a T)> + 'a> {
+        Box::new(self.people.iter().map(|(&id, person)| (id, person)))
+    }
+
+    fn count(&self, matches: &dyn Fn(&T) -> bool) -> usize {
+        self.people.values().filter(|person| matches(person)).count()
+    }
+
+    fn len(&self) -> usize {
+        self.people.len()
+    }
+}
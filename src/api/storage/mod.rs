@@ -0,0 +1,98 @@
+use std::io;
+use serde::{Serialize, Deserialize};
+
+use crate::api::cursor::Cursor;
+use crate::person::Person;
+use crate::resource::Resource;
+use crate::sorting::SortSpec;
+
+mod index;
+mod memory;
+mod append_log;
+
+pub use memory::MemoryStorage;
+pub use append_log::AppendLogStorage;
+
+
+/// Stable identifier assigned to every stored record
+pub type RecordId = u64;
+
+
+/// A `Person` (or any other `Resource`) as stored by the API, along with the id
+/// it was assigned. The person's fields are flattened, so in JSON a `Record`
+/// looks like a `Person` with an additional `"id"` key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Record<T = Person> {
+    pub id: RecordId,
+
+    #[serde(flatten)]
+    pub person: T,
+}
+
+
+/// Trait implemented by the backends that can hold the records served by the API.
+/// `api::models::Db` wraps a boxed `Storage`, so handlers never need to know
+/// whether the records live only in memory or are also persisted to disk.
+pub trait Storage<T: Resource = Person>: Send + Sync {
+
+    /// Returns a copy of every stored record, in insertion order
+    fn records(&self) -> Vec<Record<T>>;
+
+    /// Returns a copy of the record with the given id, if there is one
+    fn get(&self, id: RecordId) -> Option<Record<T>>;
+
+    /// Stores a single person under a newly assigned id
+    fn insert(&mut self, person: T) -> io::Result<Record<T>>;
+
+    /// Stores every one of `people` under newly assigned ids, in order, or none
+    /// of them if any can't be stored. By default, they're inserted one at a
+    /// time, and the ones already inserted are deleted again on an error.
+    fn insert_all(&mut self, people: Vec<T>) -> io::Result<Vec<Record<T>>> {
+        let mut inserted = Vec::with_capacity(people.len());
+
+        for person in people {
+            match self.insert(person) {
+                Ok(record) => inserted.push(record),
+                Err(e) => {
+                    for record in inserted {
+                        if let Err(e) = self.delete(record.id) {
+                            log::error!("Unable to roll back record {}: {}", record.id, e);
+                        }
+                    }
+                    return Err(e)
+                }
+            }
+        }
+
+        Ok(inserted)
+    }
+
+    /// Replaces the person stored under `id`. Returns `None` if there is no such record.
+    fn update(&mut self, id: RecordId, person: T) -> io::Result<Option<Record<T>>>;
+
+    /// Removes the record stored under `id`, returning it if it existed
+    fn delete(&mut self, id: RecordId) -> io::Result<Option<Record<T>>>;
+
+    /// Lazily yields the ids and people of the stored records in listing order:
+    /// sorted by `sort` (by every field in turn when it's empty, like `Person`'s
+    /// `Ord`), with ties broken by id. With a cursor, only the records after it are yielded.
+    fn scan<'a>(&'a self, sort: &'a SortSpec, after: Option<&'a Cursor<T>>)
+        -> Box<dyn Iterator<Item = (RecordId, &'a T)> + 'a>;
+
+    /// Lazily yields the ids and people of the stored records in no particular
+    /// order, for when the order doesn't matter, i.e., without sorting them
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (RecordId, &'a T)> + 'a>;
+
+    /// Number of stored records for which `matches` is true
+    fn count(&self, matches: &dyn Fn(&T) -> bool) -> usize;
+
+    /// Number of stored records
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+
+mod tests;
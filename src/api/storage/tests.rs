@@ -0,0 +1,213 @@
+#![allow(dead_code)]
+
+use super::*;
+
+
+fn create_people() -> Vec<Person> {
+    vec![
+        Person::new("Brennan", "Tom", "tjb1982@gmail.com", "red", "8/19/1982"),
+        Person::new("Fuller", "Rachel", "", "green", "8/10/1970"),
+    ]
+}
+
+
+#[cfg(test)]
+mod memory {
+    use super::*;
+
+    #[test]
+    fn insert_appends_in_order() {
+        let mut storage = MemoryStorage::default();
+
+        assert!(storage.is_empty());
+
+        for person in create_people() {
+            storage.insert(person).unwrap();
+        }
+
+        assert_eq!(storage.len(), 2);
+
+        let people: Vec<Person> = storage.records().into_iter()
+            .map(|record| record.person)
+            .collect();
+
+        assert_eq!(people, create_people());
+    }
+
+    #[test]
+    fn ids_are_not_reused() {
+        let mut storage = MemoryStorage::from(create_people());
+
+        assert_eq!(storage.delete(1).unwrap().map(|record| record.id), Some(1));
+        assert_eq!(storage.delete(1).unwrap(), None);
+        assert_eq!(storage.insert(create_people().remove(1)).unwrap().id, 2);
+    }
+
+    #[test]
+    fn iter_yields_every_record() {
+        let storage = MemoryStorage::from(create_people());
+        let mut ids: Vec<RecordId> = storage.iter().map(|(id, _)| id).collect();
+
+        ids.sort_unstable();
+        assert_eq!(ids, vec![0, 1]);
+    }
+
+    #[test]
+    fn update_missing_record() {
+        let mut storage = MemoryStorage::default();
+
+        assert_eq!(storage.update(0, create_people().remove(0)).unwrap(), None);
+        assert!(storage.is_empty());
+    }
+}
+
+
+#[cfg(test)]
+mod index {
+    use crate::sorting::{FieldsOrd, SortSpec, SortDirection::{self, *}};
+    use super::*;
+
+    fn init_storage() -> MemoryStorage {
+        let mut storage = MemoryStorage::from(vec![
+            Person::new("Brennan", "Tom", "tjb1982@gmail.com", "red", "8/19/1982"),
+            Person::new("Fuller", "Rachel", "", "green", "8/10/1970"),
+            Person::new("Brennan", "Chester", "", "green", ""),
+            Person::new("Brennan", "June", "", "", ""),
+            Person::new("Abbott", "June", "", "blue", "1/2/1990"),
+            Person::new("Fuller", "Rachel", "", "green", "8/10/1970"),
+        ]);
+
+        storage.update(1, Person::new("Fuller", "Zed", "", "green", "8/10/1970")).unwrap();
+        storage.delete(4).unwrap();
+        storage.insert(Person::new("Brennan", "Amy", "", "red", "3/3/2001")).unwrap();
+        storage
+    }
+
+    /// The listing order, the slow way
+    fn sorted(storage: &MemoryStorage, sort: &SortSpec) -> Vec<Record> {
+        let fields = sort.fields();
+        let mut records = storage.records();
+
+        records.sort_by(|a, b| a.person.cmp_order_by_fields(&b.person, &fields).then(a.id.cmp(&b.id)));
+        records
+    }
+
+    fn sort(fields: &[(&str, SortDirection)]) -> SortSpec {
+        SortSpec::parse::<Person>(fields, false).unwrap()
+    }
+
+    #[test]
+    fn scan_matches_sort() {
+        let storage = init_storage();
+
+        for fields in [
+            vec![],
+            vec![("last_name", Asc)],
+            vec![("last_name", Desc)],
+            vec![("favorite_color", Desc), ("first_name", Asc)],
+            vec![("dob", Asc), ("last_name", Desc)],
+            vec![("first_name", Desc), ("dob", Desc)],
+        ].iter() {
+            let sort = sort(fields);
+            let scanned: Vec<Record> = storage.scan(&sort, None)
+                .map(|(id, person)| Record { id, person: person.clone() })
+                .collect();
+
+            assert_eq!(scanned, sorted(&storage, &sort), "sorted by {:?}", fields);
+        }
+    }
+
+    #[test]
+    fn scan_after_cursor() {
+        let storage = init_storage();
+
+        for fields in [vec![], vec![("last_name", Desc), ("first_name", Asc)], vec![("favorite_color", Asc)]].iter() {
+            let sort = sort(fields);
+            let all = sorted(&storage, &sort);
+
+            for (idx, record) in all.iter().enumerate() {
+                let cursor = Cursor::after(record, &sort, idx + 1);
+                let scanned: Vec<Record> = storage.scan(&sort, Some(&cursor))
+                    .map(|(id, person)| Record { id, person: person.clone() })
+                    .collect();
+
+                assert_eq!(scanned, all[idx + 1..].to_vec(), "after {:?} sorted by {:?}", record, fields);
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod append_log {
+    use std::{fs, io::Write, path::PathBuf};
+    use super::*;
+
+    fn log_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir()
+            .join(format!("homework-{}-{}.log", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn records_survive_reopen() {
+        let path = log_path("reopen");
+        let expected = {
+            let mut storage = AppendLogStorage::open(&path).unwrap();
+            for person in create_people() {
+                storage.insert(person).unwrap();
+            }
+            storage.update(0, Person::new("Brennan", "Tom", "", "blue", "")).unwrap();
+            storage.delete(1).unwrap();
+            storage.records()
+        };
+
+        let mut storage = AppendLogStorage::open(&path).unwrap();
+
+        assert_eq!(storage.records(), expected);
+        assert_eq!(storage.insert(create_people().remove(1)).unwrap().id, 2);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn insert_all_survives_reopen() {
+        let path = log_path("insert-all");
+        let expected = {
+            let mut storage = AppendLogStorage::open(&path).unwrap();
+            storage.insert(create_people().remove(1)).unwrap();
+
+            let ids: Vec<RecordId> = storage.insert_all(create_people()).unwrap().into_iter()
+                .map(|record| record.id)
+                .collect();
+
+            assert_eq!(ids, vec![1, 2]);
+            storage.records()
+        };
+
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 3);
+
+        let storage = AppendLogStorage::open(&path).unwrap();
+
+        assert_eq!(storage.records(), expected);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn unreadable_entries_are_skipped() {
+        let path = log_path("unreadable");
+
+        {
+            let mut storage = AppendLogStorage::open(&path).unwrap();
+            storage.insert(create_people().remove(0)).unwrap();
+        }
+
+        fs::OpenOptions::new().append(true).open(&path).unwrap()
+            .write_all(b"{\"op\": \"insert\", \"rec\n").unwrap();
+
+        let storage: AppendLogStorage = AppendLogStorage::open(&path).unwrap();
+
+        assert_eq!(storage.len(), 1);
+        fs::remove_file(&path).unwrap();
+    }
+}
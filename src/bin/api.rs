@@ -1,4 +1,4 @@
-use std::{net::{SocketAddr, ToSocketAddrs}, path::PathBuf};
+use std::{net::{SocketAddr, ToSocketAddrs}, path::PathBuf, sync::Arc};
 use std::env;
 use log::LevelFilter;
 use warp::Filter;
@@ -6,6 +6,11 @@ use clap::{AppSettings, Clap};
 
 use homework::api::filters;
 use homework::api::models;
+use homework::io::InputOpts;
+use homework::validation::Rules;
+use homework::dedupe::{DedupeOpts, Policy};
+use homework::person::{Person, extra};
+use homework::serialization::date_format::{self, DateFormat, DateFormats};
 
 
 #[derive(Clap, Clone)]
@@ -25,22 +30,77 @@ pub struct Opts {
     #[clap(short = 'e', long = "input-has-header-mapping", about = "Map `--input-has-header` to each respective input file (any remaining unmapped files fall back to `--input-has-header`)")]
     input_has_header_mappings: Vec<bool>,
 
-    #[clap(name = "FILE", parse(from_os_str), about = "CSV input files...", required = true)]
+    #[clap(long = "input-date-format", about = "Format of the dates in the inputs, e.g. `%Y-%m-%d` or `iso` (may be repeated; each is tried in order; defaults to `%-m/%-d/%Y` then `iso`)")]
+    input_date_formats: Vec<DateFormat>,
+
+    #[clap(long, about = "Format of the dates in the output (defaults to `%-m/%-d/%Y`)")]
+    output_date_format: Option<DateFormat>,
+
+    #[clap(long, about = "Keep the columns of the records that aren't fields (e.g. `department`), and return them after the fields. They can be sorted and filtered by as strings")]
+    extra_columns: bool,
+
+    #[clap(name = "FILE", parse(from_os_str), about = "CSV input files...", required_unless_present = "database")]
     files: Vec<PathBuf>,
 
+    #[clap(short = 'b', long, parse(from_os_str), about = "Persist records to an append-only log at this path (input files only seed a new log)")]
+    database: Option<PathBuf>,
+
+    #[clap(long, about = "Validate records with the built-in rules: a last and first name, a well-formed email and no future dob")]
+    validate: bool,
+
+    #[clap(long, parse(from_os_str), about = "Validate records with the rules in this JSON file (rules left out keep their built-in values)")]
+    validation: Option<PathBuf>,
+
+    #[clap(long = "dedupe-by", about = "Merge records in the input files whose values of these fields match, ignoring case and surrounding whitespace, e.g. `email` or `last_name,first_name` (may be repeated)")]
+    dedupe_by: Vec<String>,
+
+    #[clap(long, default_value = "first-wins", about = "How `--dedupe-by` merges a record into an earlier one: first-wins, last-wins or prefer-non-empty")]
+    on_duplicate: Policy,
+
+    #[clap(long, parse(from_os_str), about = "Write a CSV report of the records merged by `--dedupe-by` to this file")]
+    duplicates: Option<PathBuf>,
+
     #[clap(short = 'H', long = "hostname", about = "Hostname to serve this API on")]
     hostname: String,
 }
 
 
+impl Opts {
+    fn rules(&self) -> Rules {
+        // Records are only validated when asked to.
+        match (&self.validation, self.validate) {
+            (Some(path), _) => Rules::from_file::<Person>(path)
+                .unwrap_or_else(|e| panic!("Unable to read validation rules from {}: {}", path.display(), e)),
+            (None, true) => Rules::default(),
+            (None, false) => Rules::none(),
+        }
+    }
+
+    fn dedupe(&self) -> Option<DedupeOpts> {
+        DedupeOpts::new::<Person>(&self.dedupe_by, self.on_duplicate)
+            .unwrap_or_else(|e| panic!("Invalid `--dedupe-by`: {}", e))
+    }
+}
+
+
 impl From<Opts> for models::DbOpts {
     fn from(opts: Opts) -> Self {
+        let rules = opts.rules();
+        let dedupe = opts.dedupe();
+
         Self::new(
             opts.files,
-            opts.input_field_separator,
-            opts.input_field_separator_mappings.clone(),
-            opts.input_has_header,
-            opts.input_has_header_mappings.clone(),
+            InputOpts {
+                field_separator: opts.input_field_separator,
+                field_separator_mappings: opts.input_field_separator_mappings,
+                has_header: opts.input_has_header,
+                has_header_mappings: opts.input_has_header_mappings,
+                rules: Arc::new(rules),
+                ..InputOpts::default()
+            },
+            opts.database,
+            dedupe,
+            opts.duplicates,
         )
     }
 }
@@ -49,8 +109,9 @@ impl From<Opts> for models::DbOpts {
 fn cors() -> warp::cors::Builder {
     warp::cors()
         .allow_any_origin()
-        .allow_headers(vec!["content-type"])
-        .allow_methods(vec!["POST"])
+        .allow_headers(vec!["content-type", "accept"])
+        .expose_headers(vec!["link", "x-total-count"])
+        .allow_methods(vec!["POST", "PUT", "PATCH", "DELETE"])
 }
 
 
@@ -59,14 +120,19 @@ async fn main() {
     homework::log::set_console_logger(LevelFilter::Info).unwrap();
 
     let opts: Opts = Opts::parse();
-    let db = models::init_db(opts.clone().into()).await;
+    date_format::set_default(DateFormats::new(opts.input_date_formats.clone(), opts.output_date_format.clone()));
+    extra::set_default(opts.extra_columns);
+
+    let db = models::init_db(opts.clone().into())
+        .await
+        .expect("Unable to open database");
 
     let addr: Vec<SocketAddr> = opts.hostname
         .to_socket_addrs()
         .expect(format!("Bad hostname: {}", opts.hostname).as_str())
         .collect();
 
-    let api = warp::options().map(warp::reply).or(filters::records(db))
+    let api = warp::options().map(warp::reply).or(filters::records(db, opts.rules()))
         .with(cors())
         .with(warp::log("homework"));
 
@@ -1,66 +1,248 @@
-use std::{collections::VecDeque, path::PathBuf};
-use clap::{AppSettings, Clap};
+use std::{collections::VecDeque, fs::File, path::PathBuf, sync::Arc};
+use clap::{AppSettings, Clap, ErrorKind};
 use log::LevelFilter;
 
 use tokio::io;
+use futures::StreamExt;
 
-use homework::person::Person;
-use homework::serialization::StructFieldDeserialize;
-use homework::sorting::{SortDirection, FieldsOrd};
+use homework::person::{Person, extra};
+use homework::serialization::{StructFieldDeserialize, date_format::{self, DateFormat, DateFormats}};
+use homework::sorting::{SortDirection, SortSpec};
+use homework::filtering::{self, FieldsFilter, Predicate};
+use homework::validation::Rules;
+use homework::dedupe::{self, Deduper, DedupeOpts, DuplicateWriter, Policy};
+use homework::sets::{self, Difference};
+use homework::stats::{self, Stats};
 use homework::io::*;
+use homework::io::external_sort::ExternalSorter;
 use homework::log::*;
 
 
 #[derive(Clap)]
 #[clap(version = env!("CARGO_PKG_VERSION"), author = env!("CARGO_PKG_AUTHORS"))]
 #[clap(setting = AppSettings::ColoredHelp)]
+#[clap(setting = AppSettings::SubcommandsNegateReqs)]
 struct Opts {
 
     #[clap(short, long, about = "Display all available sorting fields and exit")]
     available_fields: bool,
 
-    #[clap(short = 'S', long, default_value = ",")]
+    #[clap(short = 'I', long, default_value = "csv", global = true, about = "Format of the inputs: csv, json or ndjson")]
+    input_format: Format,
+
+    #[clap(short = 'i', long = "input-format-mapping", global = true, about = "Map `--input-format` to each respective input file (any remaining unmapped files fall back to `--input-format`)")]
+    input_format_mappings: Vec<Format>,
+
+    #[clap(short = 'O', long, default_value = "csv", global = true, about = "Format of the output: csv, json or ndjson")]
+    output_format: Format,
+
+    #[clap(short = 'S', long, default_value = ",", global = true)]
     input_field_separator: char,
 
-    #[clap(short = 's', long = "input-field-separator-mapping", about = "Map `--field-separator` to each respective input file (any remaining unmapped files fall back to `--field-separator`)")]
+    #[clap(short = 's', long = "input-field-separator-mapping", global = true, about = "Map `--field-separator` to each respective input file (any remaining unmapped files fall back to `--field-separator`)")]
     input_field_separator_mappings: Vec<char>,
 
-    #[clap(short, long, about = "Separator to use for the output")]
+    #[clap(short, long, global = true, about = "Separator to use for the output")]
     output_field_separator: Option<char>,
 
-    #[clap(short = 'E', long, about = "Inputs contain header row")]
+    #[clap(short = 'E', long, global = true, about = "Inputs contain header row")]
     input_has_header: bool,
 
-    #[clap(short = 'e', long = "input-has-header-mapping", about = "Map `--input-has-header` to each respective input file (any remaining unmapped files fall back to `--input-has-header`)")]
+    #[clap(short = 'e', long = "input-has-header-mapping", global = true, about = "Map `--input-has-header` to each respective input file (any remaining unmapped files fall back to `--input-has-header`)")]
     input_has_header_mappings: Vec<bool>,
 
-    #[clap(short = 't', long, about = "Output will contain a header row")]
+    #[clap(long = "input-date-format", global = true, about = "Format of the dates in the inputs, e.g. `%Y-%m-%d` or `iso` (may be repeated; each is tried in order; defaults to `%-m/%-d/%Y` then `iso`)")]
+    input_date_formats: Vec<DateFormat>,
+
+    #[clap(long, global = true, about = "Format of the dates in the output (defaults to `%-m/%-d/%Y`)")]
+    output_date_format: Option<DateFormat>,
+
+    #[clap(long, global = true, about = "Keep the columns of the inputs that aren't fields (e.g. `department`), and write them after the fields. They can be sorted, filtered and grouped by as strings")]
+    extra_columns: bool,
+
+    #[clap(short = 't', long, global = true, about = "Output will contain a header row")]
     output_has_header: bool,
 
-    #[clap(short = 'f', long = "field", about = "Sequential list of fields to sort the output")]
+    #[clap(short = 'f', long = "field", global = true, about = "Sequential list of fields to sort the output, each optionally followed by a collation: `binary` (the default), `nocase`, `noaccent` or `natural`, and by where to put missing values: `nulls=first` or `nulls=last`, e.g. `last_name:nocase` or `dob:nulls=last`")]
     fields: Vec<String>,
 
-    #[clap(short = 'D', long, default_value = "asc")]
+    #[clap(short = 'D', long, default_value = "asc", global = true)]
     sort_direction: SortDirection,
 
-    #[clap(short = 'd', long = "sort-direction-mapping", about = "Sequential list of sort directions, mapped to each provided `--field` (any remaining unmapped `--fields` fall back to `--sort-direction`)")]
+    #[clap(short = 'd', long = "sort-direction-mapping", global = true, about = "Sequential list of sort directions, mapped to each provided `--field` (any remaining unmapped `--fields` fall back to `--sort-direction`)")]
     sort_direction_mappings: Vec<SortDirection>,
 
+    #[clap(short = 'w', long = "where", global = true, about = "Only output records matching this predicate, e.g. `favorite_color=red`, `last_name^=Bre`, `dob>=1980-01-01`, `email~=@example.com$` (may be repeated)")]
+    predicates: Vec<Predicate>,
+
+    #[clap(short = 'c', long, about = "Sort externally, holding at most this many records in memory at once and spilling sorted chunks of this size to temporary files")]
+    chunk_size: Option<usize>,
+
+    #[clap(long, parse(from_os_str), about = "Directory for the chunks spilled by `--chunk-size` (defaults to the system's temporary directory)")]
+    temp_dir: Option<PathBuf>,
+
+    #[clap(long, global = true, about = "Validate records with the built-in rules: a last and first name, a well-formed email and no future dob")]
+    validate: bool,
+
+    #[clap(long, parse(from_os_str), global = true, about = "Validate records with the rules in this JSON file (rules left out keep their built-in values)")]
+    validation: Option<PathBuf>,
+
+    #[clap(long, parse(from_os_str), global = true, about = "Write a CSV report of the input rows that couldn't be read to this file")]
+    rejects: Option<PathBuf>,
+
+    #[clap(long, global = true, about = "Exit with an error on the first input row that can't be read")]
+    strict: bool,
+
+    #[clap(long = "dedupe-by", about = "Merge records whose values of these fields match, ignoring case and surrounding whitespace, e.g. `email` or `last_name,first_name` (may be repeated)")]
+    dedupe_by: Vec<String>,
+
+    #[clap(long, default_value = "first-wins", about = "How `--dedupe-by` merges a record into an earlier one: first-wins, last-wins or prefer-non-empty")]
+    on_duplicate: Policy,
+
+    #[clap(long, parse(from_os_str), about = "Write a CSV report of the records merged by `--dedupe-by` to this file")]
+    duplicates: Option<PathBuf>,
+
     #[clap(name = "FILE", parse(from_os_str), about = "CSV input files...", required = true)]
     files: Vec<PathBuf>,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+
+/// Compare inputs instead of concatenating them. The input, output and sort
+/// options of the CLI may follow the subcommand.
+#[derive(Clap)]
+enum Command {
+    #[clap(about = "Print the records added, removed and changed from OLD to NEW, with the fields that changed")]
+    Diff(DiffOpts),
+
+    #[clap(about = "Print the records of every input, once per key")]
+    Union(SetOpts),
+
+    #[clap(about = "Print the records of the first input whose key is in every other input")]
+    Intersect(SetOpts),
+
+    #[clap(about = "Print how many records there are in each group, the most common first")]
+    Stats(StatsOpts),
+}
+
+
+#[derive(Clap)]
+struct DiffOpts {
+    #[clap(short, long, about = "Match records whose values of these fields match, ignoring case and surrounding whitespace, e.g. `email` or `last_name,first_name` (defaults to every field)")]
+    key: Vec<String>,
+
+    #[clap(name = "OLD", parse(from_os_str))]
+    old: PathBuf,
+
+    #[clap(name = "NEW", parse(from_os_str))]
+    new: PathBuf,
+}
+
+
+#[derive(Clap)]
+struct StatsOpts {
+    #[clap(short, long, required = true, about = "Group records by this field, or by a bucket of it: `dob:age` (decades), `dob:year`, `dob:month` or `email:domain`, e.g. `favorite_color,dob:age` (may be repeated)")]
+    group_by: Vec<String>,
+
+    #[clap(name = "FILE", parse(from_os_str), required = true)]
+    files: Vec<PathBuf>,
+}
+
+
+#[derive(Clap)]
+struct SetOpts {
+    #[clap(short, long, about = "Match records whose values of these fields match, ignoring case and surrounding whitespace, e.g. `email` or `last_name,first_name` (defaults to every field)")]
+    key: Vec<String>,
+
+    #[clap(name = "FILE", parse(from_os_str), required = true)]
+    files: Vec<PathBuf>,
 }
 
 
-fn sorting_fields(opts: &Opts) -> Vec<(&str, SortDirection)> {
+/// The `--field`s, each with its direction. Exits if any of them isn't a field
+/// (unless extra columns are kept), or has an invalid modifier.
+fn sort_spec(opts: &Opts) -> SortSpec {
     let mut sort_direction_mappings = VecDeque::from(opts.sort_direction_mappings.clone());
-    let field_names: Vec<&str> = opts.fields.iter()
-        .map(String::as_str)
+    let fields: Vec<(&str, SortDirection)> = opts.fields.iter()
+        .map(|name| match sort_direction_mappings.pop_front() {
+            Some(sd) => (name.as_str(), sd),
+            None => (name.as_str(), opts.sort_direction)
+        })
         .collect();
 
-    field_names.iter().map(|&name| match sort_direction_mappings.pop_front() {
-        Some(sd) => (name, sd),
-        None => (name, opts.sort_direction)
-    }).collect()
+    SortSpec::parse::<Person>(&fields, opts.extra_columns).unwrap_or_else(|e| invalid_value(format!("Invalid `--field`: {}", e)))
+}
+
+
+/// Exits with an invalid value error (see `clap::ErrorKind::InvalidValue`) of `message`
+fn invalid_value(message: String) -> ! {
+    // Unlike clap's own errors, one with a description doesn't end with a newline.
+    clap::Error::with_description(format!("{}\n", message), ErrorKind::InvalidValue).exit()
+}
+
+
+/// The fields of `--dedupe-by`, if any were given
+fn dedupe_opts(opts: &Opts) -> Option<DedupeOpts> {
+    DedupeOpts::new::<Person>(&opts.dedupe_by, opts.on_duplicate).unwrap_or_else(|e| invalid_value(format!("Invalid `--dedupe-by`: {}", e)))
+}
+
+
+fn output_field_separator(opts: &Opts) -> char {
+    match opts.output_field_separator {
+        Some(o) => o,
+        None => opts.input_field_separator
+    }
+}
+
+
+/// Writes `people`, with the extra `columns` when they're delimited
+fn write_people(opts: &Opts, columns: &[String], people: impl IntoIterator<Item = Person>) -> io::Result<usize> {
+    match opts.output_format {
+        Format::Csv => write_output(
+            std::io::stdout(),
+            output_field_separator(opts),
+            opts.output_has_header,
+            columns,
+            people
+        ),
+        Format::Json => write_json_output(std::io::stdout(), people),
+        Format::Ndjson => write_ndjson_output(std::io::stdout(), people),
+    }
+}
+
+
+/// Logs a row that couldn't be read and adds it to the `--rejects` report.
+/// Returns whether to exit, i.e., with `--strict`.
+fn report_reject(opts: &Opts, rejects: &mut Option<RejectWriter<File>>, reject: &Reject) -> io::Result<bool> {
+    if let Some(writer) = rejects.as_mut() {
+        writer.write(reject)?;
+    }
+
+    if opts.strict {
+        log::error!("Rejected {}", reject);
+        if let Some(writer) = rejects.as_mut() {
+            writer.flush()?;
+        }
+        return Ok(true)
+    }
+
+    log::warn!("Rejected {}", reject);
+    Ok(false)
+}
+
+
+fn input_opts(opts: &Opts, rules: Rules) -> InputOpts {
+    InputOpts {
+        format: opts.input_format,
+        format_mappings: opts.input_format_mappings.clone(),
+        field_separator: opts.input_field_separator,
+        field_separator_mappings: opts.input_field_separator_mappings.clone(),
+        has_header: opts.input_has_header,
+        has_header_mappings: opts.input_has_header_mappings.clone(),
+        rules: Arc::new(rules),
+    }
 }
 
 
@@ -69,33 +251,256 @@ async fn main() -> io::Result<()> {
     set_console_logger(LevelFilter::Warn).unwrap();
 
     let opts: Opts = Opts::parse();
-    let fields = sorting_fields(&opts);
-    let mut people: Vec<Person> = vec![];
-    let output_field_separator = match opts.output_field_separator {
-        Some(o) => o,
-        None => opts.input_field_separator
-    };
+    date_format::set_default(DateFormats::new(opts.input_date_formats.clone(), opts.output_date_format.clone()));
+    extra::set_default(opts.extra_columns);
 
     if opts.available_fields {
         println!("{}", Person::struct_fields().join(", "));
         return Ok(());
     }
 
-    read_input_files(
-        &opts.files,
-        opts.input_field_separator,
-        &opts.input_field_separator_mappings,
-        opts.input_has_header,
-        &opts.input_has_header_mappings,
-        &mut people
-    ).await?;
+    let spec = sort_spec(&opts);
+
+    // Extra columns can have any name.
+    if let Some(predicate) = filtering::unknown_field(&opts.predicates, Person::struct_fields()).filter(|_| !opts.extra_columns) {
+        invalid_value(format!("Field \"{}\" not found in `--where {}`. Available fields: {}",
+            predicate.field, predicate, Person::struct_fields().join(", ")));
+    }
+
+    // Records are only validated when asked to.
+    let rules = match (&opts.validation, opts.validate) {
+        (Some(path), _) => Rules::from_file::<Person>(path).unwrap_or_else(|e| invalid_value(
+            format!("Unable to read `--validation {}`: {}", path.display(), e))),
+        (None, true) => Rules::default(),
+        (None, false) => Rules::none(),
+    };
+
+    let input_opts = input_opts(&opts, rules);
+
+    match &opts.command {
+        Some(command @ Command::Diff(diff)) =>
+            return compare(command, &[diff.old.clone(), diff.new.clone()], &diff.key, &opts, &input_opts).await,
+        Some(command @ Command::Union(set)) | Some(command @ Command::Intersect(set)) =>
+            return compare(command, &set.files, &set.key, &opts, &input_opts).await,
+        Some(Command::Stats(stats)) => return count(stats, &opts, &input_opts).await,
+        None => (),
+    }
+
+    // Without `--chunk-size`, the sorter never spills, i.e., it sorts in memory.
+    let temp_dir = opts.temp_dir.clone().unwrap_or_else(std::env::temp_dir);
+    let mut sorter = ExternalSorter::new(&spec, opts.chunk_size.unwrap_or(usize::MAX), &temp_dir);
+    let mut rejects = match &opts.rejects {
+        Some(path) => Some(RejectWriter::create(path)?),
+        None => None
+    };
+    let mut deduper = dedupe_opts(&opts).map(|dedupe| Deduper::<Person>::new(&dedupe));
+    let mut stream = read_input_stream_with_origins(&opts.files, &input_opts)?;
+    let (mut read, mut rejected, mut merged) = (0, 0, 0);
+    // The extra columns of every selected person, since they're only written at the end
+    let mut columns = vec![];
+
+    while let Some((origin, result)) = stream.next().await {
+        let person = match result {
+            Ok(person) => person,
+            Err(reject) => {
+                rejected += 1;
+
+                if report_reject(&opts, &mut rejects, &reject)? {
+                    // Exiting skips destructors, so remove any spilled chunks first.
+                    drop(sorter);
+                    std::process::exit(1);
+                }
+                continue
+            }
+        };
+
+        read += 1;
+
+        match deduper.as_mut() {
+            // Duplicates are merged before anything is selected, so every record is held until the end.
+            Some(deduper) => { deduper.push(person, origin); },
+            None if person.matches_all(&opts.predicates) => {
+                add_extra_columns(&mut columns, &person);
+                sorter.push(person)?
+            },
+            None => (),
+        }
+    }
+
+    if let Some(mut writer) = rejects {
+        writer.flush()?;
+    }
+
+    if let Some(deduper) = deduper {
+        let (people, duplicates) = deduper.finish();
+        let mut report = match &opts.duplicates {
+            Some(path) => Some(DuplicateWriter::create(path)?),
+            None => None
+        };
+
+        merged = duplicates.len();
+
+        for duplicate in duplicates.iter() {
+            log::info!("Merged {}", duplicate);
+
+            if let Some(writer) = report.as_mut() {
+                writer.write(duplicate)?;
+            }
+        }
+
+        if let Some(mut writer) = report {
+            writer.flush()?;
+        }
+
+        for person in people.into_iter().filter(|person| person.matches_all(&opts.predicates)) {
+            add_extra_columns(&mut columns, &person);
+            sorter.push(person)?;
+        }
+    }
+
+    let mut merge_error = None;
+    let people = sorter.finish()?
+        .map_while(|result| result.map_err(|e| merge_error = Some(e)).ok());
+
+    let written = write_people(&opts, &columns, people)?;
+
+    eprintln!("{} read, {} rejected, {} merged, {} written", read, rejected, merged, written);
+
+    match merge_error {
+        Some(e) => Err(e),
+        None => Ok(())
+    }
+}
+
+
+/// Runs `diff`, `union` or `intersect` on `files`. Each of them is read into memory in full.
+async fn compare(command: &Command, files: &[PathBuf], key: &[String], opts: &Opts, input_opts: &InputOpts) -> io::Result<()> {
+    let key = dedupe::key_fields::<Person>(key).unwrap_or_else(|e| invalid_value(format!("Invalid `--key`: {}", e)));
+
+    let mut rejects = match &opts.rejects {
+        Some(path) => Some(RejectWriter::create(path)?),
+        None => None
+    };
+    let mut inputs = vec![];
+    let (mut read, mut rejected) = (0, 0);
+
+    for mut stream in read_input_streams_with_origins(files, input_opts)? {
+        let mut people: Vec<Person> = vec![];
+
+        while let Some((_, result)) = stream.next().await {
+            match result {
+                Ok(person) => {
+                    read += 1;
+                    people.push(person);
+                },
+                Err(reject) => {
+                    rejected += 1;
+
+                    if report_reject(opts, &mut rejects, &reject)? {
+                        std::process::exit(1);
+                    }
+                }
+            }
+        }
+
+        inputs.push(people);
+    }
+
+    if let Some(mut writer) = rejects {
+        writer.flush()?;
+    }
+
+    let spec = sort_spec(opts);
+    let written = match command {
+        Command::Diff(_) => {
+            let new = inputs.pop().unwrap_or_default();
+            let old = inputs.pop().unwrap_or_default();
+            let mut differences: Vec<Difference> = sets::diff(old, new, &key).into_iter()
+                .filter(|difference| difference.person.matches_all(&opts.predicates))
+                .collect();
+
+            differences.sort_by(|a, b| spec.cmp(&a.person, &b.person));
+
+            match opts.output_format {
+                Format::Csv => sets::write_differences(
+                    std::io::stdout(),
+                    output_field_separator(opts),
+                    opts.output_has_header,
+                    &extra_columns(differences.iter().map(|difference| &difference.person)),
+                    differences
+                ),
+                Format::Json => write_json_output(std::io::stdout(), differences),
+                Format::Ndjson => write_ndjson_output(std::io::stdout(), differences),
+            }?
+        },
+        _ => {
+            let mut people = match command {
+                Command::Union(_) => sets::union(inputs, &key),
+                _ => sets::intersect(inputs, &key),
+            };
+
+            people.retain(|person| person.matches_all(&opts.predicates));
+            people.sort_by(|a, b| spec.cmp(a, b));
+
+            write_people(opts, &extra_columns(&people), people)?
+        },
+    };
+
+    eprintln!("{} read, {} rejected, {} written", read, rejected, written);
+
+    Ok(())
+}
+
+
+/// Runs the `stats` subcommand, counting every record read that matches the predicates
+async fn count(command: &StatsOpts, opts: &Opts, input_opts: &InputOpts) -> io::Result<()> {
+    let group_by = stats::group_by::<Person>(&command.group_by).unwrap_or_else(|e| invalid_value(format!("Invalid `--group-by`: {}", e)));
+
+    let mut rejects = match &opts.rejects {
+        Some(path) => Some(RejectWriter::create(path)?),
+        None => None
+    };
+    let mut stats = Stats::new(group_by.clone());
+    let mut stream: OriginStream = read_input_stream_with_origins(&command.files, input_opts)?;
+    let (mut read, mut rejected) = (0, 0);
+
+    while let Some((_, result)) = stream.next().await {
+        match result {
+            Ok(person) => {
+                read += 1;
+
+                if person.matches_all(&opts.predicates) {
+                    stats.push(&person);
+                }
+            },
+            Err(reject) => {
+                rejected += 1;
+
+                if report_reject(opts, &mut rejects, &reject)? {
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+
+    if let Some(mut writer) = rejects {
+        writer.flush()?;
+    }
+
+    let groups = stats.finish();
+    let written = match opts.output_format {
+        Format::Csv => stats::write_stats(
+            std::io::stdout(),
+            output_field_separator(opts),
+            opts.output_has_header,
+            &group_by,
+            groups
+        ),
+        Format::Json => write_json_output(std::io::stdout(), groups),
+        Format::Ndjson => write_ndjson_output(std::io::stdout(), groups),
+    }?;
 
-    people.sort_by(|a, b| a.cmp_order_by_fields(b, &fields));
+    eprintln!("{} read, {} rejected, {} written", read, rejected, written);
 
-    write_output(
-        std::io::stdout(),
-        output_field_separator,
-        opts.output_has_header,
-        &people
-    )
+    Ok(())
 }
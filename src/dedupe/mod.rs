@@ -0,0 +1,311 @@
+use std::{collections::HashMap, fmt, fs::File, io::{self, Write}, path::Path, str::FromStr};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::filtering::FieldValue;
+use crate::io::Origin;
+use crate::person::{Extra, Person};
+use crate::resource::Resource;
+use crate::serialization::date_format::{self, DateFormats};
+
+
+/// How a person is merged into an earlier one with the same key
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Policy {
+    /// Keep the earlier person as is
+    #[default]
+    FirstWins,
+    /// Replace the earlier person with the later one
+    LastWins,
+    /// Keep the earlier person, filling its empty fields from the later one
+    PreferNonEmpty,
+}
+
+
+impl FromStr for Policy {
+    type Err = String;
+
+    fn from_str(policy: &str) -> Result<Self, Self::Err> {
+        match policy {
+            "first-wins" => Ok(Policy::FirstWins),
+            "last-wins" => Ok(Policy::LastWins),
+            "prefer-non-empty" => Ok(Policy::PreferNonEmpty),
+            x => Err(format!("Unknown policy \"{}\". Expected \"first-wins\", \"last-wins\" or \"prefer-non-empty\"", x)),
+        }
+    }
+}
+
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match self {
+            Policy::FirstWins => "first-wins",
+            Policy::LastWins => "last-wins",
+            Policy::PreferNonEmpty => "prefer-non-empty",
+        })
+    }
+}
+
+
+/// Which fields make two people duplicates, and how to merge them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DedupeOpts {
+    pub key: Vec<String>,
+    pub policy: Policy,
+}
+
+
+impl DedupeOpts {
+
+    /// Parses the key from lists of comma-separated fields of a `T`, e.g.,
+    /// `["last_name,first_name"]`. `None` if there are no fields at all.
+    pub fn new<T: Resource>(fields: &[String], policy: Policy) -> Result<Option<Self>, String> {
+        let key = key_fields::<T>(fields)?;
+
+        match key.is_empty() {
+            true => Ok(None),
+            false => Ok(Some(Self { key, policy })),
+        }
+    }
+}
+
+
+/// Parses lists of comma-separated fields, e.g., `["last_name,first_name", "email"]`,
+/// checking that every one of them is a field of a `T`
+pub fn key_fields<T: Resource>(fields: &[String]) -> Result<Vec<String>, String> {
+    let key: Vec<String> = fields.iter()
+        .flat_map(|fields| fields.split(','))
+        .map(|field| field.trim().to_string())
+        .filter(|field| !field.is_empty())
+        .collect();
+
+    // Extra columns can have any name.
+    match key.iter().find(|field| !T::struct_fields().contains(&field.as_str()) && !T::keeps_extra()) {
+        Some(field) => Err(format!("Field \"{}\" not found. Available fields: {}", field, T::struct_fields().join(", "))),
+        None => Ok(key),
+    }
+}
+
+
+/// A person merged into an earlier one with the same key
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Duplicate {
+    /// The key shared by both, e.g., `email=tom@example.com`
+    pub key: String,
+    pub file: String,
+    pub record: u64,
+    /// Where the person it was merged into was read from
+    pub kept_file: String,
+    pub kept_record: u64,
+    pub policy: String,
+    /// Fields of the kept person that were changed by the merge, separated by spaces
+    pub changed: String,
+}
+
+
+impl fmt::Display for Duplicate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {} duplicates {}:{}", self.file, self.record, self.key, self.kept_file, self.kept_record)?;
+
+        match self.changed.is_empty() {
+            true => Ok(()),
+            false => write!(f, " (changed {})", self.changed),
+        }
+    }
+}
+
+
+/// The value of `field`, or of the extra column named `field`, with dates in ISO-8601
+pub(crate) fn value<T: Resource>(record: &T, field: &str) -> String {
+    match record.field_value(field) {
+        Some(FieldValue::Str(value)) => value.to_string(),
+        Some(FieldValue::Date(Some(date))) => date.to_string(),
+        Some(FieldValue::Date(None)) => String::new(),
+        None => record.extra().and_then(|extra| extra.get(field)).unwrap_or_default().to_string(),
+    }
+}
+
+
+/// The fields two records are compared on: the fields of a `T`, followed by
+/// the extra columns of either of them, in the order they were read
+pub(crate) fn fields<T: Resource>(a: &T, b: &T) -> Vec<String> {
+    let mut fields: Vec<String> = T::struct_fields().iter().map(|field| field.to_string()).collect();
+
+    for column in a.extra().into_iter().chain(b.extra()).flat_map(Extra::columns) {
+        if !fields.iter().any(|field| field == column) {
+            fields.push(column.to_string());
+        }
+    }
+
+    fields
+}
+
+
+/// The value of `field` as compared by a key: trimmed and ignoring case
+pub(crate) fn key_value<T: Resource>(record: &T, field: &str) -> String {
+    value(record, field).trim().to_lowercase()
+}
+
+
+/// Copies `fields` from `from` into `into`: the fields of a `T` through their
+/// serialized values, so that they don't have to be listed here, and any
+/// others as extra columns, which are removed where `from` doesn't have them
+fn copy_fields<T: Resource>(into: &mut T, from: &T, fields: &[String]) {
+    let (columns, fields): (Vec<&str>, Vec<&str>) = fields.iter()
+        .map(String::as_str)
+        .partition(|field| !T::struct_fields().contains(field));
+    let formats = DateFormats::default();
+
+    let merged = date_format::with_formats(&formats, || {
+        let mut values = match serde_json::to_value(&*into) {
+            Ok(Value::Object(values)) => values,
+            _ => return None,
+        };
+
+        if let Ok(Value::Object(from)) = serde_json::to_value(from) {
+            for &field in fields.iter() {
+                if let Some(value) = from.get(field) {
+                    values.insert(field.to_string(), value.clone());
+                }
+            }
+        }
+
+        serde_json::from_value::<T>(Value::Object(values)).ok()
+    });
+
+    if let Some(mut merged) = merged {
+        if let (Some(extra), Some(kept)) = (merged.extra_mut(), into.extra_mut()) {
+            *extra = std::mem::take(kept);
+        }
+        *into = merged;
+    }
+
+    let none = Extra::default();
+    let (into, from) = match into.extra_mut() {
+        Some(into) => (into, from.extra().unwrap_or(&none)),
+        None => return,
+    };
+
+    for column in columns {
+        match from.get(column) {
+            Some(value) => into.insert(column.to_string(), value.to_string()),
+            None => { into.remove(column); },
+        }
+    }
+}
+
+
+/// Merges the people read from any number of inputs that share a key, i.e.,
+/// the values of the `key` fields (see `key_value`). People missing any of the
+/// key fields are never duplicates. Every unique person is held in memory, in
+/// the order they were first read. People can be any other `Resource`.
+#[derive(Debug)]
+pub struct Deduper<T = Person> {
+    key: Vec<String>,
+    policy: Policy,
+    index: HashMap<Vec<String>, usize>,
+    people: Vec<(T, Origin)>,
+    duplicates: Vec<Duplicate>,
+}
+
+
+impl<T: Resource> Deduper<T> {
+
+    pub fn new(opts: &DedupeOpts) -> Self {
+        Self {
+            key: opts.key.clone(),
+            policy: opts.policy,
+            index: HashMap::new(),
+            people: vec![],
+            duplicates: vec![],
+        }
+    }
+
+    /// Adds `person`, merging it into an earlier person with the same key, if
+    /// there is one. Returns what was merged.
+    pub fn push(&mut self, person: T, origin: Origin) -> Option<&Duplicate> {
+        let key: Vec<String> = self.key.iter().map(|field| key_value(&person, field)).collect();
+
+        if key.iter().any(String::is_empty) {
+            self.people.push((person, origin));
+            return None
+        }
+
+        let idx = match self.index.get(&key) {
+            Some(&idx) => idx,
+            None => {
+                self.index.insert(key, self.people.len());
+                self.people.push((person, origin));
+                return None
+            }
+        };
+
+        let policy = self.policy;
+        let (kept, kept_origin) = &mut self.people[idx];
+        let changed: Vec<String> = fields(kept, &person).into_iter()
+            .filter(|field| match policy {
+                Policy::FirstWins => false,
+                Policy::LastWins => value(kept, field) != value(&person, field),
+                Policy::PreferNonEmpty => value(kept, field).is_empty() && !value(&person, field).is_empty(),
+            })
+            .collect();
+
+        copy_fields(kept, &person, &changed);
+
+        self.duplicates.push(Duplicate {
+            key: self.key.iter().zip(key.iter())
+                .map(|(field, value)| format!("{}={}", field, value))
+                .collect::<Vec<String>>()
+                .join(" "),
+            file: origin.file,
+            record: origin.record,
+            kept_file: kept_origin.file.clone(),
+            kept_record: kept_origin.record,
+            policy: policy.to_string(),
+            changed: changed.join(" "),
+        });
+
+        self.duplicates.last()
+    }
+
+    /// The merged people, in the order they were first read, and every duplicate merged into them
+    pub fn finish(self) -> (Vec<T>, Vec<Duplicate>) {
+        (self.people.into_iter().map(|(person, _)| person).collect(), self.duplicates)
+    }
+}
+
+
+/// Writes a CSV report of merged duplicates, with a header
+pub struct DuplicateWriter<W: Write> {
+    writer: csv::Writer<W>,
+}
+
+
+impl DuplicateWriter<File> {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(Self::new(File::create(path)?))
+    }
+}
+
+
+impl<W: Write> DuplicateWriter<W> {
+
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: csv::WriterBuilder::new()
+                .terminator(csv::Terminator::CRLF)
+                .from_writer(writer),
+        }
+    }
+
+    pub fn write(&mut self, duplicate: &Duplicate) -> io::Result<()> {
+        self.writer.serialize(duplicate).map_err(io::Error::from)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+
+mod tests;
@@ -0,0 +1,120 @@
+#![allow(dead_code)]
+
+use super::*;
+
+
+fn origin(record: u64) -> Origin {
+    Origin { file: "people.csv".into(), record }
+}
+
+
+fn dedupe(policy: Policy, key: &[&str], people: Vec<Person>) -> (Vec<Person>, Vec<Duplicate>) {
+    let mut deduper = Deduper::new(&DedupeOpts {
+        key: key.iter().map(|field| field.to_string()).collect(),
+        policy,
+    });
+
+    for (idx, person) in people.into_iter().enumerate() {
+        deduper.push(person, origin(idx as u64 + 1));
+    }
+
+    deduper.finish()
+}
+
+
+fn people() -> Vec<Person> {
+    vec![
+        Person::new("Brennan", "Tom", "tjb1982@gmail.com", "", "8/19/1982"),
+        Person::new("Fuller", "Rachel", "", "green", "8/10/1970"),
+        Person::new("Brennan", "Thomas", " TJB1982@gmail.com", "red", ""),
+        Person::new("Fuller", "Rachel", "", "blue", ""),
+    ]
+}
+
+
+#[cfg(test)]
+mod policies {
+    use super::*;
+
+    #[test]
+    fn first_wins() {
+        let (merged, duplicates) = dedupe(Policy::FirstWins, &["email"], people());
+
+        // Rachel has no email, so she's never a duplicate
+        assert_eq!(merged, vec![people()[0].clone(), people()[1].clone(), people()[3].clone()]);
+        assert_eq!(duplicates, vec![Duplicate {
+            key: "email=tjb1982@gmail.com".into(),
+            file: "people.csv".into(),
+            record: 3,
+            kept_file: "people.csv".into(),
+            kept_record: 1,
+            policy: "first-wins".into(),
+            changed: "".into(),
+        }]);
+    }
+
+    #[test]
+    fn last_wins() {
+        let (merged, duplicates) = dedupe(Policy::LastWins, &["email"], people());
+
+        assert_eq!(merged[0], people()[2]);
+        assert_eq!(duplicates[0].changed, "first_name email favorite_color dob");
+    }
+
+    #[test]
+    fn prefer_non_empty() {
+        let (merged, duplicates) = dedupe(Policy::PreferNonEmpty, &["last_name", "first_name"], people());
+
+        assert_eq!(merged.len(), 3);
+        assert_eq!(merged[1], people()[1]);
+        assert_eq!(duplicates[0].key, "last_name=fuller first_name=rachel");
+        assert_eq!(duplicates[0].changed, "");
+
+        let (merged, duplicates) = dedupe(Policy::PreferNonEmpty, &["email"], people());
+
+        assert_eq!(merged[0], Person::new("Brennan", "Tom", "tjb1982@gmail.com", "red", "8/19/1982"));
+        assert_eq!(duplicates[0].changed, "favorite_color");
+    }
+
+    #[test]
+    fn extra_columns() {
+        let with = |columns: &[(&str, &str)]| {
+            let mut person = Person::new("Brennan", "Tom", "tjb1982@gmail.com", "red", "8/19/1982");
+            person.extra = columns.iter().map(|(column, value)| (column.to_string(), value.to_string())).collect();
+            person
+        };
+        let people = || vec![with(&[("department", "Sales"), ("phone", "")]), with(&[("phone", "555-0100"), ("desk", "4")])];
+
+        let (merged, duplicates) = dedupe(Policy::LastWins, &["email"], people());
+
+        assert_eq!(merged[0].extra, people()[1].extra);
+        assert_eq!(duplicates[0].changed, "department phone desk");
+
+        let (merged, duplicates) = dedupe(Policy::PreferNonEmpty, &["email"], people());
+
+        assert_eq!(merged[0].extra, with(&[("department", "Sales"), ("phone", "555-0100"), ("desk", "4")]).extra);
+        assert_eq!(duplicates[0].changed, "phone desk");
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("prefer-non-empty".parse::<Policy>(), Ok(Policy::PreferNonEmpty));
+        assert_eq!(Policy::LastWins.to_string().parse::<Policy>(), Ok(Policy::LastWins));
+        assert!("newest".parse::<Policy>().is_err());
+    }
+
+    #[test]
+    fn report() {
+        let (_, duplicates) = dedupe(Policy::PreferNonEmpty, &["email"], people());
+        let mut writer = DuplicateWriter::new(vec![]);
+
+        writer.write(&duplicates[0]).unwrap();
+        writer.flush().unwrap();
+
+        let report = String::from_utf8(writer.writer.into_inner().unwrap()).unwrap();
+
+        assert_eq!(report, "key,file,record,kept_file,kept_record,policy,changed\r\n\
+            email=tjb1982@gmail.com,people.csv,3,people.csv,1,prefer-non-empty,favorite_color\r\n");
+        assert_eq!(duplicates[0].to_string(), "people.csv:3: email=tjb1982@gmail.com duplicates people.csv:1 (changed favorite_color)");
+    }
+}
@@ -0,0 +1,262 @@
+use std::{cmp::Ordering, fmt, str::FromStr};
+use chrono::NaiveDate;
+use regex::Regex;
+
+use crate::serialization::date_format;
+
+pub use homework_derive::FieldsFilter;
+
+
+/// Comparison a `Predicate` makes between a field and its value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    StartsWith,
+    EndsWith,
+    Contains,
+    Matches,
+}
+
+
+impl Operator {
+
+    /// Operator tokens, longest first, so that e.g. `>=` is preferred over `>`
+    const TOKENS: [(&'static str, Operator); 10] = [
+        (">=", Operator::Ge),
+        ("<=", Operator::Le),
+        ("!=", Operator::Ne),
+        ("^=", Operator::StartsWith),
+        ("$=", Operator::EndsWith),
+        ("*=", Operator::Contains),
+        ("~=", Operator::Matches),
+        ("=", Operator::Eq),
+        (">", Operator::Gt),
+        ("<", Operator::Lt),
+    ];
+
+    fn token(&self) -> &'static str {
+        Self::TOKENS.iter()
+            .find(|(_, op)| op == self)
+            .map(|(token, _)| *token)
+            .unwrap()
+    }
+}
+
+
+/// The value of a single field, as seen by a `Predicate`
+pub enum FieldValue<'a> {
+    Str(&'a str),
+    Date(Option<NaiveDate>),
+}
+
+
+/// A single condition on a record's field, parsed from a string such as
+/// `favorite_color=red`, `last_name^=Bre`, `dob>=1980-01-01` or `email~=@example.com$`.
+#[derive(Debug, Clone)]
+pub struct Predicate {
+    pub field: String,
+    pub operator: Operator,
+    pub value: String,
+    regex: Option<Regex>,
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PredicateParseError {
+    pub predicate: String,
+    pub reason: String,
+}
+
+
+impl fmt::Display for PredicateParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid predicate \"{}\": {}", self.predicate, self.reason)
+    }
+}
+
+
+impl FromStr for Predicate {
+    type Err = PredicateParseError;
+
+    fn from_str(predicate: &str) -> Result<Self, Self::Err> {
+        let err = |reason: &str| PredicateParseError {
+            predicate: predicate.to_string(),
+            reason: reason.to_string(),
+        };
+
+        let split = predicate
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(predicate.len());
+        let (field, rest) = predicate.split_at(split);
+
+        if field.is_empty() {
+            return Err(err("missing field name"));
+        }
+
+        let (token, operator) = Operator::TOKENS.iter()
+            .find(|(token, _)| rest.starts_with(token))
+            .ok_or_else(|| err("expected one of =, !=, <, <=, >, >=, ^=, $=, *=, ~="))?;
+
+        let value = &rest[token.len()..];
+
+        let regex = match operator {
+            Operator::Matches => Some(Regex::new(value).map_err(|e| err(&e.to_string()))?),
+            _ => None
+        };
+
+        Ok(Predicate {
+            field: field.to_string(),
+            operator: *operator,
+            value: value.to_string(),
+            regex,
+        })
+    }
+}
+
+
+impl fmt::Display for Predicate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}{}", self.field, self.operator.token(), self.value)
+    }
+}
+
+
+impl Predicate {
+
+    /// Parses the predicate's value as a date: either ISO-8601 (`YYYY-MM-DD`)
+    /// or one of the formats used to read `dob`.
+    fn date_value(&self) -> Option<NaiveDate> {
+        NaiveDate::parse_from_str(&self.value, "%Y-%m-%d").ok()
+            .or_else(|| date_format::date_from_str(&self.value).ok())
+    }
+
+    fn matches_str(&self, s: &str) -> bool {
+        let ord = s.cmp(self.value.as_str());
+
+        match self.operator {
+            Operator::StartsWith => s.starts_with(&self.value),
+            Operator::EndsWith => s.ends_with(&self.value),
+            Operator::Contains => s.contains(&self.value),
+            Operator::Matches => self.regex.as_ref().is_some_and(|re| re.is_match(s)),
+            _ => self.matches_ordering(ord),
+        }
+    }
+
+    fn matches_date(&self, date: Option<NaiveDate>) -> bool {
+        let rendered = date.as_ref().map(date_format::str_from_date).unwrap_or_default();
+
+        match self.operator {
+            Operator::StartsWith | Operator::EndsWith | Operator::Contains | Operator::Matches =>
+                self.matches_str(&rendered),
+            Operator::Eq | Operator::Ne if self.value.is_empty() =>
+                self.matches_ordering(if date.is_none() { Ordering::Equal } else { Ordering::Greater }),
+            _ => match (date, self.date_value()) {
+                (Some(date), Some(value)) => self.matches_ordering(date.cmp(&value)),
+                (None, _) => self.operator == Operator::Ne,
+                (Some(_), None) => {
+                    log::warn!("Unable to parse \"{}\" as a date in \"{}\".", self.value, self);
+                    false
+                }
+            }
+        }
+    }
+
+    fn matches_ordering(&self, ord: Ordering) -> bool {
+        match self.operator {
+            Operator::Eq => ord == Ordering::Equal,
+            Operator::Ne => ord != Ordering::Equal,
+            Operator::Lt => ord == Ordering::Less,
+            Operator::Le => ord != Ordering::Greater,
+            Operator::Gt => ord == Ordering::Greater,
+            Operator::Ge => ord != Ordering::Less,
+            _ => false,
+        }
+    }
+
+    /// Evaluates the predicate against the value of its field
+    pub fn matches(&self, value: FieldValue) -> bool {
+        match value {
+            FieldValue::Str(s) => self.matches_str(s),
+            FieldValue::Date(d) => self.matches_date(d),
+        }
+    }
+}
+
+
+/// A value of a field that `#[derive(FieldsFilter)]` can filter on
+pub trait FilterValue {
+    fn filter_value(&self) -> FieldValue<'_>;
+}
+
+
+impl FilterValue for str {
+    fn filter_value(&self) -> FieldValue<'_> {
+        FieldValue::Str(self)
+    }
+}
+
+
+impl FilterValue for String {
+    fn filter_value(&self) -> FieldValue<'_> {
+        FieldValue::Str(self)
+    }
+}
+
+
+/// `None` is empty
+impl FilterValue for Option<String> {
+    fn filter_value(&self) -> FieldValue<'_> {
+        FieldValue::Str(self.as_deref().unwrap_or_default())
+    }
+}
+
+
+impl FilterValue for NaiveDate {
+    fn filter_value(&self) -> FieldValue<'_> {
+        FieldValue::Date(Some(*self))
+    }
+}
+
+
+impl FilterValue for Option<NaiveDate> {
+    fn filter_value(&self) -> FieldValue<'_> {
+        FieldValue::Date(*self)
+    }
+}
+
+
+/// Trait implemented by records that can be selected with `Predicate`s, i.e.,
+/// that give the value of each of their fields by name. Usually derived, see
+/// `homework_derive`.
+pub trait FieldsFilter {
+
+    /// The value of the field named `field`, if there is one
+    fn field_value(&self, field: &str) -> Option<FieldValue<'_>>;
+
+    /// Whether the value of the predicate's field matches it. A field the
+    /// record doesn't have never does.
+    fn filter_field(&self, predicate: &Predicate) -> bool {
+        match self.field_value(&predicate.field) {
+            Some(value) => predicate.matches(value),
+            None => {
+                log::warn!("Field \"{}\" not found: ignoring.", predicate.field);
+                false
+            }
+        }
+    }
+
+    fn matches_all(&self, predicates: &[Predicate]) -> bool {
+        predicates.iter().all(|predicate| self.filter_field(predicate))
+    }
+}
+
+
+/// Returns the first predicate whose field isn't in `available`, if any
+pub fn unknown_field<'a>(predicates: &'a [Predicate], available: &[&str]) -> Option<&'a Predicate> {
+    predicates.iter().find(|p| !available.contains(&p.field.as_str()))
+}
@@ -0,0 +1,226 @@
+use std::{cmp::Ordering, collections::BinaryHeap, fs::{self, File}, io::{self, BufRead, BufReader, BufWriter, Lines, Write}};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+use crate::person::Person;
+use crate::resource::Resource;
+use crate::serialization::date_format::{self, DateFormats};
+use crate::sorting::SortSpec;
+
+
+/// Used to give every spilled chunk in the process a unique file name
+static SPILL_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+
+/// A sorted chunk written to a temporary file as newline-delimited JSON, with
+/// dates in the default `DateFormats`. The file is removed when the chunk is dropped.
+struct Spill {
+    path: PathBuf,
+}
+
+
+impl Spill {
+
+    fn write<T: Resource>(dir: &Path, people: &[T]) -> io::Result<Self> {
+        let path = dir.join(format!(
+            "homework-sort-{}-{}.ndjson",
+            std::process::id(),
+            SPILL_COUNT.fetch_add(1, AtomicOrdering::SeqCst)
+        ));
+        let spill = Spill { path };
+        let mut writer = BufWriter::new(File::create(&spill.path)?);
+
+        date_format::with_formats(&DateFormats::default(), || -> io::Result<()> {
+            for person in people.iter() {
+                serde_json::to_writer(&mut writer, person)?;
+                writeln!(writer)?;
+            }
+            Ok(())
+        })?;
+        writer.flush()?;
+
+        Ok(spill)
+    }
+
+    fn lines(&self) -> io::Result<Lines<BufReader<File>>> {
+        Ok(BufReader::new(File::open(&self.path)?).lines())
+    }
+}
+
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            log::warn!("Unable to remove {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+
+/// Sorts any number of people (or other records) using a bounded amount of memory.
+///
+/// People are buffered until `chunk_size` of them have been pushed. The buffer
+/// is then sorted and spilled to a temporary file. `finish` merges the spilled
+/// chunks back together. Ties are resolved in favour of the earlier chunk. The
+/// chunks are sorted with the stable `sort_by`, so the output is identical to
+/// sorting everything in memory with the same fields.
+pub struct ExternalSorter<'a, T = Person> {
+    sort: &'a SortSpec,
+    chunk_size: usize,
+    dir: PathBuf,
+    chunk: Vec<T>,
+    spills: Vec<Spill>,
+}
+
+
+impl<'a, T: Resource> ExternalSorter<'a, T> {
+
+    /// `chunk_size` is the most people held in memory at once while sorting
+    /// (it is at least 1). Chunks are spilled to `dir`.
+    pub fn new(sort: &'a SortSpec, chunk_size: usize, dir: &Path) -> Self {
+        Self {
+            sort,
+            chunk_size: chunk_size.max(1),
+            dir: dir.to_path_buf(),
+            chunk: vec![],
+            spills: vec![],
+        }
+    }
+
+    fn sort_chunk(&mut self) {
+        let sort = self.sort;
+        self.chunk.sort_by(|a, b| sort.cmp(a, b));
+    }
+
+    pub fn push(&mut self, person: T) -> io::Result<()> {
+        self.chunk.push(person);
+
+        if self.chunk.len() >= self.chunk_size {
+            self.sort_chunk();
+            self.spills.push(Spill::write(&self.dir, &self.chunk)?);
+            self.chunk.clear();
+        }
+
+        Ok(())
+    }
+
+    /// Returns the people in sorted order. If nothing was spilled, this simply
+    /// sorts what's in memory.
+    pub fn finish(mut self) -> io::Result<Merge<'a, T>> {
+        if !self.chunk.is_empty() {
+            self.sort_chunk();
+        }
+
+        if self.spills.is_empty() {
+            return Ok(Merge {
+                memory: self.chunk.into_iter(),
+                heap: BinaryHeap::new(),
+                sources: vec![],
+                _spills: vec![],
+            })
+        }
+
+        if !self.chunk.is_empty() {
+            self.spills.push(Spill::write(&self.dir, &self.chunk)?);
+        }
+
+        let mut sources = vec![];
+        let mut heap = BinaryHeap::new();
+
+        for (chunk, spill) in self.spills.iter().enumerate() {
+            let mut lines = spill.lines()?;
+
+            if let Some(person) = next_person(&mut lines)? {
+                heap.push(Head { person, chunk, sort: self.sort });
+            }
+            sources.push(lines);
+        }
+
+        Ok(Merge {
+            memory: vec![].into_iter(),
+            heap,
+            sources,
+            _spills: self.spills,
+        })
+    }
+}
+
+
+fn next_person<T: Resource>(lines: &mut Lines<BufReader<File>>) -> io::Result<Option<T>> {
+    match lines.next() {
+        None => Ok(None),
+        Some(line) => {
+            let line = line?;
+            Ok(Some(date_format::with_formats(&DateFormats::default(), || serde_json::from_str(&line))?))
+        },
+    }
+}
+
+
+/// The next person from one of the spilled chunks, ordered so that
+/// `BinaryHeap` (a max-heap) pops the smallest person, then the earliest chunk.
+struct Head<'a, T> {
+    person: T,
+    chunk: usize,
+    sort: &'a SortSpec,
+}
+
+
+impl<'a, T: Resource> Ord for Head<'a, T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.sort.cmp(&self.person, &other.person)
+            .then(self.chunk.cmp(&other.chunk))
+            .reverse()
+    }
+}
+
+
+impl<'a, T: Resource> PartialOrd for Head<'a, T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+
+impl<'a, T: Resource> PartialEq for Head<'a, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+
+impl<'a, T: Resource> Eq for Head<'a, T> {}
+
+
+/// Iterator over the sorted output of an `ExternalSorter`
+pub struct Merge<'a, T = Person> {
+    /// The sorted people, when nothing had to be spilled
+    memory: std::vec::IntoIter<T>,
+    heap: BinaryHeap<Head<'a, T>>,
+    sources: Vec<Lines<BufReader<File>>>,
+    _spills: Vec<Spill>,
+}
+
+
+impl<'a, T: Resource> Iterator for Merge<'a, T> {
+    type Item = io::Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(person) = self.memory.next() {
+            return Some(Ok(person))
+        }
+
+        let Head { person, chunk, sort } = self.heap.pop()?;
+
+        match next_person(&mut self.sources[chunk]) {
+            Ok(Some(next)) => self.heap.push(Head { person: next, chunk, sort }),
+            Ok(None) => (),
+            Err(e) => {
+                self.heap.clear();
+                return Some(Err(e))
+            },
+        }
+
+        Some(Ok(person))
+    }
+}
@@ -0,0 +1,865 @@
+use std::{borrow::{Borrow, Cow}, collections::VecDeque, fmt, io::{self as stdio, BufRead, BufReader, Read, Write}, path::{Path, PathBuf}, pin::Pin, str::FromStr, sync::Arc};
+use csv;
+use futures::{future, stream::{self, Stream, StreamExt}};
+use serde::{Deserializer, de::{SeqAccess, Visitor, value::MapDeserializer}};
+
+use tokio::io;
+use tokio::sync::mpsc;
+use serde::Serialize;
+
+use crate::person::{Extra, Person};
+use crate::resource::Resource;
+use crate::validation::Rules;
+
+pub mod external_sort;
+pub mod reject;
+
+pub use reject::{Reject, RejectWriter};
+use reject::field_from_message;
+
+
+/// Serialization formats understood by the input and output functions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Delimited rows; see `parse_csv_people_from_reader` and `write_output`
+    Csv,
+    /// A single JSON array of objects
+    Json,
+    /// One JSON object per line
+    Ndjson,
+}
+
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format.to_lowercase().as_str() {
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            "ndjson" | "jsonl" => Ok(Format::Ndjson),
+            x => Err(format!("Unknown format \"{}\": expected one of csv, json, ndjson", x)),
+        }
+    }
+}
+
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Format::Csv => "csv",
+            Format::Json => "json",
+            Format::Ndjson => "ndjson",
+        })
+    }
+}
+
+
+impl Format {
+
+    /// The media type of the format, e.g., for a `Content-Type` header
+    pub fn media_type(&self) -> &'static str {
+        match self {
+            Format::Csv => "text/csv",
+            Format::Json => "application/json",
+            Format::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    /// The format of a media type (without parameters), if it's one of ours
+    pub fn from_media_type(media_type: &str) -> Option<Self> {
+        match media_type.trim().to_lowercase().as_str() {
+            "text/csv" => Some(Format::Csv),
+            "application/json" => Some(Format::Json),
+            "application/x-ndjson" | "application/ndjson" => Some(Format::Ndjson),
+            _ => None
+        }
+    }
+}
+
+
+fn csv_err_is_broken_pipe(e: &csv::Error) -> bool
+{
+    match e.kind() {
+        csv::ErrorKind::Io(e) => e.kind() == io::ErrorKind::BrokenPipe,
+        _ => false
+    }
+}
+
+
+/// Adds the extra columns of `record` (see `person::extra`) that aren't among
+/// `columns` yet, after them
+pub fn add_extra_columns<T: Resource>(columns: &mut Vec<String>, record: &T) {
+    for column in record.extra().into_iter().flat_map(Extra::columns) {
+        if !columns.iter().any(|c| c == column) {
+            columns.push(column.to_string());
+        }
+    }
+}
+
+
+/// The extra columns of every one of `records`, in the order they're first
+/// found: the columns to write them with (see `write_output`)
+pub fn extra_columns<'a, T: Resource>(records: impl IntoIterator<Item = &'a T>) -> Vec<String> {
+    let mut columns = vec![];
+
+    for record in records {
+        add_extra_columns(&mut columns, record);
+    }
+
+    columns
+}
+
+
+/// The header of delimited rows: the fields of a `T`, followed by `columns`
+pub(crate) fn csv_header<T: Resource>(columns: &[String]) -> impl Iterator<Item = &str> {
+    T::struct_fields().iter().copied().chain(columns.iter().map(String::as_str))
+}
+
+
+/// The delimited row for `record`, with its values of `columns`, or why it
+/// can't be written under them, i.e., it has an extra column that isn't one
+pub(crate) fn csv_output_record<'a, T: Resource>(record: &'a T, columns: &'a [String]) -> Result<Vec<Cow<'a, str>>, String> {
+    match record.extra().into_iter().flat_map(Extra::columns).find(|column| !columns.iter().any(|c| c == column)) {
+        Some(column) => Err(format!("extra column \"{}\" isn't in the header", column)),
+        None => Ok(record.csv_record(columns)),
+    }
+}
+
+
+/// Writes `people` as delimited rows (see `Resource::csv_record`). Returns how many were written.
+///
+/// Extra columns (see `person::extra`) follow the fields: every row has the
+/// extra `columns`, e.g., the `extra_columns` of all of the people, so that
+/// they line up with the header. People with any others are skipped with a warning.
+pub fn write_output<W, T> (
+    writer: W,
+    output_field_separator: char,
+    output_has_header: bool,
+    columns: &[String],
+    people: impl IntoIterator<Item = impl Borrow<T>>,
+) -> Result<usize, stdio::Error>
+    where W: Write, T: Resource
+{
+
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(output_field_separator as u8)
+        .terminator(csv::Terminator::CRLF)
+        .from_writer(writer);
+
+    let mut header = output_has_header;
+    let mut written = 0;
+
+    for person in people.into_iter() {
+        let person = person.borrow();
+        let record = match csv_output_record(person, columns) {
+            Ok(record) => record,
+            Err(e) => {
+                log::warn!("Problem serializing person: {}", e);
+                continue
+            }
+        };
+        let result = match std::mem::take(&mut header) {
+            true => writer.write_record(csv_header::<T>(columns)),
+            false => Ok(()),
+        };
+
+        match result.and_then(|_| writer.write_record(record.iter().map(|value| value.as_ref()))) {
+            Err(e) if csv_err_is_broken_pipe(&e) => {
+                log::warn!("{}", e);
+                return Ok(written)
+            },
+            Err(e) => log::warn!("Problem serializing person: {}", e),
+            _ => written += 1
+        }
+    }
+
+    writer.flush()?;
+
+    Ok(written)
+}
+
+
+/// Writes `people` as a single JSON array, one element at a time. Returns how
+/// many were written.
+pub fn write_json_output<T> (
+    mut writer: T,
+    people: impl IntoIterator<Item = impl Serialize>,
+) -> Result<usize, stdio::Error>
+    where T: Write
+{
+    let mut written = 0;
+    let write = || -> Result<(), stdio::Error> {
+        writer.write_all(b"[")?;
+
+        for person in people.into_iter() {
+            if written > 0 {
+                writer.write_all(b",")?;
+            }
+            serde_json::to_writer(&mut writer, &person)?;
+            written += 1;
+        }
+
+        writer.write_all(b"]\n")?;
+        writer.flush()
+    };
+
+    match write() {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {
+            log::warn!("{}", e);
+            Ok(written)
+        },
+        x => x.map(|_| written)
+    }
+}
+
+
+/// Writes `people` as newline-delimited JSON, one object per line. Returns how
+/// many were written.
+pub fn write_ndjson_output<T> (
+    mut writer: T,
+    people: impl IntoIterator<Item = impl Serialize>,
+) -> Result<usize, stdio::Error>
+    where T: Write
+{
+    let mut written = 0;
+
+    for person in people.into_iter() {
+        let result = serde_json::to_writer(&mut writer, &person)
+            .map_err(stdio::Error::from)
+            .and_then(|_| writeln!(writer));
+
+        match result {
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {
+                log::warn!("{}", e);
+                return Ok(written)
+            },
+            Err(e) => log::warn!("Problem serializing person: {}", e),
+            _ => written += 1
+        }
+    }
+
+    writer.flush()?;
+
+    Ok(written)
+}
+
+
+/// Lazily parses CSV rows into people (or any other `Resource`), one row at a time.
+pub fn parse_csv_people_from_reader<R: Read, T: Resource>(
+    reader: R,
+    input_field_separator: char,
+    input_has_header: bool
+) -> impl Iterator<Item = Result<T, csv::Error>>
+{
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(input_field_separator as u8)
+        .has_headers(input_has_header)
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+
+    let mut headers = match input_has_header {
+        false => Ok(None),
+        true => reader.headers().map(|headers| Some(headers.clone())),
+    };
+    let mut record = csv::StringRecord::new();
+
+    std::iter::from_fn(move || {
+        let headers = match &headers {
+            Ok(headers) => headers.as_ref(),
+            Err(_) => return std::mem::replace(&mut headers, Ok(None)).err().map(Err),
+        };
+
+        match reader.read_record(&mut record) {
+            Ok(false) => None,
+            Ok(true) => Some(deserialize_csv::<T>(&record, headers)),
+            Err(e) => Some(Err(e)),
+        }
+    })
+}
+
+
+/// Deserializes a CSV row by its `headers`, or by position without them (see
+/// `positional_headers`), keeping the columns that aren't fields of a `T` as
+/// they were read.
+fn deserialize_csv<T: Resource>(record: &csv::StringRecord, headers: Option<&csv::StringRecord>) -> Result<T, csv::Error>
+{
+    let positional;
+    let headers = match headers {
+        Some(headers) => headers,
+        None => {
+            positional = positional_headers::<T>(record.len());
+            &positional
+        }
+    };
+
+    record.deserialize::<T>(Some(headers)).map(|mut person| {
+        if let Some(extra) = person.extra_mut().filter(|_| T::keeps_extra()) {
+            *extra = csv_extra::<T>(record, headers);
+        }
+        person
+    })
+}
+
+
+/// The names of the columns of a row without a header: the fields of a `T`,
+/// in order, followed by any others named by position, e.g., `column_6`.
+fn positional_headers<T: Resource>(len: usize) -> csv::StringRecord {
+    T::struct_fields().iter()
+        .map(|field| field.to_string())
+        .chain((T::struct_fields().len()..len).map(|idx| format!("column_{}", idx + 1)))
+        .collect()
+}
+
+
+/// Visits each element of a JSON array in turn, so that the whole array never
+/// has to be held in memory. `each` returns `false` to stop early.
+struct JsonArrayVisitor<F> {
+    each: F,
+}
+
+
+impl<'de, F> Visitor<'de> for JsonArrayVisitor<F>
+    where F: FnMut(serde_json::Value) -> bool
+{
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an array of people")
+    }
+
+    fn visit_seq<A>(mut self, mut seq: A) -> Result<(), A::Error>
+        where A: SeqAccess<'de>
+    {
+        while let Some(value) = seq.next_element::<serde_json::Value>()? {
+            if !(self.each)(value) {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+
+/// The message of a `serde_json::Error`, without its location
+fn json_reason(e: &serde_json::Error) -> String {
+    let message = e.to_string();
+
+    match message.rfind(" at line ") {
+        Some(idx) if e.line() > 0 => message[..idx].to_string(),
+        _ => message
+    }
+}
+
+
+/// Sends a result downstream. Returns whether to keep reading.
+type Sender<'a, T> = dyn FnMut(Result<T, Reject>) -> bool + 'a;
+
+
+/// Reads CSV rows one at a time, keeping each row's position and raw text so
+/// that a row that fails to deserialize can be reported.
+fn read_csv<T: Resource>(
+    reader: impl Read,
+    input_field_separator: char,
+    input_has_header: bool,
+    file: &str,
+    rules: &Rules,
+    send: &mut Sender<T>,
+)
+{
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(input_field_separator as u8)
+        .has_headers(input_has_header)
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+
+    let mut record = csv::StringRecord::new();
+
+    let headers = match input_has_header {
+        false => None,
+        true => match reader.headers() {
+            Ok(headers) => Some(headers.clone()),
+            Err(e) => {
+                send(Err(csv_reject::<T>(file, &e, &record, input_field_separator, None, 0)));
+                return
+            }
+        }
+    };
+    let header_records = headers.is_some() as u64;
+
+    loop {
+        let result = match reader.read_record(&mut record) {
+            Ok(false) => break,
+            Ok(true) => deserialize_csv::<T>(&record, headers.as_ref()),
+            Err(e) => Err(e),
+        };
+
+        let result = match result {
+            Err(e) => Err(csv_reject::<T>(
+                file, &e, &record, input_field_separator, headers.as_ref(), header_records)),
+            Ok(person) => match invalid(&person, rules) {
+                None => Ok(person),
+                Some(reject) => Err(Reject {
+                    field: reject.field,
+                    reason: reject.reason,
+                    ..csv_row(file, &record, input_field_separator, record.position(), header_records)
+                }),
+            },
+        };
+
+        if !send(result) {
+            break;
+        }
+    }
+}
+
+
+/// The columns of a CSV row that aren't fields of a `T`, as they were read
+fn csv_extra<T: Resource>(record: &csv::StringRecord, headers: &csv::StringRecord) -> Extra {
+    headers.iter()
+        .zip(record.iter())
+        .filter(|(name, _)| !T::struct_fields().contains(name))
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+
+fn csv_reject<T: Resource>(
+    file: &str,
+    e: &csv::Error,
+    record: &csv::StringRecord,
+    input_field_separator: char,
+    headers: Option<&csv::StringRecord>,
+    header_records: u64,
+) -> Reject
+{
+    let position = e.position().or_else(|| record.position());
+
+    let (field, reason) = match e.kind() {
+        csv::ErrorKind::Deserialize { err, .. } => {
+            let field = match err.field() {
+                Some(idx) => match headers {
+                    Some(headers) => headers.get(idx as usize).map(String::from),
+                    None => T::struct_fields().get(idx as usize).map(|f| f.to_string()),
+                },
+                None => failed_csv_field::<T>(record, headers)
+                    .or_else(|| field_from_message(&err.to_string())),
+            };
+            (field, err.kind().to_string())
+        },
+        csv::ErrorKind::UnequalLengths { expected_len, len, .. } =>
+            (None, format!("expected {} fields, found {}", expected_len, len)),
+        csv::ErrorKind::Utf8 { err, .. } => (None, err.to_string()),
+        _ => (None, e.to_string()),
+    };
+
+    Reject {
+        field,
+        reason,
+        fatal: e.is_io_error(),
+        ..csv_row(file, record, input_field_separator, position, header_records)
+    }
+}
+
+
+/// A reject for the row in `record`, with its position and raw text
+fn csv_row(
+    file: &str,
+    record: &csv::StringRecord,
+    input_field_separator: char,
+    position: Option<&csv::Position>,
+    header_records: u64,
+) -> Reject
+{
+    let raw = match record.is_empty() {
+        true => None,
+        false => Some(record.iter().collect::<Vec<&str>>().join(&input_field_separator.to_string())),
+    };
+
+    Reject {
+        file: file.to_string(),
+        record: position.map(|p| p.record() + 1 - header_records),
+        line: position.map(|p| p.line()),
+        byte: position.map(|p| p.byte()),
+        raw,
+        ..Reject::default()
+    }
+}
+
+
+/// The reject for a person that was read successfully but breaks any of the
+/// validation `rules`, without its position
+fn invalid<T: Resource>(person: &T, rules: &Rules) -> Option<Reject>
+{
+    let errors = person.validate(rules).err()?;
+
+    Some(Reject {
+        field: Some(errors.iter().map(|e| e.field.as_str()).collect::<Vec<&str>>().join(", ")),
+        reason: errors.iter().map(|e| e.reason.as_str()).collect::<Vec<&str>>().join("; "),
+        ..Reject::default()
+    })
+}
+
+
+/// csv only knows which field failed for its own parse errors. Others, e.g.,
+/// an invalid date, are located by replaying the row as a map of strings.
+fn failed_csv_field<T: Resource>(record: &csv::StringRecord, headers: Option<&csv::StringRecord>) -> Option<String>
+{
+    let names: Vec<&str> = match headers {
+        Some(headers) => headers.iter().collect(),
+        None => T::struct_fields().to_vec(),
+    };
+    let row = MapDeserializer::<_, serde::de::value::Error>::new(names.into_iter().zip(record.iter()));
+
+    json_field(serde_path_to_error::deserialize::<_, T>(row).err()?.path())
+}
+
+
+/// The field and reason a JSON object failed to deserialize with, without its position
+fn json_reject(e: serde_path_to_error::Error<serde_json::Error>) -> Reject {
+    Reject {
+        field: json_field(e.path()).or_else(|| field_from_message(&e.inner().to_string())),
+        reason: json_reason(e.inner()),
+        ..Reject::default()
+    }
+}
+
+
+/// The field a path points to, unless it's the whole record
+fn json_field(path: &serde_path_to_error::Path) -> Option<String> {
+    Some(path.to_string()).filter(|path| path != ".")
+}
+
+
+/// Reads a JSON array one element at a time. Elements don't have a line
+/// number, so a bad element is reported by its (1-based) index.
+fn read_json<T: Resource>(reader: impl Read, file: &str, rules: &Rules, send: &mut Sender<T>)
+{
+    let mut record = 0;
+
+    let result = serde_json::Deserializer::from_reader(reader)
+        .deserialize_seq(JsonArrayVisitor { each: |value: serde_json::Value| {
+            record += 1;
+
+            let result = match serde_path_to_error::deserialize::<_, T>(&value) {
+                Err(e) => Err(json_reject(e)),
+                Ok(person) => match invalid(&person, rules) {
+                    None => Ok(person),
+                    Some(reject) => Err(reject),
+                },
+            };
+
+            send(result.map_err(|reject| Reject {
+                file: file.to_string(),
+                record: Some(record),
+                raw: Some(value.to_string()),
+                ..reject
+            }))
+        }});
+
+    if let Err(e) = result {
+        send(Err(Reject {
+            file: file.to_string(),
+            line: Some(e.line() as u64).filter(|&line| line > 0),
+            reason: json_reason(&e),
+            fatal: true,
+            ..Reject::default()
+        }));
+    }
+}
+
+
+/// Reads newline-delimited JSON, one object per line. Blank lines are skipped.
+fn read_ndjson<T: Resource>(mut reader: impl BufRead, file: &str, rules: &Rules, send: &mut Sender<T>)
+{
+    let mut line = String::new();
+    let (mut line_number, mut byte, mut record) = (0, 0, 0);
+
+    loop {
+        line.clear();
+
+        let length = match reader.read_line(&mut line) {
+            Ok(0) => break,
+            Ok(length) => length,
+            Err(e) => {
+                send(Err(Reject {
+                    file: file.to_string(),
+                    line: Some(line_number + 1),
+                    byte: Some(byte),
+                    reason: e.to_string(),
+                    fatal: true,
+                    ..Reject::default()
+                }));
+                break
+            }
+        };
+
+        line_number += 1;
+        let start = byte;
+        byte += length as u64;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        record += 1;
+
+        let mut deserializer = serde_json::Deserializer::from_str(&line);
+        let result = match serde_path_to_error::deserialize::<_, T>(&mut deserializer) {
+            Err(e) => Err(json_reject(e)),
+            Ok(person) => match invalid(&person, rules) {
+                None => Ok(person),
+                Some(reject) => Err(reject),
+            },
+        };
+
+        let result = result.map_err(|reject| Reject {
+            file: file.to_string(),
+            record: Some(record),
+            line: Some(line_number),
+            byte: Some(start),
+            raw: Some(line.trim_end().to_string()),
+            ..reject
+        });
+
+        if !send(result) {
+            break;
+        }
+    }
+}
+
+
+/// Stream of people (or of any other `Resource`) read from one or more inputs,
+/// in order. Rows that can't be read are yielded as a `Reject`.
+pub type PeopleStream<T = Person> = Pin<Box<dyn Stream<Item = Result<T, Reject>> + Send>>;
+
+
+/// Maximum number of parsed people buffered ahead of the consumer, per input
+const STREAM_CAPACITY: usize = 1024;
+
+
+fn open_input(path: &Path) -> stdio::Result<Box<dyn Read + Send>>
+{
+    match path.to_str() {
+        Some("-") => Ok(Box::new(stdio::stdin())),
+        _ => Ok(Box::new(std::fs::File::open(path)?)),
+    }
+}
+
+
+/// Parses people from `reader` in the given format, one at a time, and passes
+/// each of them (or the reason it was rejected) to `each`. People that break
+/// any of the validation `rules` are rejected. Reading stops when `each`
+/// returns `false`, or after a fatal reject. `file` names the input in rejects.
+pub fn read_people<T: Resource>(
+    reader: impl BufRead,
+    input_format: Format,
+    input_field_separator: char,
+    input_has_header: bool,
+    rules: &Rules,
+    file: &str,
+    mut each: impl FnMut(Result<T, Reject>) -> bool,
+)
+{
+    let mut send = |result: Result<T, Reject>| {
+        let fatal = matches!(&result, Err(reject) if reject.fatal);
+        each(result) && !fatal
+    };
+
+    match input_format {
+        Format::Csv => read_csv(reader, input_field_separator, input_has_header, file, rules, &mut send),
+        Format::Json => read_json(reader, file, rules, &mut send),
+        Format::Ndjson => read_ndjson(reader, file, rules, &mut send),
+    }
+}
+
+
+/// Opens a single input and streams the people parsed from it. People that
+/// break any of the validation `rules` are rejected.
+///
+/// The input is read through a buffered reader on a blocking thread, which
+/// parses one person at a time into a bounded channel. Memory use therefore
+/// doesn't depend on the size of the input. Must be called from within a
+/// tokio runtime.
+pub fn read_input_file<T: Resource>(
+    input_format: Format,
+    input_field_separator: char,
+    input_has_header: bool,
+    rules: Arc<Rules>,
+    path: &Path,
+) -> stdio::Result<PeopleStream<T>>
+{
+    let reader = BufReader::new(open_input(path)?);
+    let file = path.display().to_string();
+    let (tx, rx) = mpsc::channel::<Result<T, Reject>>(STREAM_CAPACITY);
+
+    tokio::task::spawn_blocking(move || {
+        read_people(reader, input_format, input_field_separator, input_has_header, &rules, &file, |result| {
+            tx.blocking_send(result).is_ok()
+        })
+    });
+
+    Ok(Box::pin(stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|result| (result, rx))
+    })))
+}
+
+
+/// Describes how to read a list of input files. Each of the `*_mappings` lists is
+/// mapped onto the files in turn; any remaining unmapped files fall back to the
+/// corresponding default.
+#[derive(Debug, Clone)]
+pub struct InputOpts {
+    pub format: Format,
+    pub format_mappings: Vec<Format>,
+    pub field_separator: char,
+    pub field_separator_mappings: Vec<char>,
+    pub has_header: bool,
+    pub has_header_mappings: Vec<bool>,
+    /// Validation rules every person read must satisfy, none by default
+    pub rules: Arc<Rules>,
+}
+
+
+impl Default for InputOpts {
+    fn default() -> Self {
+        Self {
+            format: Format::Csv,
+            format_mappings: vec![],
+            field_separator: ',',
+            field_separator_mappings: vec![],
+            has_header: false,
+            has_header_mappings: vec![],
+            rules: Arc::new(Rules::none()),
+        }
+    }
+}
+
+
+/// Opens every file and streams the people parsed from them, file by file,
+/// using the format, separator and header settings mapped to each file by `opts`.
+pub fn read_input_stream<T: Resource>(
+    files: &[PathBuf],
+    opts: &InputOpts,
+) -> stdio::Result<PeopleStream<T>>
+{
+    Ok(Box::pin(stream::iter(read_input_streams(files, opts)?).flatten()))
+}
+
+
+/// Where a row of input was read from
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Origin {
+    /// Input the row was read from (`-` for stdin)
+    pub file: String,
+    /// 1-based index of the row among the input's records (not counting a header)
+    pub record: u64,
+}
+
+
+/// Stream of people read from one or more inputs, in order, each with its `Origin`
+pub type OriginStream<T = Person> = Pin<Box<dyn Stream<Item = (Origin, Result<T, Reject>)> + Send>>;
+
+
+/// Like `read_input_stream`, but yields the `Origin` of every row along with it
+pub fn read_input_stream_with_origins<T: Resource>(
+    files: &[PathBuf],
+    opts: &InputOpts,
+) -> stdio::Result<OriginStream<T>>
+{
+    Ok(Box::pin(stream::iter(read_input_streams_with_origins(files, opts)?).flatten()))
+}
+
+
+/// Like `read_input_stream_with_origins`, but a separate stream for each of the `files`, in order
+pub fn read_input_streams_with_origins<T: Resource>(
+    files: &[PathBuf],
+    opts: &InputOpts,
+) -> stdio::Result<Vec<OriginStream<T>>>
+{
+    Ok(files.iter()
+        .zip(read_input_streams(files, opts)?)
+        .map(|(path, stream)| {
+            let file = path.display().to_string();
+            let stream: OriginStream<T> = Box::pin(stream.enumerate().map(move |(idx, result)| {
+                (Origin { file: file.clone(), record: idx as u64 + 1 }, result)
+            }));
+
+            stream
+        })
+        .collect())
+}
+
+
+/// A stream for each of the `files`, in order (see `read_input_stream`)
+fn read_input_streams<T: Resource>(
+    files: &[PathBuf],
+    opts: &InputOpts,
+) -> stdio::Result<Vec<PeopleStream<T>>>
+{
+
+    let mut input_format_mappings = VecDeque::from(opts.format_mappings.clone());
+    let mut input_field_separator_mappings = VecDeque::from(opts.field_separator_mappings.clone());
+    let mut input_has_header_mappings = opts.has_header_mappings.clone();
+    let mut streams: Vec<PeopleStream<T>> = vec![];
+
+    for path in files.iter() {
+
+        let input_format = match input_format_mappings.pop_front() {
+            None => opts.format,
+            Some(f) => f
+        };
+
+        let input_field_separator = match input_field_separator_mappings.pop_front() {
+            None => opts.field_separator,
+            Some(c) => c
+        };
+
+        let input_has_header = match input_has_header_mappings.pop() {
+            None => opts.has_header,
+            Some(b) => b
+        };
+
+        streams.push(read_input_file(
+            input_format,
+            input_field_separator,
+            input_has_header,
+            opts.rules.clone(),
+            path,
+        )?);
+    }
+
+    Ok(streams)
+}
+
+
+/// Drops (and logs) the results of a `PeopleStream` that failed to parse.
+pub fn valid_people<T: Resource>(stream: PeopleStream<T>) -> impl Stream<Item = T> + Send
+{
+    stream.filter_map(|result| future::ready(match result {
+        Err(reject) => {
+            log::warn!("Rejected {}", reject);
+            None
+        },
+        Ok(p) => Some(p)
+    }))
+}
+
+
+pub async fn read_input_files<T: Resource>(
+    files: &[PathBuf],
+    opts: &InputOpts,
+    people: &mut Vec<T>
+) -> io::Result<()>
+{
+    let mut stream = valid_people(read_input_stream(files, opts)?);
+
+    while let Some(person) = stream.next().await {
+        people.push(person);
+    }
+
+    Ok(())
+}
+
+
+mod tests;
@@ -0,0 +1,92 @@
+use std::{fmt, fs::File, io::{self, Write}, path::Path};
+use serde::Serialize;
+
+
+/// A row of input that couldn't be read as a `Person`, along with where it
+/// was found and why it was rejected.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Reject {
+    /// Input the row was read from (`-` for stdin)
+    pub file: String,
+    /// 1-based index of the row among the input's records (not counting a header)
+    pub record: Option<u64>,
+    /// 1-based line the row starts on
+    pub line: Option<u64>,
+    /// Byte offset the row starts at
+    pub byte: Option<u64>,
+    /// Name of the field that couldn't be read, when it's known
+    pub field: Option<String>,
+    pub reason: String,
+    /// The row as it was read
+    pub raw: Option<String>,
+    /// Whether the rest of the input can't be read either
+    #[serde(skip)]
+    pub fatal: bool,
+}
+
+
+impl fmt::Display for Reject {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.file)?;
+
+        match (self.line, self.record) {
+            (Some(line), _) => write!(f, ":{}", line)?,
+            (None, Some(record)) => write!(f, " (record {})", record)?,
+            _ => ()
+        }
+
+        if let Some(field) = &self.field {
+            write!(f, ": {}", field)?;
+        }
+
+        write!(f, ": {}", self.reason)
+    }
+}
+
+
+impl std::error::Error for Reject {}
+
+
+/// Extracts the field a serde error message is about, if it's one that names a
+/// field: "missing field `first_name`" or "unknown field `age`, expected ...".
+/// Other messages may quote values, e.g., "invalid type: integer `5`", so
+/// they don't name one.
+pub(crate) fn field_from_message(message: &str) -> Option<String> {
+    let rest = message.strip_prefix("missing field `")
+        .or_else(|| message.strip_prefix("unknown field `"))?;
+
+    rest.split_once('`').map(|(field, _)| field.to_string())
+}
+
+
+/// Writes a CSV report of rejected rows, with a header
+pub struct RejectWriter<W: Write> {
+    writer: csv::Writer<W>,
+}
+
+
+impl RejectWriter<File> {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(Self::new(File::create(path)?))
+    }
+}
+
+
+impl<W: Write> RejectWriter<W> {
+
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: csv::WriterBuilder::new()
+                .terminator(csv::Terminator::CRLF)
+                .from_writer(writer),
+        }
+    }
+
+    pub fn write(&mut self, reject: &Reject) -> io::Result<()> {
+        self.writer.serialize(reject).map_err(io::Error::from)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
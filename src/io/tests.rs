@@ -0,0 +1,464 @@
+#![allow(dead_code)]
+
+use super::*;
+
+
+const CSV: &str = "Brennan, Tom, tjb1982@gmail.com, red, 8/19/1982
+Fuller, Rachel
+Fuller, Rachel, , green, 8/10/1970
+";
+
+const JSON: &str = r#"[
+    {"last_name": "Brennan", "first_name": "Tom", "email": "tjb1982@gmail.com", "favorite_color": "red", "dob": "8/19/1982"},
+    {"last_name": "Fuller"},
+    {"last_name": "Fuller", "first_name": "Rachel", "email": "", "favorite_color": "green", "dob": "8/10/1970"}
+]"#;
+
+
+fn expected() -> Vec<Person> {
+    vec![
+        Person::new("Brennan", "Tom", "tjb1982@gmail.com", "red", "8/19/1982"),
+        Person::new("Fuller", "Rachel", "", "green", "8/10/1970"),
+    ]
+}
+
+
+fn write_input(name: &str, contents: &str) -> PathBuf {
+    let path = std::env::temp_dir()
+        .join(format!("homework-io-{}-{}", std::process::id(), name));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+
+async fn read(format: Format, contents: &str) -> (Vec<Person>, usize) {
+    let path = write_input(&format.to_string(), contents);
+    let results: Vec<Result<Person, Reject>> = read_input_file(format, ',', false, Arc::new(Rules::default()), &path)
+        .unwrap()
+        .collect()
+        .await;
+    std::fs::remove_file(&path).unwrap();
+
+    let errors = results.iter().filter(|r| r.is_err()).count();
+    let people = results.into_iter().filter_map(Result::ok).collect();
+
+    (people, errors)
+}
+
+
+#[tokio::test]
+async fn stream_csv() {
+    assert_eq!(read(Format::Csv, CSV).await, (expected(), 1));
+}
+
+
+#[tokio::test]
+async fn stream_json() {
+    assert_eq!(read(Format::Json, JSON).await, (expected(), 1));
+}
+
+
+#[tokio::test]
+async fn stream_ndjson() {
+    let ndjson: String = JSON.lines()
+        .map(|line| line.trim().trim_end_matches(','))
+        .filter(|line| line.starts_with('{'))
+        .map(|line| format!("{}\n\n", line))
+        .collect();
+
+    assert_eq!(read(Format::Ndjson, &ndjson).await, (expected(), 1));
+}
+
+
+#[tokio::test]
+async fn stream_malformed_json_stops() {
+    let (people, errors) = read(Format::Json, &JSON[..JSON.len() / 2]).await;
+
+    assert_eq!(people, expected()[..1]);
+    assert_eq!(errors, 1);
+}
+
+
+#[tokio::test]
+async fn stream_files_in_order() {
+    let paths = vec![
+        write_input("first.json", JSON),
+        write_input("second.csv", CSV),
+    ];
+    let opts = InputOpts {
+        format_mappings: vec![Format::Json],
+        ..InputOpts::default()
+    };
+    let mut people: Vec<Person> = vec![];
+
+    read_input_files(&paths, &opts, &mut people).await.unwrap();
+
+    for path in paths.iter() {
+        std::fs::remove_file(path).unwrap();
+    }
+
+    assert_eq!(people, [expected(), expected()].concat());
+}
+
+
+#[tokio::test]
+async fn stream_origins() {
+    let paths = vec![
+        write_input("origins.json", JSON),
+        write_input("origins.csv", CSV),
+    ];
+    let opts = InputOpts {
+        format_mappings: vec![Format::Json],
+        ..InputOpts::default()
+    };
+    let origins: Vec<(String, u64, bool)> = read_input_stream_with_origins::<Person>(&paths, &opts)
+        .unwrap()
+        .map(|(origin, result)| (origin.file, origin.record, result.is_ok()))
+        .collect()
+        .await;
+
+    for path in paths.iter() {
+        std::fs::remove_file(path).unwrap();
+    }
+
+    let (json, csv) = (paths[0].display().to_string(), paths[1].display().to_string());
+
+    assert_eq!(origins, vec![
+        (json.clone(), 1, true), (json.clone(), 2, false), (json, 3, true),
+        (csv.clone(), 1, true), (csv.clone(), 2, false), (csv, 3, true),
+    ]);
+}
+
+
+#[tokio::test]
+async fn missing_file() {
+    let path = PathBuf::from("/nonexistent/people.csv");
+
+    assert!(read_input_stream::<Person>(&[path], &InputOpts::default()).is_err());
+}
+
+
+#[cfg(test)]
+mod rejects {
+    use super::*;
+
+    async fn rejects(format: Format, has_header: bool, contents: &str) -> Vec<Reject> {
+        let path = write_input(&format!("rejects.{}", format), contents);
+        let results: Vec<Result<Person, Reject>> = read_input_file(format, ',', has_header, Arc::new(Rules::default()), &path)
+            .unwrap()
+            .collect()
+            .await;
+        std::fs::remove_file(&path).unwrap();
+
+        results.into_iter()
+            .filter_map(Result::err)
+            .map(|reject| Reject { file: String::new(), ..reject })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn csv_unequal_lengths() {
+        assert_eq!(rejects(Format::Csv, false, CSV).await, vec![Reject {
+            record: Some(2),
+            line: Some(2),
+            byte: Some(48),
+            reason: "expected 5 fields, found 2".to_string(),
+            raw: Some("Fuller,Rachel".to_string()),
+            ..Reject::default()
+        }]);
+    }
+
+    #[tokio::test]
+    async fn csv_invalid_date_with_header() {
+        let csv = "last_name,first_name,email,favorite_color,dob
+Brennan,Tom,tjb1982@gmail.com,red,8/19/1982
+Fuller,Rachel,,green,1970-13-10
+";
+        let rejects = rejects(Format::Csv, true, csv).await;
+
+        assert_eq!(rejects.len(), 1);
+        assert_eq!(rejects[0].record, Some(2));
+        assert_eq!(rejects[0].line, Some(3));
+        assert_eq!(rejects[0].field.as_deref(), Some("dob"));
+        assert!(rejects[0].reason.starts_with("invalid date \"1970-13-10\""));
+        assert_eq!(rejects[0].raw.as_deref(), Some("Fuller,Rachel,,green,1970-13-10"));
+        assert!(!rejects[0].fatal);
+    }
+
+    #[tokio::test]
+    async fn ndjson_positions() {
+        let ndjson = r#"{"last_name": "Brennan", "first_name": "Tom", "email": "", "favorite_color": "red", "dob": "8/19/1982"}
+
+{"last_name": "Fuller"}
+"#;
+
+        assert_eq!(rejects(Format::Ndjson, false, ndjson).await, vec![Reject {
+            record: Some(2),
+            line: Some(3),
+            byte: Some(105),
+            field: Some("first_name".to_string()),
+            reason: "missing field `first_name`".to_string(),
+            raw: Some(r#"{"last_name": "Fuller"}"#.to_string()),
+            ..Reject::default()
+        }]);
+    }
+
+    #[tokio::test]
+    async fn json_record_index() {
+        assert_eq!(rejects(Format::Json, false, JSON).await, vec![Reject {
+            record: Some(2),
+            field: Some("first_name".to_string()),
+            reason: "missing field `first_name`".to_string(),
+            raw: Some(r#"{"last_name":"Fuller"}"#.to_string()),
+            ..Reject::default()
+        }]);
+    }
+
+    #[tokio::test]
+    async fn json_element_not_an_object() {
+        let json = r#"[{"last_name": "Brennan", "first_name": "Tom", "email": "", "favorite_color": "red", "dob": "8/19/1982"}, 5]"#;
+        let rejects = rejects(Format::Json, false, json).await;
+
+        assert_eq!(rejects.len(), 1);
+        assert_eq!(rejects[0].record, Some(2));
+        assert_eq!(rejects[0].field, None);
+        assert!(rejects[0].reason.starts_with("invalid type: integer `5`"));
+        assert!(!rejects[0].fatal);
+    }
+
+    #[test]
+    fn field_from_serde_messages() {
+        use crate::io::reject::field_from_message;
+
+        assert_eq!(field_from_message("missing field `first_name`").as_deref(), Some("first_name"));
+        assert_eq!(field_from_message("unknown field `age`, expected `dob`").as_deref(), Some("age"));
+        assert_eq!(field_from_message("invalid type: integer `5`, expected struct Person"), None);
+    }
+
+    #[tokio::test]
+    async fn invalid_records() {
+        let ndjson = r#"{"last_name": "Brennan", "first_name": "", "email": "tjb1982", "favorite_color": "red", "dob": ""}
+"#;
+
+        assert_eq!(rejects(Format::Ndjson, false, ndjson).await, vec![Reject {
+            record: Some(1),
+            line: Some(1),
+            byte: Some(0),
+            field: Some("first_name, email".to_string()),
+            reason: "is required; \"tjb1982\" is not a valid email address".to_string(),
+            raw: Some(ndjson.trim_end().to_string()),
+            ..Reject::default()
+        }]);
+    }
+
+    #[tokio::test]
+    async fn malformed_json_is_fatal() {
+        let rejects = rejects(Format::Json, false, &JSON[..JSON.len() / 2]).await;
+
+        assert_eq!(rejects.len(), 1);
+        assert!(rejects[0].fatal);
+        assert!(rejects[0].line.is_some());
+    }
+
+    #[test]
+    fn display() {
+        let reject = Reject {
+            file: "people.csv".to_string(),
+            record: Some(2),
+            line: Some(3),
+            field: Some("dob".to_string()),
+            reason: "invalid date".to_string(),
+            ..Reject::default()
+        };
+
+        assert_eq!(reject.to_string(), "people.csv:3: dob: invalid date");
+    }
+
+    #[test]
+    fn write_report() {
+        let mut buffer = vec![];
+        let mut writer = RejectWriter::new(&mut buffer);
+
+        writer.write(&Reject {
+            file: "people.csv".to_string(),
+            record: Some(2),
+            line: Some(2),
+            byte: Some(48),
+            reason: "expected 5 fields, found 2".to_string(),
+            raw: Some("Fuller,Rachel".to_string()),
+            ..Reject::default()
+        }).unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "file,record,line,byte,field,reason,raw\r\n\
+             people.csv,2,2,48,,\"expected 5 fields, found 2\",\"Fuller,Rachel\"\r\n"
+        );
+    }
+}
+
+
+#[cfg(test)]
+mod external_sort {
+    use super::*;
+    use crate::io::external_sort::ExternalSorter;
+    use crate::sorting::{FieldsOrd, SortDirection, SortSpec};
+
+    fn create_people() -> Vec<Person> {
+        let colors = ["red", "green", "blue"];
+
+        (0..50)
+            .map(|i| Person::new(
+                &format!("Last{}", i % 7),
+                &format!("First{}", i),
+                "",
+                colors[i % colors.len()],
+                &format!("{}/{}/19{}", i % 12 + 1, i % 28 + 1, 50 + i % 5),
+            ))
+            .collect()
+    }
+
+    fn sort_externally(fields: &Vec<(&str, SortDirection)>, chunk_size: usize) -> Vec<Person> {
+        let sort = SortSpec::parse::<Person>(fields, false).unwrap();
+        let mut sorter = ExternalSorter::new(&sort, chunk_size, &std::env::temp_dir());
+
+        for person in create_people() {
+            sorter.push(person).unwrap();
+        }
+
+        sorter.finish().unwrap()
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    #[test]
+    fn matches_in_memory_sort() {
+        let fields = vec![
+            ("favorite_color", SortDirection::Asc),
+            ("last_name", SortDirection::Desc),
+        ];
+        let mut expected = create_people();
+
+        expected.sort_by(|a, b| a.cmp_order_by_fields(b, &fields));
+
+        for chunk_size in [1, 3, 16, 50, 1000].iter() {
+            assert_eq!(sort_externally(&fields, *chunk_size), expected, "chunk size: {}", chunk_size);
+        }
+    }
+
+    #[test]
+    fn empty_fields_use_plain_sort() {
+        let mut expected = create_people();
+
+        expected.sort();
+
+        assert_eq!(sort_externally(&vec![], 4), expected);
+    }
+
+    #[test]
+    fn other_resources() {
+        use serde::{Serialize, Deserialize};
+        use crate::filtering::FieldsFilter;
+        use crate::resource::Resource;
+        use crate::serialization::StructFields;
+
+        #[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, FieldsOrd, FieldsFilter, StructFields)]
+        struct Account {
+            number: String,
+            owner: String,
+        }
+
+        impl Resource for Account {}
+
+        let accounts: Vec<Account> = (0..20)
+            .map(|i| Account { number: format!("{:03}", (i * 7) % 20), owner: format!("Owner{}", i % 3) })
+            .collect();
+        let sort = SortSpec::parse::<Account>(&[("owner", SortDirection::Desc), ("number", SortDirection::Asc)], false).unwrap();
+        let mut sorter = ExternalSorter::new(&sort, 3, &std::env::temp_dir());
+
+        for account in accounts.iter().cloned() {
+            sorter.push(account).unwrap();
+        }
+
+        let mut expected = accounts;
+        expected.sort_by(|a, b| sort.cmp(a, b));
+
+        assert_eq!(sorter.finish().unwrap().map(Result::unwrap).collect::<Vec<Account>>(), expected);
+    }
+}
+
+
+#[cfg(test)]
+mod extra_columns {
+    use super::*;
+    use crate::person;
+
+    fn read_csv(has_header: bool, contents: &str) -> Vec<Person> {
+        let mut people = vec![];
+
+        person::extra::with_extra_columns(true, || {
+            read_people(contents.as_bytes(), Format::Csv, ',', has_header, &Rules::default(), "extra.csv", |result| {
+                people.push(result.unwrap());
+                true
+            })
+        });
+
+        people
+    }
+
+    #[test]
+    fn with_header() {
+        let people = read_csv(true, "department,last_name,first_name,email,favorite_color,dob,phone\n\
+            Sales,Brennan,Tom,tjb1982@gmail.com,red,8/19/1982,555-0100\n");
+
+        assert_eq!(people[0].last_name, "Brennan");
+        assert_eq!(people[0].extra.iter().collect::<Vec<_>>(), [("department", "Sales"), ("phone", "555-0100")]);
+    }
+
+    #[test]
+    fn without_header() {
+        let people = read_csv(false, "Brennan,Tom,tjb1982@gmail.com,red,8/19/1982,Sales\n");
+
+        assert_eq!(people[0].extra.get("column_6"), Some("Sales"));
+    }
+
+    #[test]
+    fn written_after_the_fields() {
+        let people = read_csv(true, "last_name,first_name,email,favorite_color,dob,department\n\
+            Brennan,Tom,tjb1982@gmail.com,red,8/19/1982,Sales\n\
+            Fuller,Rachel,,green,8/10/1970,\n");
+        let mut out = vec![];
+
+        assert_eq!(write_output::<_, Person>(&mut out, ',', true, &extra_columns(&people), &people).unwrap(), 2);
+        assert_eq!(String::from_utf8(out).unwrap(), "last_name,first_name,email,favorite_color,dob,department\r\n\
+            Brennan,Tom,tjb1982@gmail.com,red,8/19/1982,Sales\r\n\
+            Fuller,Rachel,,green,8/10/1970,\r\n");
+    }
+
+    #[test]
+    fn union_of_every_record() {
+        let mut people = read_csv(true, "last_name,first_name,email,favorite_color,dob\n\
+            Brennan,Tom,tjb1982@gmail.com,red,8/19/1982\n");
+        people.extend(read_csv(true, "last_name,first_name,email,favorite_color,dob,phone,department\n\
+            Fuller,Rachel,,green,8/10/1970,555-0100,Sales\n"));
+        let mut out = vec![];
+
+        assert_eq!(extra_columns(&people), ["phone", "department"]);
+        assert_eq!(write_output::<_, Person>(&mut out, ',', true, &extra_columns(&people), &people).unwrap(), 2);
+        assert_eq!(String::from_utf8(out).unwrap(), "last_name,first_name,email,favorite_color,dob,phone,department\r\n\
+            Brennan,Tom,tjb1982@gmail.com,red,8/19/1982,,\r\n\
+            Fuller,Rachel,,green,8/10/1970,555-0100,Sales\r\n");
+    }
+
+    #[test]
+    fn skips_columns_not_in_the_header() {
+        let people = read_csv(true, "last_name,first_name,email,favorite_color,dob,phone\n\
+            Fuller,Rachel,,green,8/10/1970,555-0100\n");
+        let mut out = vec![];
+
+        assert_eq!(write_output::<_, Person>(&mut out, ',', true, &[], &people).unwrap(), 0);
+        assert!(out.is_empty());
+    }
+}
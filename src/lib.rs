@@ -1,6 +1,15 @@
+// Lets `homework_derive`'s `::homework::...` paths resolve within the crate, too
+extern crate self as homework;
+
 pub mod person;
 pub mod serialization;
 pub mod sorting;
+pub mod filtering;
+pub mod validation;
+pub mod resource;
+pub mod dedupe;
+pub mod sets;
+pub mod stats;
 pub mod io;
 pub mod log;
 pub mod api;
@@ -0,0 +1,196 @@
+use std::{cell::Cell, fmt, iter::FromIterator, sync::atomic::{AtomicBool, Ordering}};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::{self, MapAccess, Visitor}, ser::SerializeMap};
+
+
+/// Columns of a record that aren't fields of a `Person`, e.g., `department`
+/// or `phone`, with their values as strings, in the order they were read.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Extra(Vec<(String, String)>);
+
+
+impl Extra {
+
+    pub fn get(&self, column: &str) -> Option<&str> {
+        self.0.iter()
+            .find(|(name, _)| name == column)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Sets the value of `column`, which is added after the others if it's new
+    pub fn insert(&mut self, column: String, value: String) {
+        match self.0.iter_mut().find(|(name, _)| *name == column) {
+            Some((_, old)) => *old = value,
+            None => self.0.push((column, value)),
+        }
+    }
+
+    pub fn remove(&mut self, column: &str) -> Option<String> {
+        let idx = self.0.iter().position(|(name, _)| name == column)?;
+        Some(self.0.remove(idx).1)
+    }
+
+    /// Keeps only the columns for which `keep` is true
+    pub fn retain(&mut self, mut keep: impl FnMut(&str) -> bool) {
+        self.0.retain(|(name, _)| keep(name));
+    }
+
+    pub fn columns(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(|(name, _)| name.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+
+impl FromIterator<(String, String)> for Extra {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(columns: I) -> Self {
+        let mut extra = Extra::default();
+
+        for (column, value) in columns {
+            extra.insert(column, value);
+        }
+
+        extra
+    }
+}
+
+
+impl Serialize for Extra {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.len()))?;
+
+        for (column, value) in self.iter() {
+            map.serialize_entry(column, value)?;
+        }
+
+        map.end()
+    }
+}
+
+
+/// The value of an extra column: any string, number or boolean, as a string
+struct Column(String);
+
+
+impl<'de> Deserialize<'de> for Column {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ColumnVisitor;
+
+        impl<'de> Visitor<'de> for ColumnVisitor {
+            type Value = Column;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a string, number or boolean")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Column, E> {
+                Ok(Column(value.to_string()))
+            }
+
+            fn visit_bool<E: de::Error>(self, value: bool) -> Result<Column, E> {
+                Ok(Column(value.to_string()))
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Column, E> {
+                Ok(Column(value.to_string()))
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Column, E> {
+                Ok(Column(value.to_string()))
+            }
+
+            fn visit_f64<E: de::Error>(self, value: f64) -> Result<Column, E> {
+                Ok(Column(value.to_string()))
+            }
+
+            fn visit_unit<E: de::Error>(self) -> Result<Column, E> {
+                Ok(Column(String::new()))
+            }
+        }
+
+        deserializer.deserialize_any(ColumnVisitor)
+    }
+}
+
+
+/// Reads the entries of a map as columns, e.g., the ones a `#[serde(flatten)]`
+/// struct has no field for, but only when they're kept; otherwise they're ignored.
+impl<'de> Deserialize<'de> for Extra {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ExtraVisitor;
+
+        impl<'de> Visitor<'de> for ExtraVisitor {
+            type Value = Extra;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a map of columns")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Extra, A::Error> {
+                let mut extra = Extra::default();
+                let keep = keep();
+
+                while let Some(column) = map.next_key::<String>()? {
+                    match keep {
+                        true => extra.insert(column, map.next_value::<Column>()?.0),
+                        false => { map.next_value::<de::IgnoredAny>()?; },
+                    }
+                }
+
+                Ok(extra)
+            }
+        }
+
+        deserializer.deserialize_map(ExtraVisitor)
+    }
+}
+
+
+/// Whether extra columns are kept by every thread, outside of `with_extra_columns`
+static DEFAULT: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static SCOPED: Cell<Option<bool>> = const { Cell::new(None) };
+}
+
+
+/// Sets whether people are read with their extra columns, i.e., whether
+/// `Person::extra` is filled in or left empty. Like the `DateFormats`, it's
+/// set out of band because serde's `Deserialize` can't take arguments.
+pub fn set_default(keep: bool) {
+    DEFAULT.store(keep, Ordering::Relaxed);
+}
+
+
+/// Whether extra columns are kept on this thread
+pub fn keep() -> bool {
+    SCOPED.with(|scoped| scoped.get()).unwrap_or_else(|| DEFAULT.load(Ordering::Relaxed))
+}
+
+
+/// Calls `f` with extra columns kept (or not) on the current thread.
+pub fn with_extra_columns<T>(keep: bool, f: impl FnOnce() -> T) -> T {
+
+    /// Restores the previous setting, even if `f` panics
+    struct Restore(Option<bool>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            SCOPED.with(|scoped| scoped.set(self.0));
+        }
+    }
+
+    let _restore = Restore(SCOPED.with(|scoped| scoped.replace(Some(keep))));
+
+    f()
+}
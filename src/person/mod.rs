@@ -1,14 +1,19 @@
-use std::{cmp::Ordering};
-use log::{warn};
 use serde::{Serialize, Deserialize};
 use chrono::NaiveDate;
 
-use crate::{serialization::{self, date_format}};
-use crate::sorting::{FieldsOrd, SortDirection};
+use crate::serialization::{StructFields, date_format::{self, DateFormats}};
+use crate::sorting::FieldsOrd;
+use crate::filtering::FieldsFilter;
+use crate::resource::Resource;
+use crate::validation::{FieldError, Rules};
 
+pub mod extra;
+pub use extra::Extra;
 
-/// `struct` representing a "record"
-#[derive(Debug, Clone, Eq, Ord, PartialEq, PartialOrd, Serialize, Deserialize)]
+
+/// `struct` representing a "record". Any other columns it's read with are kept
+/// in `extra` when `extra::keep()`, and written after its fields.
+#[derive(Debug, Clone, Default, Eq, Ord, PartialEq, PartialOrd, Serialize, Deserialize, FieldsOrd, FieldsFilter, StructFields)]
 pub struct Person {
     pub last_name: String,
     pub first_name: String,
@@ -17,46 +22,39 @@ pub struct Person {
 
     #[serde(with = "date_format")]
     pub dob: Option<NaiveDate>,
+
+    /// Columns that aren't any of the above, only read when `extra::keep()`
+    #[serde(flatten)]
+    #[fields(extra)]
+    pub extra: Extra,
 }
 
 
-/// Allows for the listing of a `Person`'s fields via serde
-/// See `src/serialization.rs` for more details.
-impl serialization::StructFieldDeserialize for Person {
+impl Resource for Person {
 
-    /// Returns a list of strings representing the fields
-    /// of a `Person`
-    fn struct_fields() -> &'static[&'static str] {
-        let mut fields = None;
-        
-        let _ = Self::deserialize(serialization::StructFieldsDeserializer {
-            fields: &mut fields
-        });
+    fn field_alias(field: &str) -> &str {
+        match field {
+            "name" => "last_name",
+            "color" => "favorite_color",
+            "birthdate" => "dob",
+            x => x
+        }
+    }
 
-        fields.unwrap()
+    fn keeps_extra() -> bool {
+        extra::keep()
     }
-}
 
+    fn extra(&self) -> Option<&Extra> {
+        Some(&self.extra)
+    }
 
-impl FieldsOrd for Person {
-
-    fn cmp_field(&self, b: &Self, field: &str, direction: &SortDirection) -> Ordering {
-        let ord = match field {
-            "first_name" => self.first_name.cmp(&b.first_name),
-            "last_name" => self.last_name.cmp(&b.last_name),
-            "email" => self.email.cmp(&b.email),
-            "favorite_color" => self.favorite_color.cmp(&b.favorite_color),
-            "dob" => self.dob.cmp(&b.dob),
-            _ => {
-                warn!("Field \"{}\" not found: ignoring.", field);
-                Ordering::Equal
-            }
-        };
-
-        match direction {
-            SortDirection::Desc => ord.reverse(),
-            _ => ord
-        }
+    fn extra_mut(&mut self) -> Option<&mut Extra> {
+        Some(&mut self.extra)
+    }
+
+    fn validate(&self, rules: &Rules) -> Result<(), Vec<FieldError>> {
+        rules.validate(self)
     }
 }
 
@@ -64,16 +62,19 @@ impl FieldsOrd for Person {
 impl Person {
 
     /// Convenience method for creating a `Person` from `&str` components.
+    /// `dob` may be in any of the configured input `DateFormats`, or the defaults.
     pub fn new(last_name: &str, first_name: &str, email: &str, favorite_color: &str, dob: &str) -> Person {
         Person {
             last_name: String::from(last_name),
             first_name: String::from(first_name),
             email: String::from(email),
             favorite_color: String::from(favorite_color),
-            dob: match date_format::date_from_str(&dob.to_string()) {
-                Ok(dob) => Some(dob),
-                _ => None
-            }
+            dob: date_format::date_from_str(dob)
+                .or_else(|_| date_format::with_formats(&DateFormats::default(), || {
+                    date_format::date_from_str(dob)
+                }))
+                .ok(),
+            extra: Extra::default(),
         }
     }
 }
@@ -1,5 +1,6 @@
 #![allow(dead_code)]
 
+use crate::sorting::SortDirection;
 use super::*;
 
 
@@ -17,7 +18,7 @@ fn new_person() {
     assert!(person.last_name.eq(last_name));
     assert!(person.email.eq(email));
     assert!(person.favorite_color.eq(favorite_color));
-    assert!(person.dob.eq(&Some(date_format::date_from_str(&dob.to_string()).unwrap())));
+    assert!(person.dob.eq(&Some(date_format::date_from_str(dob).unwrap())));
 }
 
 
@@ -100,6 +101,63 @@ mod sorting {
     }
 }
 
+mod filtering {
+
+    use super::*;
+    use crate::filtering::{FieldsFilter, Predicate};
+
+
+    fn select(predicates: &[&str]) -> Vec<String> {
+        let predicates: Vec<Predicate> = predicates.iter()
+            .map(|p| p.parse().unwrap())
+            .collect();
+
+        [
+            Person::new("Brennan","Tom", "tjb1982@gmail.com", "red", "8/19/1982"),
+            Person::new("Fuller","Rachel", "rachel@example.com", "green", "8/10/1970"),
+            Person::new("Brennan","Chester", "", "", ""),
+        ]
+            .iter()
+            .filter(|p| p.matches_all(&predicates))
+            .map(|p| p.first_name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn string_operators() {
+        assert_eq!(select(&["favorite_color=red"]), ["Tom"]);
+        assert_eq!(select(&["favorite_color!=red"]), ["Rachel", "Chester"]);
+        assert_eq!(select(&["last_name^=Bre"]), ["Tom", "Chester"]);
+        assert_eq!(select(&["email$=.com", "email*=example"]), ["Rachel"]);
+        assert_eq!(select(&["email~=@example.com$"]), ["Rachel"]);
+        assert_eq!(select(&["first_name<Rachel"]), ["Chester"]);
+    }
+
+    #[test]
+    fn date_operators() {
+        assert_eq!(select(&["dob>=1980-01-01"]), ["Tom"]);
+        assert_eq!(select(&["dob<8/19/1982"]), ["Rachel"]);
+        assert_eq!(select(&["dob="]), ["Chester"]);
+        assert_eq!(select(&["dob^=8/1"]), ["Tom", "Rachel"]);
+    }
+
+    #[test]
+    fn invalid_predicates() {
+        assert!("favorite_color".parse::<Predicate>().is_err());
+        assert!("=red".parse::<Predicate>().is_err());
+        assert!("email~=(".parse::<Predicate>().is_err());
+    }
+
+    #[test]
+    fn predicate_round_trip() {
+        let predicate: Predicate = "last_name^=Bre=nnan".parse().unwrap();
+
+        assert_eq!(predicate.field, "last_name");
+        assert_eq!(predicate.value, "Bre=nnan");
+        assert_eq!(predicate.to_string(), "last_name^=Bre=nnan");
+    }
+}
+
 #[allow(unused_imports)]
 mod struct_fields {
     use crate::serialization::StructFieldDeserialize;
@@ -117,3 +175,510 @@ mod struct_fields {
         assert!(fields[4].eq("dob"));
     }
 }
+
+
+#[cfg(test)]
+mod date_formats {
+    use chrono::NaiveDate;
+    use crate::serialization::date_format::{self, DateFormat, DateFormats};
+    use super::*;
+
+    fn formats(input: &[&str], output: &str) -> DateFormats {
+        DateFormats {
+            input: input.iter().map(|f| f.parse().unwrap()).collect(),
+            output: output.parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn defaults_read_iso() {
+        let person = Person::new("Brennan", "Tom", "", "", "1982-08-19");
+
+        assert_eq!(person.dob, NaiveDate::from_ymd_opt(1982, 8, 19));
+        assert_eq!(date_format::str_from_date(&person.dob.unwrap()), "8/19/1982");
+    }
+
+    #[test]
+    fn input_formats_tried_in_order() {
+        let dob = date_format::with_formats(&formats(&["%d/%m/%Y", "us"], "iso"), || {
+            date_format::date_from_str("8/1/1982").unwrap()
+        });
+
+        assert_eq!(dob, NaiveDate::from_ymd_opt(1982, 1, 8).unwrap());
+    }
+
+    #[test]
+    fn output_format() {
+        let person = Person::new("Brennan", "Tom", "", "", "8/19/1982");
+        let json = date_format::with_formats(&formats(&["us"], "%d %B %Y"), || {
+            serde_json::to_value(&person).unwrap()
+        });
+
+        assert_eq!(json["dob"], "19 August 1982");
+    }
+
+    #[test]
+    fn new_with_configured_format() {
+        let person = date_format::with_formats(&formats(&["%d.%m.%Y"], "iso"), || {
+            (Person::new("Brennan", "Tom", "", "", "19.8.1982"), Person::new("Brennan", "Tom", "", "", "8/19/1982"))
+        });
+
+        assert_eq!(person.0.dob, NaiveDate::from_ymd_opt(1982, 8, 19));
+        assert_eq!(person.1.dob, person.0.dob);
+    }
+
+    #[test]
+    fn invalid_dates_are_errors() {
+        assert!(serde_json::from_str::<Person>(r#"{
+            "last_name": "Brennan", "first_name": "Tom", "email": "", "favorite_color": "", "dob": "19.8.1982"
+        }"#).is_err());
+    }
+
+    #[test]
+    fn invalid_format() {
+        assert!("%Q".parse::<DateFormat>().is_err());
+        assert!("".parse::<DateFormat>().is_err());
+        assert_eq!("ISO".parse::<DateFormat>().unwrap().as_str(), date_format::ISO);
+    }
+
+    #[test]
+    fn new_falls_back_to_defaults() {
+        assert_eq!(DateFormats::new(vec![], None), DateFormats::default());
+    }
+
+    #[test]
+    fn no_input_formats() {
+        let dob = date_format::with_formats(&formats(&[], "us"), || date_format::date_from_str("8/19/1982"));
+
+        assert_eq!(dob, Err(date_format::DateError::NoFormats));
+    }
+}
+
+
+#[cfg(test)]
+mod validation {
+    use chrono::NaiveDate;
+    use crate::validation::{FieldError, Rules};
+    use super::*;
+
+    fn fields(result: Result<(), Vec<FieldError>>) -> Vec<String> {
+        result.err().unwrap_or_default().into_iter().map(|e| e.field).collect()
+    }
+
+    #[test]
+    fn valid_by_default() {
+        let person = Person::new("Brennan", "Tom", "tjb1982@gmail.com", "red", "8/19/1982");
+
+        assert_eq!(Rules::default().validate(&person), Ok(()));
+        assert_eq!(Rules::default().validate(&Person::new("Brennan", "Tom", "", "", "")), Ok(()));
+    }
+
+    #[test]
+    fn required_fields() {
+        let person = Person::new("", "Tom", "", "red", "");
+        let rules = Rules {
+            required: vec!["last_name".into(), "dob".into()],
+            ..Rules::default()
+        };
+
+        assert_eq!(fields(rules.validate(&person)), vec!["last_name", "dob"]);
+    }
+
+    #[test]
+    fn required_extra_columns() {
+        let mut person = Person::new("Brennan", "Tom", "", "red", "");
+        let rules = Rules { required: vec!["department".into()], ..Rules::none() };
+
+        // Without extra columns, it isn't a field the person has
+        assert_eq!(fields(rules.validate(&person)), vec!["department"]);
+
+        person.extra.insert("department".into(), "Sales".into());
+
+        assert_eq!(fields(crate::person::extra::with_extra_columns(true, || rules.validate(&person))), Vec::<String>::new());
+    }
+
+    #[test]
+    fn none_by_default() {
+        assert_eq!(*crate::io::InputOpts::default().rules, Rules::none());
+        assert_eq!(Rules::none().validate(&Person::new("", "", "june", "", "")), Ok(()));
+    }
+
+    #[test]
+    fn email_syntax() {
+        for email in ["tjb1982", "@gmail.com", "tjb@gmail", "tjb@gmail.", "t jb@gmail.com", "a@b@c.com"].iter() {
+            let person = Person::new("Brennan", "Tom", email, "red", "");
+            assert_eq!(fields(Rules::default().validate(&person)), vec!["email"], "{}", email);
+        }
+    }
+
+    #[test]
+    fn dob_range() {
+        let rules = Rules {
+            dob_min: NaiveDate::from_ymd_opt(1900, 1, 1),
+            ..Rules::default()
+        };
+
+        assert_eq!(fields(rules.validate(&Person::new("Brennan", "Tom", "", "", "12/31/1899"))), vec!["dob"]);
+        assert_eq!(fields(rules.validate(&Person::new("Brennan", "Tom", "", "", "1/1/9999"))), vec!["dob"]);
+        assert_eq!(Rules::none().validate(&Person::new("", "", "", "", "1/1/9999")), Ok(()));
+    }
+
+    #[test]
+    fn allowed_colors() {
+        let rules = Rules {
+            favorite_colors: Some(vec!["red".into(), "green".into()]),
+            ..Rules::default()
+        };
+
+        assert_eq!(rules.validate(&Person::new("Brennan", "Tom", "", "Red", "")), Ok(()));
+        assert_eq!(fields(rules.validate(&Person::new("Brennan", "Tom", "", "mauve", ""))), vec!["favorite_color"]);
+    }
+
+    #[test]
+    fn from_file() {
+        let path = std::env::temp_dir().join(format!("homework-rules-{}.json", std::process::id()));
+
+        std::fs::write(&path, r#"{"required": ["email"], "favorite_colors": ["red"]}"#).unwrap();
+        let rules = Rules::from_file::<Person>(&path).unwrap();
+
+        std::fs::write(&path, r#"{"required": ["shoe_size"]}"#).unwrap();
+        let unknown = Rules::from_file::<Person>(&path);
+
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(rules.required, vec!["email"]);
+        assert!(rules.email);
+        assert_eq!(rules.favorite_colors, Some(vec!["red".to_string()]));
+        assert!(unknown.is_err());
+    }
+}
+
+
+#[cfg(test)]
+mod extra_columns {
+    use crate::filtering::{FieldsFilter, Predicate};
+    use crate::sorting::{FieldsOrd, SortDirection};
+    use super::*;
+
+    const JSON: &str = r#"[
+        {"last_name": "Brennan", "first_name": "Tom", "email": "", "favorite_color": "red", "dob": "", "department": "Sales", "floor": 3},
+        {"last_name": "Fuller", "first_name": "Rachel", "email": "", "favorite_color": "green", "dob": "", "department": "Engineering"},
+        {"last_name": "Doe", "first_name": "Jane", "email": "", "favorite_color": "", "dob": ""}
+    ]"#;
+
+    fn people(keep: bool) -> Vec<Person> {
+        extra::with_extra_columns(keep, || serde_json::from_str(JSON).unwrap())
+    }
+
+    #[test]
+    fn dropped_by_default() {
+        assert!(people(false).iter().all(|person| person.extra.is_empty()));
+    }
+
+    #[test]
+    fn kept_in_order() {
+        let people = people(true);
+
+        assert_eq!(people[0].extra.iter().collect::<Vec<_>>(), [("department", "Sales"), ("floor", "3")]);
+        assert_eq!(serde_json::to_string(&people[1]).unwrap(), "{\"last_name\":\"Fuller\",\"first_name\":\"Rachel\",\
+            \"email\":\"\",\"favorite_color\":\"green\",\"dob\":\"\",\"department\":\"Engineering\"}");
+        assert_eq!(serde_json::to_string(&people[2]).unwrap(), "{\"last_name\":\"Doe\",\"first_name\":\"Jane\",\
+            \"email\":\"\",\"favorite_color\":\"\",\"dob\":\"\"}");
+    }
+
+    #[test]
+    fn sorted_as_strings() {
+        let mut people = people(true);
+
+        extra::with_extra_columns(true, || people.sort_by(|a, b| a.cmp_field(b, "department", &SortDirection::Asc)));
+        assert_eq!(people.iter().map(|p| p.last_name.as_str()).collect::<Vec<_>>(), ["Doe", "Fuller", "Brennan"]);
+    }
+
+    #[test]
+    fn filtered_as_strings() {
+        let predicates: Vec<Predicate> = vec!["department^=Eng".parse().unwrap()];
+        let selected = extra::with_extra_columns(true, || {
+            people(true).into_iter()
+                .filter(|p| p.matches_all(&predicates))
+                .map(|p| p.last_name)
+                .collect::<Vec<_>>()
+        });
+
+        assert_eq!(selected, ["Fuller"]);
+    }
+}
+
+
+#[cfg(test)]
+mod collations {
+    use std::cmp::Ordering;
+    use crate::sorting::{Collation, Modifiers, modified, modifiers};
+    use super::*;
+
+    fn sorted(names: &[&str], field: &str) -> Vec<String> {
+        let mut people: Vec<Person> = names.iter().map(|name| Person::new(name, "", "", "", "")).collect();
+
+        people.sort_by(|a, b| a.cmp_order_by_fields(b, &vec![(field, SortDirection::Asc)]));
+        people.into_iter().map(|p| p.last_name).collect()
+    }
+
+    #[test]
+    fn binary_by_default() {
+        assert_eq!(sorted(&["de la Cruz", "Zimmer", "Élodie"], "last_name"), ["Zimmer", "de la Cruz", "Élodie"]);
+        assert_eq!(sorted(&["de la Cruz", "Zimmer", "Élodie"], "last_name:binary"), ["Zimmer", "de la Cruz", "Élodie"]);
+    }
+
+    #[test]
+    fn nocase() {
+        assert_eq!(sorted(&["de la Cruz", "Zimmer", "Élodie", "Ada"], "last_name:nocase"), ["Ada", "de la Cruz", "Zimmer", "Élodie"]);
+    }
+
+    #[test]
+    fn noaccent() {
+        assert_eq!(sorted(&["Zimmer", "Élodie", "Elias", "Emile"], "last_name:noaccent"), ["Elias", "Élodie", "Emile", "Zimmer"]);
+        assert_eq!(Collation::NoAccent.cmp("Ångström", "angstrom"), Ordering::Equal);
+    }
+
+    #[test]
+    fn natural() {
+        assert_eq!(sorted(&["Unit 10", "unit 9", "Unit 09b", "Unit 1"], "last_name:natural"), ["Unit 1", "unit 9", "Unit 09b", "Unit 10"]);
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(modifiers("last_name:natural"), Ok(("last_name", Modifiers { collation: Collation::Natural, nulls: None })));
+        assert_eq!(modifiers("last_name"), Ok(("last_name", Modifiers::default())));
+        assert!(modifiers("last_name:fancy").is_err());
+        assert_eq!(modified("last_name", Modifiers { collation: Collation::NoCase, nulls: None }), "last_name:nocase");
+        assert_eq!(modified("last_name", Modifiers::default()), "last_name");
+    }
+}
+
+
+#[cfg(test)]
+mod nulls {
+    use crate::sorting::{Collation, Modifiers, Nulls, modified, modifiers};
+    use super::*;
+
+    fn sorted(field: &str, direction: SortDirection) -> Vec<String> {
+        let mut people = vec![
+            Person::new("Brennan", "Tom", "", "red", "8/19/1982"),
+            Person::new("Fuller", "Rachel", "", "", ""),
+            Person::new("Smith", "Jan", "", "blue", "1/1/1990"),
+        ];
+
+        people.sort_by(|a, b| a.cmp_order_by_fields(b, &vec![(field, direction)]));
+        people.into_iter().map(|p| p.first_name).collect()
+    }
+
+    #[test]
+    fn compared_by_default() {
+        assert_eq!(sorted("dob", SortDirection::Asc), ["Rachel", "Tom", "Jan"]);
+        assert_eq!(sorted("dob", SortDirection::Desc), ["Jan", "Tom", "Rachel"]);
+    }
+
+    #[test]
+    fn last_either_way() {
+        assert_eq!(sorted("dob:nulls=last", SortDirection::Asc), ["Tom", "Jan", "Rachel"]);
+        assert_eq!(sorted("dob:nulls=last", SortDirection::Desc), ["Jan", "Tom", "Rachel"]);
+        assert_eq!(sorted("favorite_color:nulls=last", SortDirection::Asc), ["Jan", "Tom", "Rachel"]);
+    }
+
+    #[test]
+    fn first_either_way() {
+        assert_eq!(sorted("dob:nulls=first", SortDirection::Desc), ["Rachel", "Jan", "Tom"]);
+        assert_eq!(sorted("favorite_color:nocase:nulls=first", SortDirection::Desc), ["Rachel", "Tom", "Jan"]);
+    }
+
+    #[test]
+    fn parse() {
+        let (field, nulls) = modifiers("dob:nulls=last").unwrap();
+
+        assert_eq!((field, nulls.nulls), ("dob", Some(Nulls::Last)));
+        assert_eq!(modified("email", Modifiers { collation: Collation::NoCase, nulls: Some(Nulls::First) }), "email:nocase:nulls=first");
+        assert!(modifiers("dob:nulls=middle").is_err());
+    }
+}
+
+
+#[cfg(test)]
+mod sort_spec {
+    use crate::serialization::StructFieldDeserialize;
+    use crate::sorting::{Collation, SortSpec, SortSpecError, did_you_mean};
+    use super::*;
+
+    #[test]
+    fn parsed() {
+        let spec = SortSpec::parse::<Person>(&[("last_name:nocase", SortDirection::Desc), ("dob", SortDirection::Asc)], false).unwrap();
+
+        assert_eq!(spec.keys()[0].field, "last_name");
+        assert_eq!(spec.keys()[0].modifiers.collation, Collation::NoCase);
+        assert_eq!(spec.fields(), [("last_name:nocase", SortDirection::Desc), ("dob", SortDirection::Asc)]);
+    }
+
+    #[test]
+    fn unknown_field() {
+        let e = SortSpec::parse::<Person>(&[("favourite_colour", SortDirection::Asc)], false).unwrap_err();
+
+        match &e {
+            SortSpecError::UnknownField(unknown) => assert_eq!(unknown.suggestion(), Some("favorite_color")),
+            e => panic!("unexpected error: {:?}", e),
+        }
+        assert_eq!(e.to_string(), "Field \"favourite_colour\" not found. Did you mean \"favorite_color\"? \
+            Available fields: last_name, first_name, email, favorite_color, dob");
+
+        // Extra columns can have any name.
+        assert!(SortSpec::parse::<Person>(&[("department", SortDirection::Asc)], true).is_ok());
+    }
+
+    #[test]
+    fn extra_column_no_longer_kept() {
+        let spec = SortSpec::parse::<Person>(&[("department", SortDirection::Asc), ("last_name", SortDirection::Asc)], true).unwrap();
+        let (a, b) = (Person::new("Brennan", "Tom", "", "", ""), Person::new("Fuller", "Rachel", "", "", ""));
+
+        crate::person::extra::with_extra_columns(false, || {
+            assert_eq!(spec.cmp(&a, &b), std::cmp::Ordering::Less);
+            assert_eq!(spec.keys()[0].cmp(&a, &b), std::cmp::Ordering::Equal);
+            assert_eq!(spec.try_cmp(&a, &b).unwrap_err().field, "department");
+        });
+        crate::person::extra::with_extra_columns(true, || {
+            assert_eq!(spec.try_cmp(&a, &b), Ok(std::cmp::Ordering::Less));
+        });
+    }
+
+    #[test]
+    fn invalid_modifier() {
+        let e = SortSpec::parse::<Person>(&[("dob:nulls=middle", SortDirection::Asc)], false).unwrap_err();
+
+        assert!(matches!(e, SortSpecError::InvalidModifier { ref field, .. } if field == "dob:nulls=middle"));
+    }
+
+    #[test]
+    fn suggestions() {
+        let fields = Person::struct_fields().iter().copied();
+
+        assert_eq!(did_you_mean("lastname", fields.clone()), Some("last_name"));
+        assert_eq!(did_you_mean("phone", fields.clone()), None);
+        // Equally close to both
+        assert_eq!(did_you_mean("dsc", ["asc", "desc"]), None);
+    }
+
+    #[test]
+    fn strict_direction() {
+        assert_eq!("desc".parse::<SortDirection>(), Ok(SortDirection::Desc));
+        assert_eq!("descending".parse::<SortDirection>(), Err("Unknown sort direction \"descending\". Expected \"asc\" or \"desc\"".to_string()));
+        assert_eq!("dessc".parse::<SortDirection>(), Err("Unknown sort direction \"dessc\". Did you mean \"desc\"?".to_string()));
+    }
+}
+
+
+#[cfg(test)]
+mod derive {
+    use std::cmp::Ordering;
+    use serde::Deserialize;
+    use crate::serialization::{StructFieldDeserialize, StructFields};
+    use crate::sorting::{Collation, FieldsOrd, SortDirection, UnknownField};
+    use super::Person;
+
+    #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize, FieldsOrd, StructFields)]
+    #[serde(rename_all = "camelCase")]
+    struct Address {
+        street_name: String,
+        #[serde(rename = "no")]
+        number: u32,
+        unit: Option<String>,
+        #[serde(skip)]
+        geocoded: bool,
+    }
+
+    fn address(street_name: &str, number: u32, unit: Option<&str>) -> Address {
+        Address { street_name: street_name.into(), number, unit: unit.map(String::from), geocoded: false }
+    }
+
+    #[test]
+    fn struct_fields_follow_serde() {
+        let mut fields = None;
+        let _ = Address::deserialize(crate::serialization::StructFieldsDeserializer { fields: &mut fields });
+
+        assert_eq!(Address::struct_fields(), ["streetName", "no", "unit"]);
+        assert_eq!(Address::struct_fields(), fields.unwrap());
+    }
+
+    #[test]
+    fn compares_by_serde_names() {
+        let (a, b) = (address("elm Street", 10, None), address("Elm Street", 9, Some("2B")));
+
+        assert_eq!(a.cmp_field(&b, "no", &SortDirection::Asc), Ordering::Greater);
+        assert_eq!(a.cmp_field(&b, "streetName", &SortDirection::Desc), Ordering::Less);
+        assert_eq!(a.cmp_field_collated(&b, "streetName", &SortDirection::Asc, Collation::NoCase), Ordering::Equal);
+        assert_eq!(a.cmp_order_by_fields(&b, &vec![("streetName:nocase", SortDirection::Asc), ("no", SortDirection::Asc)]), Ordering::Greater);
+        assert_eq!(a.cmp_order_by_fields(&b, &vec![("unit:nulls=last", SortDirection::Asc)]), Ordering::Greater);
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        let (a, b) = (address("Elm Street", 10, None), address("Elm Street", 9, None));
+        let unknown = UnknownField { field: "number".into(), available: Address::struct_fields() };
+
+        assert_eq!(a.try_cmp_field(&b, "number", &SortDirection::Asc, Collation::Binary), Err(unknown.clone()));
+        assert_eq!(a.try_cmp_order_by_fields(&b, &[("streetName", SortDirection::Asc), ("number", SortDirection::Asc)]), Err(unknown.clone()));
+        assert_eq!(a.try_cmp_order_by_fields(&b, &[("no", SortDirection::Asc), ("number", SortDirection::Asc)]), Ok(Ordering::Greater));
+        assert_eq!(unknown.to_string(), "Field \"number\" not found. Available fields: streetName, no, unit");
+    }
+
+    #[test]
+    fn person_falls_back_to_extra_columns() {
+        let (a, b) = (Person::new("Brennan", "Tom", "", "", ""), Person::new("Fuller", "Rachel", "", "", ""));
+
+        assert_eq!(Person::struct_fields(), ["last_name", "first_name", "email", "favorite_color", "dob"]);
+        crate::person::extra::with_extra_columns(true, || {
+            assert_eq!(a.try_cmp_field(&b, "department", &SortDirection::Asc, Collation::Binary), Ok(Ordering::Equal));
+            assert!(a.is_null("department") && a.is_null("dob") && !a.is_null("last_name"));
+        });
+    }
+
+    #[test]
+    fn person_without_extra_columns_has_unknown_fields() {
+        let (a, b) = (Person::new("Brennan", "Tom", "", "", ""), Person::new("Fuller", "Rachel", "", "", ""));
+        let unknown = UnknownField { field: "department".into(), available: Person::struct_fields() };
+
+        crate::person::extra::with_extra_columns(false, || {
+            assert_eq!(a.try_cmp_field(&b, "department", &SortDirection::Asc, Collation::Binary), Err(unknown.clone()));
+            assert_eq!(a.try_cmp_order_by_fields(&b, &[("department", SortDirection::Asc)]), Err(unknown));
+            assert_eq!(a.cmp_order_by_fields(&b, &vec![("department", SortDirection::Asc), ("last_name", SortDirection::Asc)]), Ordering::Less);
+            assert!(!a.is_null("department"));
+        });
+    }
+
+    #[test]
+    fn extra_columns_follow_the_resource() {
+        use serde::Serialize;
+        use crate::filtering::{FieldValue, FieldsFilter};
+        use crate::person::Extra;
+        use crate::resource::Resource;
+
+        /// Keeps its extra columns whether or not people keep theirs
+        #[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, FieldsOrd, FieldsFilter, StructFields)]
+        struct Tagged {
+            name: String,
+            #[serde(flatten)]
+            #[fields(extra)]
+            tags: Extra,
+        }
+
+        impl Resource for Tagged {
+            fn keeps_extra() -> bool {
+                true
+            }
+        }
+
+        let tagged = |name: &str, team: &str| Tagged { name: name.into(), tags: vec![("team".to_string(), team.to_string())].into_iter().collect() };
+        let (a, b) = (tagged("a", "red"), tagged("b", "blue"));
+
+        crate::person::extra::with_extra_columns(false, || {
+            assert_eq!(a.try_cmp_field(&b, "team", &SortDirection::Asc, Collation::Binary), Ok(Ordering::Greater));
+            assert!(matches!(a.field_value("team"), Some(FieldValue::Str("red"))));
+            assert!(a.is_null("colour"));
+        });
+    }
+}
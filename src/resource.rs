@@ -0,0 +1,74 @@
+use std::{borrow::Cow, fmt};
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::filtering::{FieldValue, FieldsFilter};
+use crate::person::Extra;
+use crate::serialization::{StructFieldDeserialize, date_format};
+use crate::sorting::FieldsOrd;
+use crate::validation::{FieldError, Rules};
+
+
+/// Trait implemented by the types of record that can be read and written by
+/// `io`, and served by the API as a resource (see `api::filters::resource`),
+/// e.g., `Person`. A resource is sortable, filterable and (de)serializable, all
+/// of which can be derived, and every one of its methods has a default:
+///
+/// ```ignore
+/// #[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, FieldsOrd, FieldsFilter, StructFields)]
+/// struct Account {
+///     number: String,
+///     owner: String,
+/// }
+///
+/// impl Resource for Account {}
+/// ```
+///
+/// `Default` is needed for a template of the record: a `Cursor` decodes its
+/// position into one, and `stats::GroupBy::parse` tells the date fields from
+/// the others by their values in one.
+pub trait Resource: FieldsOrd + FieldsFilter + StructFieldDeserialize + Serialize + DeserializeOwned
+    + Default + Clone + fmt::Debug + Send + Sync + 'static
+{
+    /// The field that an alias accepted by the API stands for, e.g., `name` for `last_name`
+    fn field_alias(field: &str) -> &str {
+        field
+    }
+
+    /// Whether fields other than `struct_fields()` are read and can be selected
+    /// by, i.e., kept as extra columns (see `person::extra`)
+    fn keeps_extra() -> bool {
+        false
+    }
+
+    /// The extra columns of the record, if it has any
+    fn extra(&self) -> Option<&Extra> {
+        None
+    }
+
+    fn extra_mut(&mut self) -> Option<&mut Extra> {
+        None
+    }
+
+    /// Checks the record against the `rules`. Only the `required` fields are
+    /// checked, unless the rules are about the record's fields (see `Person`).
+    fn validate(&self, rules: &Rules) -> Result<(), Vec<FieldError>> {
+        rules.validate_required(self)
+    }
+
+    /// The values of a delimited row for the record: its fields, as given by
+    /// `field_value` with dates in the current output format, followed by its
+    /// values of the extra `columns` (empty where it doesn't have one).
+    fn csv_record<'a>(&'a self, columns: &'a [String]) -> Vec<Cow<'a, str>> {
+        let fields = Self::struct_fields().iter().map(|field| match self.field_value(field) {
+            Some(FieldValue::Str(value)) => value.into(),
+            Some(FieldValue::Date(Some(date))) => date_format::str_from_date(&date).into(),
+            Some(FieldValue::Date(None)) | None => "".into(),
+        });
+
+        let extra = columns.iter().map(|column| {
+            self.extra().and_then(|extra| extra.get(column)).unwrap_or_default().into()
+        });
+
+        fields.chain(extra).collect()
+    }
+}
@@ -1,7 +1,10 @@
 use serde::{Deserializer, de, forward_to_deserialize_any};
 
+pub use homework_derive::StructFields;
+
 /// Trait implemented by `struct`s wanting to use `serde` to
-/// deserialize their fields into a list of strings.
+/// deserialize their fields into a list of strings, either with
+/// `StructFieldsDeserializer` or with `#[derive(StructFields)]`.
 pub trait StructFieldDeserialize {
     fn struct_fields() -> &'static[&'static str];
 }
@@ -52,22 +55,191 @@ impl<'de> Deserializer<'de> for StructFieldsDeserializer<'de> {
 
 /// module provided to serde via its `with` directive (i.e., when
 /// annotating `struct` fields as deriving `Serialize` and/or `Deserialize`.
-/// It also contains some helper functions: `date_from_str` and 
-/// `date_to_str` to encapsulate the FORMAT.
+/// It also contains some helper functions: `date_from_str` and
+/// `str_from_date` to encapsulate the `DateFormats`.
+///
+/// Since serde's `with` functions can't take arguments, the formats are
+/// configured out of band: once for the process with `set_default`, and for a
+/// single (synchronous) (de)serialization on the current thread with `with_formats`.
 pub mod date_format {
+    use std::{cell::RefCell, fmt, str::FromStr, sync::RwLock};
     use serde::{self, Serializer, Deserializer, Deserialize};
-    use chrono::{NaiveDate, ParseResult};
+    use chrono::{NaiveDate, ParseError, format::{Item, StrftimeItems}};
+
+    /// E.g., 8/19/1982
+    pub const US: &str = "%-m/%-d/%Y";
+
+    /// ISO-8601, e.g., 1982-08-19
+    pub const ISO: &str = "%Y-%m-%d";
+
+
+    /// A `strftime` format, validated when parsed. `iso` and `us` are accepted
+    /// as names for `ISO` and `US`.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct DateFormat(String);
+
+
+    impl DateFormat {
+        pub fn as_str(&self) -> &str {
+            &self.0
+        }
+    }
+
+
+    impl FromStr for DateFormat {
+        type Err = String;
+
+        fn from_str(format: &str) -> Result<Self, Self::Err> {
+            let format = match format.to_lowercase().as_str() {
+                "iso" => ISO,
+                "us" => US,
+                _ => format,
+            };
+
+            if format.is_empty() || StrftimeItems::new(format).any(|item| item == Item::Error) {
+                return Err(format!("Invalid date format \"{}\"", format))
+            }
+
+            Ok(DateFormat(format.to_string()))
+        }
+    }
+
+
+    impl fmt::Display for DateFormat {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str(&self.0)
+        }
+    }
+
+
+    /// The formats dates are parsed with (tried in order) and rendered with.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct DateFormats {
+        pub input: Vec<DateFormat>,
+        pub output: DateFormat,
+    }
+
+
+    impl DateFormats {
+
+        /// Falls back to the default input formats when `input` is empty, and
+        /// the default output format when `output` is `None`.
+        pub fn new(input: Vec<DateFormat>, output: Option<DateFormat>) -> Self {
+            let default = Self::default();
+
+            Self {
+                input: if input.is_empty() { default.input } else { input },
+                output: output.unwrap_or(default.output),
+            }
+        }
+    }
+
+
+    /// Reads `US` and `ISO` dates, and writes `US` dates.
+    impl Default for DateFormats {
+        fn default() -> Self {
+            Self {
+                input: vec![DateFormat(US.into()), DateFormat(ISO.into())],
+                output: DateFormat(US.into()),
+            }
+        }
+    }
+
+
+    /// Why a date couldn't be read with the input formats in effect
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum DateError {
+        /// There are no input formats to read dates with
+        NoFormats,
+        /// The date matched none of the formats; the error is from the last one
+        Invalid(ParseError),
+    }
 
-    const FORMAT: &'static str = "%-m/%-d/%Y";
 
+    impl fmt::Display for DateError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                Self::NoFormats => f.write_str("no input date formats configured"),
+                Self::Invalid(e) => e.fmt(f),
+            }
+        }
+    }
+
+
+    impl std::error::Error for DateError {}
+
+
+    /// The formats used when nothing else has been set; `None` until `set_default`
+    static DEFAULT: RwLock<Option<DateFormats>> = RwLock::new(None);
+
+    thread_local! {
+        static SCOPED: RefCell<Option<DateFormats>> = const { RefCell::new(None) };
+    }
+
+
+    /// Sets the formats used by every thread, outside of `with_formats`.
+    pub fn set_default(formats: DateFormats) {
+        *DEFAULT.write().unwrap() = Some(formats);
+    }
+
+
+    /// The formats currently in effect on this thread
+    pub fn current() -> DateFormats {
+        in_effect(DateFormats::clone)
+    }
+
+
+    /// Calls `f` with the formats currently in effect, without cloning them
+    fn in_effect<T>(f: impl FnOnce(&DateFormats) -> T) -> T {
+        SCOPED.with(|scoped| match scoped.borrow().as_ref() {
+            Some(formats) => f(formats),
+            None => match DEFAULT.read().unwrap().as_ref() {
+                Some(formats) => f(formats),
+                None => f(&DateFormats::default()),
+            }
+        })
+    }
+
+
+    /// Calls `f` with `formats` in effect on the current thread.
+    pub fn with_formats<T>(formats: &DateFormats, f: impl FnOnce() -> T) -> T {
 
-    pub fn date_from_str(s: &String) -> ParseResult<NaiveDate> {
-        NaiveDate::parse_from_str(s.as_str(), FORMAT)
+        /// Restores the previous formats, even if `f` panics
+        struct Restore(Option<DateFormats>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                SCOPED.with(|scoped| *scoped.borrow_mut() = self.0.take());
+            }
+        }
+
+        let _restore = Restore(SCOPED.with(|scoped| scoped.replace(Some(formats.clone()))));
+
+        f()
+    }
+
+
+    /// Parses `s` with each of the current input formats in turn, returning
+    /// the error from the last one if none of them match.
+    pub fn date_from_str(s: &str) -> Result<NaiveDate, DateError> {
+        in_effect(|formats| {
+            let mut result = Err(DateError::NoFormats);
+
+            for format in formats.input.iter() {
+                result = NaiveDate::parse_from_str(s, format.as_str()).map_err(DateError::Invalid);
+
+                if result.is_ok() {
+                    break;
+                }
+            }
+
+            result
+        })
     }
 
 
     pub fn str_from_date(d: &NaiveDate) -> String {
-        d.format(FORMAT).to_string()
+        in_effect(|formats| d.format(formats.output.as_str()).to_string())
     }
     
 
@@ -90,18 +262,14 @@ pub mod date_format {
         where D: Deserializer<'de>
     {
         let s = String::deserialize(deserializer)?;
-        let d = match date_from_str(&s) {
-            Ok(d) => Some(d),
-            Err(e) if e.to_string().eq("premature end of input") => {
+
+        match date_from_str(&s) {
+            Ok(d) => Ok(Some(d)),
+            Err(e) if s.trim().is_empty() => {
                 log::trace!("{}: missing optional field", e);
-                None
+                Ok(None)
             },
-            Err(e) => {
-                log::error!("{}", e);
-                None
-            }
-        };
-        
-        Ok(d)
+            Err(e) => Err(serde::de::Error::custom(format!("invalid date \"{}\": {}", s, e)))
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,200 @@
+use std::{collections::{HashMap, HashSet, VecDeque}, fmt, io::{self, Write}};
+use serde::Serialize;
+
+use crate::dedupe::{fields, key_value, value};
+use crate::io::{csv_header, csv_output_record};
+use crate::person::{Extra, Person};
+use crate::resource::Resource;
+
+
+/// What identifies a person across inputs: the values of the key fields (see
+/// `dedupe::key_value`), or the values of every field and extra column if any
+/// of those is empty
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    Fields(Vec<String>),
+    Record(Vec<String>),
+}
+
+
+impl Key {
+    fn of<T: Resource>(person: &T, key: &[String]) -> Self {
+        let values: Vec<String> = key.iter().map(|field| key_value(person, field)).collect();
+
+        match values.is_empty() || values.iter().any(String::is_empty) {
+            true => {
+                let mut columns: Vec<String> = person.extra().into_iter()
+                    .flat_map(Extra::iter)
+                    .map(|(column, value)| format!("{}={}", column, value.trim().to_lowercase()))
+                    .collect();
+                columns.sort();
+
+                Key::Record(T::struct_fields().iter()
+                    .map(|field| key_value(person, field))
+                    .chain(columns)
+                    .collect())
+            },
+            false => Key::Fields(values),
+        }
+    }
+}
+
+
+/// How a person differs between two inputs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    Added,
+    Removed,
+    Changed,
+}
+
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match self {
+            Change::Added => "added",
+            Change::Removed => "removed",
+            Change::Changed => "changed",
+        })
+    }
+}
+
+
+/// A person added, removed or changed between two inputs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Difference<T = Person> {
+    pub change: Change,
+    /// Fields whose values changed, separated by spaces
+    pub fields: String,
+    /// The new person, or the old one if it was removed
+    #[serde(flatten)]
+    pub person: T,
+}
+
+
+/// The differences between `old` and `new`, matching people by `key`, e.g.,
+/// `["email"]`. People with the same key are matched in the order they were
+/// read; people missing any of the key fields only match identical people.
+/// Changes come in the order of `new`, followed by what was removed, in the
+/// order of `old`.
+pub fn diff<T: Resource>(old: Vec<T>, new: Vec<T>, key: &[String]) -> Vec<Difference<T>> {
+    let mut index: HashMap<Key, VecDeque<usize>> = HashMap::new();
+    let mut old: Vec<Option<T>> = old.into_iter().map(Some).collect();
+
+    for (idx, person) in old.iter().enumerate() {
+        if let Some(person) = person {
+            index.entry(Key::of(person, key)).or_default().push_back(idx);
+        }
+    }
+
+    let mut differences = vec![];
+
+    for person in new.into_iter() {
+        let matched = index.get_mut(&Key::of(&person, key))
+            .and_then(VecDeque::pop_front)
+            .and_then(|idx| old[idx].take());
+
+        let was = match matched {
+            Some(was) => was,
+            None => {
+                differences.push(Difference { change: Change::Added, fields: String::new(), person });
+                continue
+            }
+        };
+
+        let fields: Vec<String> = fields(&was, &person).into_iter()
+            .filter(|field| value(&was, field) != value(&person, field))
+            .collect();
+
+        if !fields.is_empty() {
+            differences.push(Difference { change: Change::Changed, fields: fields.join(" "), person });
+        }
+    }
+
+    differences.extend(old.into_iter().flatten().map(|person| {
+        Difference { change: Change::Removed, fields: String::new(), person }
+    }));
+
+    differences
+}
+
+
+/// Every person of `inputs` by `key`: the first of the people sharing a key
+/// is kept, in the order they were read
+pub fn union<T: Resource>(inputs: Vec<Vec<T>>, key: &[String]) -> Vec<T> {
+    let mut seen = HashSet::new();
+
+    inputs.into_iter()
+        .flatten()
+        .filter(|person| seen.insert(Key::of(person, key)))
+        .collect()
+}
+
+
+/// The people of the first of the `inputs` whose `key` is found in every other
+/// input. Only the first of the people sharing a key is kept.
+pub fn intersect<T: Resource>(inputs: Vec<Vec<T>>, key: &[String]) -> Vec<T> {
+    let mut inputs = inputs.into_iter();
+    let first = inputs.next().unwrap_or_default();
+    let others: Vec<HashSet<Key>> = inputs
+        .map(|people| people.iter().map(|person| Key::of(person, key)).collect())
+        .collect();
+    let mut seen = HashSet::new();
+
+    first.into_iter()
+        .filter(|person| {
+            let key = Key::of(person, key);
+            others.iter().all(|keys| keys.contains(&key)) && seen.insert(key)
+        })
+        .collect()
+}
+
+
+/// Writes `differences` as delimited rows: the change, the changed fields and
+/// the person, like `io::write_output` writes it, with the extra `columns`,
+/// e.g., the `io::extra_columns` of all of the people. Returns how many were written.
+pub fn write_differences<W: Write, T: Resource>(
+    writer: W,
+    output_field_separator: char,
+    output_has_header: bool,
+    columns: &[String],
+    differences: impl IntoIterator<Item = Difference<T>>,
+) -> io::Result<usize>
+{
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(output_field_separator as u8)
+        .terminator(csv::Terminator::CRLF)
+        .from_writer(writer);
+
+    let mut header = output_has_header;
+    let mut written = 0;
+
+    for difference in differences.into_iter() {
+        let record = match csv_output_record(&difference.person, columns) {
+            Ok(record) => record,
+            Err(e) => {
+                log::warn!("Problem serializing difference: {}", e);
+                continue
+            }
+        };
+
+        if std::mem::take(&mut header) {
+            writer.write_record(["change", "fields"].iter().copied().chain(csv_header::<T>(columns)))?;
+        }
+
+        let change = difference.change.to_string();
+
+        writer.write_record([change.as_str(), difference.fields.as_str()].iter()
+            .copied()
+            .chain(record.iter().map(|value| value.as_ref())))?;
+        written += 1;
+    }
+
+    writer.flush()?;
+
+    Ok(written)
+}
+
+
+mod tests;
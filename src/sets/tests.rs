@@ -0,0 +1,178 @@
+#![allow(dead_code)]
+
+use super::*;
+
+
+fn key(fields: &[&str]) -> Vec<String> {
+    fields.iter().map(|field| field.to_string()).collect()
+}
+
+
+fn old() -> Vec<Person> {
+    vec![
+        Person::new("Brennan", "Tom", "tjb1982@gmail.com", "red", "8/19/1982"),
+        Person::new("Fuller", "Rachel", "rf@example.com", "green", "8/10/1970"),
+        Person::new("Smith", "Jan", "", "blue", "1/1/1990"),
+    ]
+}
+
+
+fn new() -> Vec<Person> {
+    vec![
+        Person::new("Brennan", "Thomas", "TJB1982@gmail.com", "blue", "8/19/1982"),
+        Person::new("Smith", "Jan", "", "blue", "1/1/1990"),
+        Person::new("Doe", "Jane", "jd@example.com", "", ""),
+    ]
+}
+
+
+#[cfg(test)]
+mod differences {
+    use super::*;
+
+    #[test]
+    fn by_key() {
+        assert_eq!(diff(old(), new(), &key(&["email"])), vec![
+            Difference { change: Change::Changed, fields: "first_name email favorite_color".into(), person: new()[0].clone() },
+            Difference { change: Change::Added, fields: "".into(), person: new()[2].clone() },
+            Difference { change: Change::Removed, fields: "".into(), person: old()[1].clone() },
+        ]);
+    }
+
+    #[test]
+    fn by_record() {
+        let differences = diff(old(), new(), &[]);
+
+        assert_eq!(differences.iter().map(|d| d.change).collect::<Vec<Change>>(), vec![
+            Change::Added, Change::Added, Change::Removed, Change::Removed,
+        ]);
+        assert!(diff(old(), old(), &[]).is_empty());
+    }
+
+    #[test]
+    fn extra_columns() {
+        let with = |phone: &str| {
+            let mut people = old();
+            people[1].extra.insert("phone".into(), phone.into());
+            people
+        };
+
+        assert_eq!(diff(with("555-0100"), with("555-0199"), &key(&["email"])), vec![
+            Difference { change: Change::Changed, fields: "phone".into(), person: with("555-0199")[1].clone() },
+        ]);
+        assert_eq!(diff(with("555-0100"), with("555-0199"), &[]).len(), 2);
+        assert!(diff(with("555-0100"), with("555-0100"), &[]).is_empty());
+    }
+
+    #[test]
+    fn as_csv() {
+        let mut out = vec![];
+        let differences = diff(old(), new(), &key(&["email"]));
+
+        assert_eq!(write_differences(&mut out, ',', true, &[], differences).unwrap(), 3);
+        assert_eq!(String::from_utf8(out).unwrap(), "change,fields,last_name,first_name,email,favorite_color,dob\r\n\
+            changed,first_name email favorite_color,Brennan,Thomas,TJB1982@gmail.com,blue,8/19/1982\r\n\
+            added,,Doe,Jane,jd@example.com,,\r\n\
+            removed,,Fuller,Rachel,rf@example.com,green,8/10/1970\r\n");
+    }
+
+    #[test]
+    fn as_csv_with_extra_columns() {
+        let mut new = new();
+        new[2].extra.insert("phone".into(), "555-0100".into());
+        let differences = diff(old(), new, &key(&["email"]));
+        let columns = crate::io::extra_columns(differences.iter().map(|difference| &difference.person));
+        let mut out = vec![];
+
+        assert_eq!(write_differences(&mut out, ',', true, &columns, differences).unwrap(), 3);
+        assert_eq!(String::from_utf8(out).unwrap(), "change,fields,last_name,first_name,email,favorite_color,dob,phone\r\n\
+            changed,first_name email favorite_color,Brennan,Thomas,TJB1982@gmail.com,blue,8/19/1982,\r\n\
+            added,,Doe,Jane,jd@example.com,,,555-0100\r\n\
+            removed,,Fuller,Rachel,rf@example.com,green,8/10/1970,\r\n");
+    }
+
+    #[test]
+    fn as_json() {
+        let difference = &diff(old(), new(), &key(&["email"]))[1];
+
+        assert_eq!(serde_json::to_string(difference).unwrap(), "{\"change\":\"added\",\"fields\":\"\",\
+            \"last_name\":\"Doe\",\"first_name\":\"Jane\",\"email\":\"jd@example.com\",\"favorite_color\":\"\",\"dob\":\"\"}");
+    }
+}
+
+
+#[cfg(test)]
+mod operations {
+    use super::*;
+
+    #[test]
+    fn union_by_key() {
+        assert_eq!(union(vec![old(), new()], &key(&["email"])), vec![
+            old()[0].clone(), old()[1].clone(), old()[2].clone(), new()[2].clone(),
+        ]);
+    }
+
+    #[test]
+    fn intersect_by_key() {
+        assert_eq!(intersect(vec![old(), new()], &key(&["email"])), vec![old()[0].clone(), old()[2].clone()]);
+        assert_eq!(intersect(vec![new(), old(), vec![]], &key(&["email"])), vec![]);
+        assert_eq!(intersect(vec![old(), new()], &[]), vec![old()[2].clone()]);
+    }
+}
+
+
+#[cfg(test)]
+mod other_resources {
+    use serde::{Serialize, Deserialize};
+    use crate::filtering::FieldsFilter;
+    use crate::serialization::StructFields;
+    use crate::sorting::FieldsOrd;
+    use super::*;
+
+    #[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, FieldsOrd, FieldsFilter, StructFields)]
+    struct Account {
+        number: String,
+        owner: String,
+    }
+
+    impl Resource for Account {}
+
+    fn account(number: &str, owner: &str) -> Account {
+        Account { number: number.into(), owner: owner.into() }
+    }
+
+    #[test]
+    fn diff_by_key() {
+        let old = vec![account("001", "Tom"), account("002", "Rachel")];
+        let new = vec![account("001", "Thomas"), account("003", "Jan")];
+        let differences = diff(old.clone(), new.clone(), &key(&["number"]));
+
+        assert_eq!(differences, vec![
+            Difference { change: Change::Changed, fields: "owner".into(), person: new[0].clone() },
+            Difference { change: Change::Added, fields: "".into(), person: new[1].clone() },
+            Difference { change: Change::Removed, fields: "".into(), person: old[1].clone() },
+        ]);
+
+        let mut out = vec![];
+
+        assert_eq!(write_differences(&mut out, ',', true, &[], differences).unwrap(), 3);
+        assert_eq!(String::from_utf8(out).unwrap(), "change,fields,number,owner\r\n\
+            changed,owner,001,Thomas\r\n\
+            added,,003,Jan\r\n\
+            removed,,002,Rachel\r\n");
+    }
+
+    #[test]
+    fn union_and_intersect() {
+        let inputs = || vec![
+            vec![account("001", "Tom"), account("002", "Rachel")],
+            vec![account("002", "Rachel F."), account("003", "Jan")],
+        ];
+
+        assert_eq!(union(inputs(), &key(&["number"])), vec![
+            account("001", "Tom"), account("002", "Rachel"), account("003", "Jan"),
+        ]);
+        assert_eq!(intersect(inputs(), &key(&["number"])), vec![account("002", "Rachel")]);
+        assert_eq!(intersect(inputs(), &[]), vec![]);
+    }
+}
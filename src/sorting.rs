@@ -1,8 +1,14 @@
-use std::{cmp::Ordering, convert::Infallible, str::FromStr, string::ParseError};
-use serde::Deserialize;
+use std::{cmp::Ordering, error::Error, fmt, iter::Peekable, str::{Chars, FromStr}};
+use chrono::NaiveDate;
+use serde::{Serialize, Deserialize};
+use unicode_normalization::{UnicodeNormalization, char::is_combining_mark};
 
+use crate::serialization::StructFieldDeserialize;
 
-#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub use homework_derive::FieldsOrd;
+
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 #[serde(rename_all = "lowercase")]
 pub enum SortDirection {
     Asc,
@@ -10,64 +16,579 @@ pub enum SortDirection {
 }
 
 
+/// How strings are compared when sorting by a field, given after it, e.g.,
+/// `last_name:nocase`. Dates are compared the same way whatever the collation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Collation {
+    /// Byte by byte, so that `Zimmer` comes before `de la Cruz`, and `Élodie` after both
+    #[default]
+    Binary,
+    /// Ignoring case
+    NoCase,
+    /// Ignoring case and accents, e.g., `Élodie` next to `Elodie`
+    NoAccent,
+    /// Ignoring case, with runs of digits compared as numbers, e.g., `Unit 9` before `Unit 10`
+    Natural,
+}
+
+
+impl FromStr for Collation {
+    type Err = String;
+
+    fn from_str(collation: &str) -> Result<Self, Self::Err> {
+        match collation {
+            "binary" => Ok(Collation::Binary),
+            "nocase" => Ok(Collation::NoCase),
+            "noaccent" => Ok(Collation::NoAccent),
+            "natural" => Ok(Collation::Natural),
+            _ => Err(format!("Unknown collation \"{}\". Expected \"binary\", \"nocase\", \"noaccent\" or \"natural\"", collation)),
+        }
+    }
+}
+
+
+impl fmt::Display for Collation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match self {
+            Collation::Binary => "binary",
+            Collation::NoCase => "nocase",
+            Collation::NoAccent => "noaccent",
+            Collation::Natural => "natural",
+        })
+    }
+}
+
+
+/// The next run of ASCII digits of `chars`, without its leading zeros
+fn digits(chars: &mut Peekable<Chars>) -> String {
+    let mut digits = String::new();
+
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        if c != '0' || !digits.is_empty() {
+            digits.push(c);
+        }
+    }
+
+    digits
+}
+
+
+fn cmp_natural(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+
+    loop {
+        let ord = match (a.peek(), b.peek()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (x, y) = (digits(&mut a), digits(&mut b));
+                x.len().cmp(&y.len()).then_with(|| x.cmp(&y))
+            },
+            (Some(&x), Some(&y)) => {
+                a.next();
+                b.next();
+                x.to_lowercase().cmp(y.to_lowercase())
+            },
+        };
+
+        if ord.is_ne() {
+            return ord
+        }
+    }
+}
+
+
+impl Collation {
+
+    pub fn cmp(&self, a: &str, b: &str) -> Ordering {
+        match self {
+            Collation::Binary => a.cmp(b),
+            Collation::NoCase => a.chars().flat_map(char::to_lowercase).cmp(b.chars().flat_map(char::to_lowercase)),
+            Collation::NoAccent => {
+                let fold = |s: &str| s.nfd().filter(|&c| !is_combining_mark(c)).flat_map(char::to_lowercase).collect::<String>();
+                fold(a).cmp(&fold(b))
+            },
+            Collation::Natural => cmp_natural(a, b),
+        }
+    }
+}
+
+
+/// Where the sort puts records whose value of a field is null, i.e., a missing
+/// date or an empty string, whatever the direction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Nulls {
+    First,
+    Last,
+}
+
+
+/// How a sort field is compared, given after it, e.g., `last_name:nocase` or
+/// `dob:nulls=last`. Without `nulls`, nulls are compared like any other value,
+/// i.e., a missing date or an empty string comes first ascending and last descending.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Modifiers {
+    pub collation: Collation,
+    pub nulls: Option<Nulls>,
+}
+
+
+impl Modifiers {
+
+    /// Sets one modifier: a collation, `nulls=first` or `nulls=last`
+    pub fn set(&mut self, modifier: &str) -> Result<(), String> {
+        match modifier {
+            "nulls=first" => self.nulls = Some(Nulls::First),
+            "nulls=last" => self.nulls = Some(Nulls::Last),
+            _ => self.collation = modifier.parse().map_err(|_| format!(
+                "Unknown modifier \"{}\". Expected a collation (\"binary\", \"nocase\", \"noaccent\" or \"natural\"), \"nulls=first\" or \"nulls=last\"",
+                modifier
+            ))?,
+        }
+
+        Ok(())
+    }
+}
+
+
+impl fmt::Display for Modifiers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.collation != Collation::Binary {
+            write!(f, ":{}", self.collation)?;
+        }
+
+        match self.nulls {
+            Some(Nulls::First) => write!(f, ":nulls=first"),
+            Some(Nulls::Last) => write!(f, ":nulls=last"),
+            None => Ok(()),
+        }
+    }
+}
+
+
+/// Splits a sort field into the field and its modifiers, e.g., `last_name:nocase:nulls=last`
+/// into `last_name`, `Collation::NoCase` and `Nulls::Last`
+pub fn modifiers(field: &str) -> Result<(&str, Modifiers), String> {
+    let mut parts = field.split(':');
+    let field = parts.next().unwrap_or_default();
+    let mut modifiers = Modifiers::default();
+
+    for modifier in parts {
+        modifiers.set(modifier.trim())?;
+    }
+
+    Ok((field, modifiers))
+}
+
+
+/// The sort field of `field` with `modifiers`, the reverse of `modifiers()`
+pub fn modified(field: &str, modifiers: Modifiers) -> String {
+    format!("{}{}", field, modifiers)
+}
+
+
+/// A value of a field that `#[derive(FieldsOrd)]` can compare
+pub trait SortValue {
+    fn cmp_value(&self, b: &Self, collation: Collation) -> Ordering;
+
+    /// Whether it's null, for `Nulls` to put first or last
+    fn is_null(&self) -> bool {
+        false
+    }
+}
+
+
+impl SortValue for str {
+    fn cmp_value(&self, b: &Self, collation: Collation) -> Ordering {
+        collation.cmp(self, b)
+    }
+
+    fn is_null(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+
+impl SortValue for String {
+    fn cmp_value(&self, b: &Self, collation: Collation) -> Ordering {
+        self.as_str().cmp_value(b, collation)
+    }
+
+    fn is_null(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+
+/// `None` comes first, like `Option`'s `Ord`, and is null, as is a null value
+impl<T: SortValue> SortValue for Option<T> {
+    fn cmp_value(&self, b: &Self, collation: Collation) -> Ordering {
+        match (self, b) {
+            (Some(a), Some(b)) => a.cmp_value(b, collation),
+            (a, b) => a.is_some().cmp(&b.is_some()),
+        }
+    }
+
+    fn is_null(&self) -> bool {
+        match self {
+            Some(value) => value.is_null(),
+            None => true,
+        }
+    }
+}
+
+
+macro_rules! sort_value_by_ord {
+    ($($t:ty),*) => {
+        $(impl SortValue for $t {
+            fn cmp_value(&self, b: &Self, _collation: Collation) -> Ordering {
+                self.cmp(b)
+            }
+        })*
+    };
+}
+
+sort_value_by_ord!(bool, char, i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, NaiveDate);
+
+
+/// A field that a record doesn't have, with the ones it does
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownField {
+    pub field: String,
+    pub available: &'static [&'static str],
+}
+
+
+impl UnknownField {
+    pub fn new(field: &str, available: &'static [&'static str]) -> Self {
+        Self { field: field.to_string(), available }
+    }
+
+    /// The available field that `field` is most likely a typo of, if any
+    pub fn suggestion(&self) -> Option<&'static str> {
+        did_you_mean(&self.field, self.available.iter().copied())
+    }
+}
+
+
+impl fmt::Display for UnknownField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Field \"{}\" not found.", self.field)?;
+
+        if let Some(suggestion) = self.suggestion() {
+            write!(f, " Did you mean \"{}\"?", suggestion)?;
+        }
+
+        write!(f, " Available fields: {}", self.available.join(", "))
+    }
+}
+
+
+impl Error for UnknownField {}
+
+
+/// The number of single-character insertions, deletions or substitutions
+/// that turn `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, x) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+
+        for (j, &y) in b.iter().enumerate() {
+            let substitution = diagonal + (x != y) as usize;
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+
+/// The closest of `candidates` to `value`, if it's close enough to be a typo of
+/// it, i.e., at most a third of its characters (but at least one) away, and
+/// no other candidate is as close
+pub fn did_you_mean<'a>(value: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let max = (value.chars().count() / 3).max(1);
+    let mut closest: Vec<(usize, &str)> = candidates.into_iter()
+        .map(|candidate| (edit_distance(value, candidate), candidate))
+        .filter(|&(distance, _)| distance <= max)
+        .collect();
+
+    closest.sort_by_key(|&(distance, _)| distance);
+
+    match closest.as_slice() {
+        [(_, candidate)] => Some(candidate),
+        [(first, candidate), (second, _), ..] if first < second => Some(candidate),
+        _ => None,
+    }
+}
+
+
+/// A single field of a `SortSpec`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortKey {
+    pub field: String,
+    pub modifiers: Modifiers,
+    pub direction: SortDirection,
+    /// `field` with its `modifiers`, as compared by `FieldsOrd::cmp_order_by_fields`
+    modified: String,
+}
+
+
+impl SortKey {
+
+    /// Compares `a` and `b` by the field, with its modifiers and direction. The field
+    /// was checked by `SortSpec::parse`, but `T` may not have it after all, e.g., if
+    /// it's an extra column that is no longer kept (see `person::extra`).
+    pub fn try_cmp<T: FieldsOrd>(&self, a: &T, b: &T) -> Result<Ordering, UnknownField> {
+        cmp_by_field(a, b, &self.field, self.modifiers, &self.direction)
+    }
+
+    /// Like `try_cmp`. An unknown field is `Equal`, with a warning, as in `FieldsOrd::cmp_field`.
+    pub fn cmp<T: FieldsOrd>(&self, a: &T, b: &T) -> Ordering {
+        self.try_cmp(a, b).unwrap_or_else(|e| {
+            log::warn!("{} Ignoring it.", e);
+            Ordering::Equal
+        })
+    }
+}
+
+
+/// Why a sort field couldn't be part of a `SortSpec`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SortSpecError {
+    UnknownField(UnknownField),
+    InvalidModifier { field: String, reason: String },
+}
+
+
+impl fmt::Display for SortSpecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SortSpecError::UnknownField(e) => e.fmt(f),
+            SortSpecError::InvalidModifier { field, reason } => write!(f, "Invalid sort field \"{}\": {}", field, reason),
+        }
+    }
+}
+
+
+impl Error for SortSpecError {}
+
+
+/// The fields to sort records of a type by, in order, each of which is known to
+/// be one of its fields and to have valid modifiers. Parsing them up front means
+/// that a typo is an error, rather than a field that every record is `Equal` on.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SortSpec(Vec<SortKey>);
+
+
+impl SortSpec {
+
+    /// Parses each of `fields`, i.e., `field[:modifier...]` and its direction
+    /// (see `modifiers()`), checking the field is one of `T::struct_fields()`.
+    /// With `any_field`, e.g., when extra columns are kept, any field is allowed.
+    pub fn parse<T: StructFieldDeserialize>(fields: &[(&str, SortDirection)], any_field: bool)
+        -> Result<Self, SortSpecError>
+    {
+        fields.iter()
+            .map(|&(key, direction)| {
+                let (field, modifiers) = modifiers(key).map_err(|reason| SortSpecError::InvalidModifier {
+                    field: key.to_string(),
+                    reason,
+                })?;
+
+                if !any_field && !T::struct_fields().contains(&field) {
+                    return Err(SortSpecError::UnknownField(UnknownField::new(field, T::struct_fields())))
+                }
+
+                Ok(SortKey { field: field.to_string(), modifiers, direction, modified: modified(field, modifiers) })
+            })
+            .collect::<Result<Vec<SortKey>, SortSpecError>>()
+            .map(SortSpec)
+    }
+
+    /// Every one of `T::struct_fields()` in turn, ascending, e.g., how the API lists
+    /// records that aren't sorted
+    pub fn all<T: StructFieldDeserialize>() -> Self {
+        let fields: Vec<(&str, SortDirection)> = T::struct_fields().iter().map(|&field| (field, SortDirection::Asc)).collect();
+
+        Self::parse::<T>(&fields, false).unwrap_or_default()
+    }
+
+    pub fn keys(&self) -> &[SortKey] {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Compares `a` and `b` by each of the keys in turn (see `SortKey::cmp`), or by
+    /// `Ord` when there are none, like `FieldsOrd::cmp_order_by_fields`.
+    pub fn cmp<T: FieldsOrd>(&self, a: &T, b: &T) -> Ordering {
+        match self.0.len() {
+            0 => a.cmp(b),
+            _ => cmp_by_keys(&self.0, a, b),
+        }
+    }
+
+    /// Like `cmp`, failing on the first unknown field it compares, like
+    /// `FieldsOrd::try_cmp_order_by_fields`
+    pub fn try_cmp<T: FieldsOrd>(&self, a: &T, b: &T) -> Result<Ordering, UnknownField> {
+        if self.0.is_empty() {
+            return Ok(a.cmp(b))
+        }
+
+        for key in self.0.iter() {
+            match key.try_cmp(a, b)? {
+                Ordering::Equal => continue,
+                ord => return Ok(ord),
+            }
+        }
+
+        Ok(Ordering::Equal)
+    }
+
+    /// The fields as expected by `FieldsOrd::cmp_order_by_fields`
+    pub fn fields(&self) -> Vec<(&str, SortDirection)> {
+        self.0.iter().map(|key| (key.modified.as_str(), key.direction)).collect()
+    }
+}
+
+
+/// Compares `a` and `b` by each of `keys` in turn, `Equal` when there are none
+pub fn cmp_by_keys<T: FieldsOrd>(keys: &[SortKey], a: &T, b: &T) -> Ordering {
+    keys.iter()
+        .map(|key| key.cmp(a, b))
+        .find(|ord| ord.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+
+/// Compares `a` and `b` by a single `field`, putting nulls first or last when
+/// `modifiers` say so, and otherwise comparing strings by their collation.
+fn cmp_by_field<T: FieldsOrd>(a: &T, b: &T, field: &str, modifiers: Modifiers, direction: &SortDirection)
+    -> Result<Ordering, UnknownField>
+{
+    match (modifiers.nulls, a.is_null(field), b.is_null(field)) {
+        (Some(_), true, true) => Ok(Ordering::Equal),
+        (Some(Nulls::First), true, false) | (Some(Nulls::Last), false, true) => Ok(Ordering::Less),
+        (Some(Nulls::First), false, true) | (Some(Nulls::Last), true, false) => Ok(Ordering::Greater),
+        _ => a.try_cmp_field(b, field, direction, modifiers.collation),
+    }
+}
+
+
 /// Since this trait is private, the method declared here will not be available
 /// outside of this module, making it private. But in order for it to be
 /// useful within the `FieldsOrd` trait, we have to define an implementation for
 /// any type that also implements `FieldsOrd`. Then the compiler knows that any
 /// `FieldsOrd` also has the `FieldsOrdImpl` trait.
 trait FieldsOrdImpl {
-    fn _cmp_order_by_fields_impl(&self, b: &Self, fields: &Vec<(&str, SortDirection)>, prev: Ordering) -> Ordering;
+    fn _cmp_order_by_fields_impl(&self, b: &Self, fields: &[(&str, SortDirection)], strict: bool, prev: Ordering)
+        -> Result<Ordering, UnknownField>;
 }
 
 
+/// Records that can be sorted by their fields, by name. Usually derived, see
+/// `homework_derive`.
 pub trait FieldsOrd: Eq + Ord + Sized {
 
-    fn cmp_field(&self, b: &Self, field: &str, direction: &SortDirection) -> Ordering;
+    /// Compares the values of `field`, with strings compared by `collation`.
+    /// A field the record doesn't have is an `UnknownField`.
+    fn try_cmp_field(&self, b: &Self, field: &str, direction: &SortDirection, collation: Collation)
+        -> Result<Ordering, UnknownField>;
+
+    /// Like `try_cmp_field`, with strings compared byte by byte. Unknown fields are `Equal`.
+    fn cmp_field(&self, b: &Self, field: &str, direction: &SortDirection) -> Ordering {
+        self.cmp_field_collated(b, field, direction, Collation::Binary)
+    }
+
+    /// Like `try_cmp_field`. Unknown fields are `Equal`, with a warning.
+    fn cmp_field_collated(&self, b: &Self, field: &str, direction: &SortDirection, collation: Collation) -> Ordering {
+        self.try_cmp_field(b, field, direction, collation).unwrap_or_else(|e| {
+            log::warn!("{} Ignoring it.", e);
+            Ordering::Equal
+        })
+    }
+
+    /// Whether the value of `field` is null, for `Nulls` to put first or last.
+    /// Implementors without nulls can leave it to never be.
+    fn is_null(&self, _field: &str) -> bool {
+        false
+    }
 
+    /// Compares by each of `fields` in turn, any of which may have modifiers
+    /// (see `modifiers()`), or by `Ord` when there are none. Unknown fields are `Equal`,
+    /// with a warning; see `SortSpec` to reject them up front instead.
     fn cmp_order_by_fields(&self, b: &Self, fields: &Vec<(&str, SortDirection)>) -> Ordering
     {
         match fields.len() {
             0 => self.cmp(b),
-            _ => self._cmp_order_by_fields_impl(b, fields, Ordering::Equal)
+            _ => self._cmp_order_by_fields_impl(b, fields, false, Ordering::Equal).unwrap_or(Ordering::Equal)
+        }
+    }
+
+    /// Like `cmp_order_by_fields`, failing on the first unknown field it compares
+    fn try_cmp_order_by_fields(&self, b: &Self, fields: &[(&str, SortDirection)]) -> Result<Ordering, UnknownField>
+    {
+        match fields.len() {
+            0 => Ok(self.cmp(b)),
+            _ => self._cmp_order_by_fields_impl(b, fields, true, Ordering::Equal)
         }
     }
 }
 
 
 impl<T: FieldsOrd> FieldsOrdImpl for T {
-    fn _cmp_order_by_fields_impl(&self, b: &Self, fields: &Vec<(&str, SortDirection)>, prev: Ordering) -> Ordering
+    fn _cmp_order_by_fields_impl(&self, b: &Self, fields: &[(&str, SortDirection)], strict: bool, prev: Ordering)
+        -> Result<Ordering, UnknownField>
     {
-        if fields.len() == 0 {
-            return prev
+        if fields.is_empty() {
+            return Ok(prev)
         }
 
         match prev {
             Ordering::Equal => {
-                let rest = fields[1..].to_vec();
                 let (field, direction) = &fields[0];
-            
-                match self.cmp_field(b, field, &direction) {
-                    Ordering::Equal => self._cmp_order_by_fields_impl(b, &rest, prev),
-                    x => x
+                let (field, modifiers) = modifiers(field).unwrap_or((field, Modifiers::default()));
+
+                let ord = match cmp_by_field(self, b, field, modifiers, direction) {
+                    Ok(ord) => ord,
+                    Err(e) if strict => return Err(e),
+                    Err(e) => {
+                        log::warn!("{} Ignoring it.", e);
+                        Ordering::Equal
+                    },
+                };
+
+                match ord {
+                    Ordering::Equal => self._cmp_order_by_fields_impl(b, &fields[1..], strict, prev),
+                    x => Ok(x)
                 }
             },
-            _ => prev
+            _ => Ok(prev)
         }
     }
 }
 
 
 impl FromStr for SortDirection {
-    type Err = ParseError;
+    type Err = String;
 
-    fn from_str(direction: &str) -> Result<Self, Infallible> {
+    fn from_str(direction: &str) -> Result<Self, Self::Err> {
         match direction {
             "asc" => Ok(SortDirection::Asc),
             "desc" => Ok(SortDirection::Desc),
-            _ => {
-                log::warn!("Unable to parse sort direction \"{}.\" Falling back to \"asc.\"", direction);
-                Ok(SortDirection::Asc)
-            },
+            _ => Err(match did_you_mean(direction, ["asc", "desc"]) {
+                Some(suggestion) => format!("Unknown sort direction \"{}\". Did you mean \"{}\"?", direction, suggestion),
+                None => format!("Unknown sort direction \"{}\". Expected \"asc\" or \"desc\"", direction),
+            }),
         }
     }
 }
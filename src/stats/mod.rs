@@ -0,0 +1,234 @@
+use std::{collections::HashMap, fmt, io::{self, Write}, str::FromStr};
+use chrono::{Datelike, Local, NaiveDate};
+use serde::{Serialize, Serializer, ser::SerializeMap};
+
+use crate::dedupe::value;
+use crate::filtering::FieldValue;
+use crate::person::Person;
+use crate::resource::Resource;
+
+
+/// What a field's values are grouped by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bucket {
+    /// The value itself
+    Value,
+    /// Age in decades, e.g., `30-39`, of a date
+    Age,
+    /// Year of a date, e.g., `1982`
+    Year,
+    /// Month of a date, e.g., `08`
+    Month,
+    /// Domain of an email address, ignoring case
+    Domain,
+}
+
+
+/// A field to group people by, e.g., `favorite_color`, or one of its `Bucket`s,
+/// e.g., `dob:age`, `dob:month` or `email:domain`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupBy {
+    pub field: String,
+    pub bucket: Bucket,
+}
+
+
+/// Parses a `GroupBy` of a `Person`, see `GroupBy::parse`
+impl FromStr for GroupBy {
+    type Err = String;
+
+    fn from_str(group_by: &str) -> Result<Self, Self::Err> {
+        Self::parse::<Person>(group_by)
+    }
+}
+
+
+impl fmt::Display for GroupBy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.field)?;
+
+        match self.bucket {
+            Bucket::Value => Ok(()),
+            Bucket::Age => write!(f, ":age"),
+            Bucket::Year => write!(f, ":year"),
+            Bucket::Month => write!(f, ":month"),
+            Bucket::Domain => write!(f, ":domain"),
+        }
+    }
+}
+
+
+/// Parses lists of comma-separated `GroupBy`s of a `T`, e.g., `["favorite_color,dob:age"]`
+pub fn group_by<T: Resource>(group_by: &[String]) -> Result<Vec<GroupBy>, String> {
+    group_by.iter()
+        .flat_map(|group_by| group_by.split(','))
+        .filter(|group_by| !group_by.trim().is_empty())
+        .map(GroupBy::parse::<T>)
+        .collect()
+}
+
+
+/// Whole years from `dob` to `today`
+fn age(dob: NaiveDate, today: NaiveDate) -> i32 {
+    let age = today.year() - dob.year();
+
+    match (today.month(), today.day()) < (dob.month(), dob.day()) {
+        true => age - 1,
+        false => age,
+    }
+}
+
+
+impl GroupBy {
+
+    /// Parses a `GroupBy` of any kind of record, e.g., `dob:age`. Whether a
+    /// field is a date is told by its value in `T::default()`.
+    pub fn parse<T: Resource>(group_by: &str) -> Result<Self, String> {
+        let mut parts = group_by.splitn(2, ':');
+        let field = parts.next().unwrap_or_default().trim().to_string();
+
+        let is_date = match T::default().field_value(&field) {
+            Some(FieldValue::Date(_)) => true,
+            Some(FieldValue::Str(_)) => false,
+            // An extra column, which is a string
+            None if T::keeps_extra() => false,
+            None => return Err(format!("Field \"{}\" not found. Available fields: {}", field, T::struct_fields().join(", "))),
+        };
+
+        let bucket = match (parts.next().map(str::trim), is_date) {
+            (None, _) => Bucket::Value,
+            (Some("age"), true) => Bucket::Age,
+            (Some("year"), true) => Bucket::Year,
+            (Some("month"), true) => Bucket::Month,
+            (Some("domain"), false) => Bucket::Domain,
+            (Some(x), true) => return Err(format!("Unable to group \"{}\" by \"{}\". Expected \"age\", \"year\" or \"month\"", field, x)),
+            (Some(x), false) => return Err(format!("Unable to group \"{}\" by \"{}\". Expected \"domain\"", field, x)),
+        };
+
+        Ok(Self { field, bucket })
+    }
+
+    /// The group of `person`. Empty when the field is.
+    fn group<T: Resource>(&self, person: &T, today: NaiveDate) -> String {
+        let date = match person.field_value(&self.field) {
+            Some(FieldValue::Date(date)) => date,
+            _ => None,
+        };
+
+        match (self.bucket, date) {
+            (Bucket::Value, _) => value(person, &self.field),
+            (Bucket::Age, Some(dob)) => {
+                let decade = age(dob, today).max(0) / 10 * 10;
+                format!("{}-{}", decade, decade + 9)
+            },
+            (Bucket::Year, Some(date)) => date.year().to_string(),
+            (Bucket::Month, Some(date)) => format!("{:02}", date.month()),
+            (Bucket::Domain, _) => match value(person, &self.field).rsplit_once('@') {
+                Some((_, domain)) => domain.trim().to_lowercase(),
+                None => String::new(),
+            },
+            (_, None) => String::new(),
+        }
+    }
+}
+
+
+/// How many people share the same groups, one for each `GroupBy`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Group {
+    /// Each `GroupBy` (e.g., `dob:age`), and the group, e.g., `30-39`
+    pub key: Vec<(String, String)>,
+    pub count: usize,
+}
+
+
+/// An object of the groups, in order, and the count
+impl Serialize for Group {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.key.len() + 1))?;
+
+        for (group_by, group) in self.key.iter() {
+            map.serialize_entry(group_by, group)?;
+        }
+
+        map.serialize_entry("count", &self.count)?;
+        map.end()
+    }
+}
+
+
+/// Counts people by any number of `GroupBy`s
+#[derive(Debug)]
+pub struct Stats {
+    group_by: Vec<GroupBy>,
+    today: NaiveDate,
+    counts: HashMap<Vec<String>, usize>,
+}
+
+
+impl Stats {
+
+    pub fn new(group_by: Vec<GroupBy>) -> Self {
+        Self::on(group_by, Local::now().naive_local().date())
+    }
+
+    /// Like `new`, but with ages as of `today`
+    pub fn on(group_by: Vec<GroupBy>, today: NaiveDate) -> Self {
+        Self { group_by, today, counts: HashMap::new() }
+    }
+
+    /// Counts `person`, or any other kind of record the `GroupBy`s were parsed for
+    pub fn push<T: Resource>(&mut self, person: &T) {
+        let key = self.group_by.iter().map(|group_by| group_by.group(person, self.today)).collect();
+
+        *self.counts.entry(key).or_default() += 1;
+    }
+
+    /// Every group, the most common first, then by the groups
+    pub fn finish(self) -> Vec<Group> {
+        let mut counts: Vec<(Vec<String>, usize)> = self.counts.into_iter().collect();
+
+        counts.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+
+        let names: Vec<String> = self.group_by.iter().map(GroupBy::to_string).collect();
+
+        counts.into_iter()
+            .map(|(key, count)| Group { key: names.iter().cloned().zip(key).collect(), count })
+            .collect()
+    }
+}
+
+
+/// Writes `groups` as delimited rows: a column for each of `group_by`, then
+/// the count. Returns how many were written.
+pub fn write_stats<T: Write>(
+    writer: T,
+    output_field_separator: char,
+    output_has_header: bool,
+    group_by: &[GroupBy],
+    groups: impl IntoIterator<Item = Group>,
+) -> io::Result<usize>
+{
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(output_field_separator as u8)
+        .terminator(csv::Terminator::CRLF)
+        .from_writer(writer);
+
+    if output_has_header {
+        writer.write_record(group_by.iter().map(GroupBy::to_string).chain(Some("count".to_string())))?;
+    }
+
+    let mut written = 0;
+
+    for group in groups.into_iter() {
+        writer.write_record(group.key.into_iter().map(|(_, group)| group).chain(Some(group.count.to_string())))?;
+        written += 1;
+    }
+
+    writer.flush()?;
+
+    Ok(written)
+}
+
+
+mod tests;
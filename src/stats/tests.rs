@@ -0,0 +1,78 @@
+#![allow(dead_code)]
+
+use super::*;
+
+
+fn people() -> Vec<Person> {
+    vec![
+        Person::new("Brennan", "Tom", "tjb1982@gmail.com", "red", "8/19/1982"),
+        Person::new("Fuller", "Rachel", "rf@Example.com", "green", "8/10/1970"),
+        Person::new("Smith", "Jan", "jan@example.com", "red", "1/1/1990"),
+        Person::new("Doe", "Jane", "", "red", ""),
+    ]
+}
+
+
+fn stats(group_by: &[&str]) -> Vec<Group> {
+    let group_by = group_by.iter().map(|group_by| group_by.parse().unwrap()).collect();
+    let mut stats = Stats::on(group_by, NaiveDate::from_ymd(2020, 8, 15));
+
+    for person in people().iter() {
+        stats.push(person);
+    }
+
+    stats.finish()
+}
+
+
+fn group(key: &[(&str, &str)], count: usize) -> Group {
+    Group { key: key.iter().map(|(a, b)| (a.to_string(), b.to_string())).collect(), count }
+}
+
+
+#[cfg(test)]
+mod grouping {
+    use super::*;
+
+    #[test]
+    fn by_value() {
+        assert_eq!(stats(&["favorite_color"]), vec![
+            group(&[("favorite_color", "red")], 3),
+            group(&[("favorite_color", "green")], 1),
+        ]);
+    }
+
+    #[test]
+    fn by_buckets() {
+        assert_eq!(stats(&["dob:age"]), vec![
+            group(&[("dob:age", "30-39")], 2),
+            group(&[("dob:age", "")], 1),
+            group(&[("dob:age", "50-59")], 1),
+        ]);
+        assert_eq!(stats(&["dob:month"])[0], group(&[("dob:month", "08")], 2));
+        assert_eq!(stats(&["email:domain"])[0], group(&[("email:domain", "example.com")], 2));
+        assert_eq!(stats(&["favorite_color", "dob:year"])[0], group(&[("favorite_color", "green"), ("dob:year", "1970")], 1));
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(group_by::<Person>(&["favorite_color, dob:age".into()]), Ok(vec![
+            GroupBy { field: "favorite_color".into(), bucket: Bucket::Value },
+            GroupBy { field: "dob".into(), bucket: Bucket::Age },
+        ]));
+        assert!("color".parse::<GroupBy>().is_err());
+        assert!("email:age".parse::<GroupBy>().is_err());
+        assert!("dob:domain".parse::<GroupBy>().is_err());
+        assert_eq!("email:domain".parse::<GroupBy>().unwrap().to_string(), "email:domain");
+    }
+
+    #[test]
+    fn output() {
+        let group_by = group_by::<Person>(&["favorite_color".into()]).unwrap();
+        let mut out = vec![];
+
+        assert_eq!(write_stats(&mut out, ',', true, &group_by, stats(&["favorite_color"])).unwrap(), 2);
+        assert_eq!(String::from_utf8(out).unwrap(), "favorite_color,count\r\nred,3\r\ngreen,1\r\n");
+        assert_eq!(serde_json::to_string(&stats(&["favorite_color"])[0]).unwrap(), "{\"favorite_color\":\"red\",\"count\":3}");
+    }
+}
@@ -0,0 +1,176 @@
+use std::{fmt, fs::File, io::{self, BufReader}, path::Path};
+use chrono::{Local, NaiveDate};
+use serde::{Serialize, Deserialize};
+
+use crate::person::Person;
+use crate::resource::Resource;
+use crate::filtering::{FieldValue, FieldsFilter};
+
+
+/// A single rule that a field of a `Person` failed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldError {
+    pub field: String,
+    pub reason: String,
+}
+
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.reason)
+    }
+}
+
+
+/// The rules a `Person` must satisfy to be stored or output.
+///
+/// The defaults require a `last_name` and `first_name`, a well-formed `email`
+/// (when one is given) and a `dob` that isn't in the future. They're the built-in
+/// rules, which aren't applied unless asked for (see `none`). Any of them can be
+/// overridden from a JSON file (see `from_file`), e.g.:
+///
+/// ```json
+/// {
+///     "required": ["last_name", "first_name", "email"],
+///     "dob_min": "1900-01-01",
+///     "favorite_colors": ["red", "green", "blue"]
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Rules {
+    /// Fields that can't be empty
+    pub required: Vec<String>,
+    /// Whether a non-empty `email` must look like an email address
+    pub email: bool,
+    /// Earliest allowed `dob` (ISO-8601 in the file)
+    pub dob_min: Option<NaiveDate>,
+    /// Latest allowed `dob` (ISO-8601 in the file)
+    pub dob_max: Option<NaiveDate>,
+    /// Whether a `dob` after today is allowed
+    pub future_dob: bool,
+    /// If set, the only allowed values of a non-empty `favorite_color` (ignoring case)
+    pub favorite_colors: Option<Vec<String>>,
+}
+
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            required: vec!["last_name".into(), "first_name".into()],
+            email: true,
+            dob_min: None,
+            dob_max: None,
+            future_dob: false,
+            favorite_colors: None,
+        }
+    }
+}
+
+
+/// Whether `email` looks like `local@domain.tld`. This is intentionally loose:
+/// it only catches values that are clearly not an address.
+fn valid_email(email: &str) -> bool {
+    let mut parts = email.splitn(2, '@');
+
+    match (parts.next(), parts.next()) {
+        (Some(local), Some(domain)) => !local.is_empty()
+            && !domain.contains('@')
+            && !email.chars().any(char::is_whitespace)
+            && domain.split('.').count() > 1
+            && domain.split('.').all(|label| !label.is_empty()),
+        _ => false
+    }
+}
+
+
+impl Rules {
+
+    /// No rules at all: every `Person` is valid. What records are read and
+    /// stored with, e.g., by `io::InputOpts::default()`, unless asked otherwise.
+    pub fn none() -> Self {
+        Self {
+            required: vec![],
+            email: false,
+            future_dob: true,
+            ..Self::default()
+        }
+    }
+
+    /// Reads rules for a `T`, e.g., `Person`, from a JSON object. Rules missing
+    /// from the object keep their defaults, and `required` must be fields of a `T`.
+    pub fn from_file<T: Resource>(path: &Path) -> io::Result<Self> {
+        let rules: Self = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+
+        if let Some(field) = rules.required.iter().find(|f| !T::struct_fields().contains(&f.as_str())) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unknown required field \"{}\". Available fields: {}",
+                    field, T::struct_fields().join(", "))
+            ))
+        }
+
+        Ok(rules)
+    }
+
+    /// The `required` fields that are empty in `record`, or that it doesn't have
+    fn missing<T: FieldsFilter + ?Sized>(&self, record: &T) -> Vec<FieldError> {
+        self.required.iter()
+            .filter(|field| match record.field_value(field) {
+                Some(FieldValue::Str(value)) => value.is_empty(),
+                Some(FieldValue::Date(date)) => date.is_none(),
+                None => true,
+            })
+            .map(|field| FieldError { field: field.clone(), reason: "is required".into() })
+            .collect()
+    }
+
+    /// Checks that none of the `required` fields of any kind of record are empty.
+    /// The other rules are about the fields of a `Person` (see `validate`).
+    pub fn validate_required<T: FieldsFilter + ?Sized>(&self, record: &T) -> Result<(), Vec<FieldError>> {
+        let errors = self.missing(record);
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors)
+        }
+    }
+
+    /// Checks `person` against every rule, returning all of the fields that fail.
+    pub fn validate(&self, person: &Person) -> Result<(), Vec<FieldError>> {
+        let mut errors = self.missing(person);
+        let mut fail = |field: &str, reason: String| errors.push(FieldError {
+            field: field.to_string(),
+            reason,
+        });
+
+        if self.email && !person.email.is_empty() && !valid_email(&person.email) {
+            fail("email", format!("\"{}\" is not a valid email address", person.email));
+        }
+
+        if let Some(dob) = person.dob {
+            let today = Local::now().naive_local().date();
+
+            match (self.dob_min, self.dob_max) {
+                (Some(min), _) if dob < min => fail("dob", format!("must be on or after {}", min)),
+                (_, Some(max)) if dob > max => fail("dob", format!("must be on or before {}", max)),
+                _ if !self.future_dob && dob > today => fail("dob", "can't be in the future".into()),
+                _ => ()
+            }
+        }
+
+        if let Some(colors) = &self.favorite_colors {
+            let color = &person.favorite_color;
+
+            if !color.is_empty() && !colors.iter().any(|c| c.eq_ignore_ascii_case(color)) {
+                fail("favorite_color", format!("\"{}\" is not one of {}", color, colors.join(", ")));
+            }
+        }
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors)
+        }
+    }
+}
+